@@ -0,0 +1,31 @@
+-- Бронирования мест и удобства у мест.
+-- Время хранится в UTC timestamp (секунды), как и sessions.expires.
+
+CREATE TABLE IF NOT EXISTS public.bookings (
+    id      SERIAL PRIMARY KEY,
+    seat_id INTEGER NOT NULL REFERENCES public.seats (id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES public.users (id) ON DELETE CASCADE,
+    starts  BIGINT  NOT NULL,
+    ends    BIGINT  NOT NULL,
+    CHECK (ends > starts)
+);
+
+CREATE INDEX IF NOT EXISTS bookings_seat_time_idx
+    ON public.bookings (seat_id, starts, ends);
+
+-- Защита от гонки двух одновременных броней одного места
+CREATE EXTENSION IF NOT EXISTS btree_gist;
+
+ALTER TABLE public.bookings
+    DROP CONSTRAINT IF EXISTS bookings_no_overlap,
+    ADD CONSTRAINT bookings_no_overlap
+    EXCLUDE USING gist (seat_id WITH =, int8range(starts, ends) WITH &&);
+
+ALTER TABLE public.seats
+    ADD COLUMN IF NOT EXISTS amenities TEXT[] NOT NULL DEFAULT '{}';
+
+-- Отметка «я на месте» по текущей брони
+CREATE TABLE IF NOT EXISTS public.booking_check_ins (
+    booking_id INTEGER PRIMARY KEY REFERENCES public.bookings (id) ON DELETE CASCADE,
+    checked_in BIGINT  NOT NULL
+);
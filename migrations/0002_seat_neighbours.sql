@@ -0,0 +1,10 @@
+-- Соседство мест, заданное вручную.
+-- Если у места есть хотя бы одна запись, вычисленное по координатам
+-- соседство для него не используется.
+
+CREATE TABLE IF NOT EXISTS public.seat_neighbours (
+    seat_id      INTEGER NOT NULL REFERENCES public.seats (id) ON DELETE CASCADE,
+    neighbour_id INTEGER NOT NULL REFERENCES public.seats (id) ON DELETE CASCADE,
+    PRIMARY KEY (seat_id, neighbour_id),
+    CHECK (seat_id <> neighbour_id)
+);
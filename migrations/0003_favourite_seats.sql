@@ -0,0 +1,7 @@
+-- Избранные места пользователей
+
+CREATE TABLE IF NOT EXISTS public.favourite_seats (
+    user_id INTEGER NOT NULL REFERENCES public.users (id) ON DELETE CASCADE,
+    seat_id INTEGER NOT NULL REFERENCES public.seats (id) ON DELETE CASCADE,
+    PRIMARY KEY (user_id, seat_id)
+);
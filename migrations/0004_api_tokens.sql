@@ -0,0 +1,14 @@
+-- Именные токены для скриптов и ботов. Сам токен не хранится, только его хеш.
+
+CREATE TABLE IF NOT EXISTS public.api_tokens (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES public.users (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL DEFAULT '{}',
+    created BIGINT NOT NULL,  -- utc timestamp
+    expires BIGINT,           -- utc timestamp, NULL -- бессрочный
+    last_used BIGINT          -- utc timestamp
+);
+
+CREATE INDEX IF NOT EXISTS api_tokens_user_id ON public.api_tokens (user_id);
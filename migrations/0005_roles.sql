@@ -0,0 +1,24 @@
+-- Роли пользователей. Права ролей описаны в коде (models::roles),
+-- таблица нужна для целостности users.role и читаемых выборок.
+
+CREATE TABLE IF NOT EXISTS public.roles (
+    id    INTEGER PRIMARY KEY,
+    name  TEXT NOT NULL UNIQUE,
+    title TEXT NOT NULL
+);
+
+INSERT INTO public.roles (id, name, title) VALUES
+    (1, 'guest', 'Гость'),
+    (2, 'member', 'Участник клуба'),
+    (3, 'club_lead', 'Руководитель клуба'),
+    (4, 'admin', 'Администратор')
+ON CONFLICT (id) DO UPDATE SET name = EXCLUDED.name, title = EXCLUDED.title;
+
+-- до этой миграции роль проставлялась как попало
+UPDATE public.users SET role = 2 WHERE role IS NULL OR role NOT IN (SELECT id FROM public.roles);
+
+ALTER TABLE public.users
+    ALTER COLUMN role SET DEFAULT 2,
+    ALTER COLUMN role SET NOT NULL,
+    DROP CONSTRAINT IF EXISTS users_role_fkey,
+    ADD CONSTRAINT users_role_fkey FOREIGN KEY (role) REFERENCES public.roles (id);
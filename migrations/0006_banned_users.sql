@@ -0,0 +1,4 @@
+-- Блокировка пользователей из админки
+
+ALTER TABLE public.users
+    ADD COLUMN IF NOT EXISTS banned BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,53 @@
+-- Журнал действий: кто, что, над чем, что было и что стало.
+-- Только дописывается: изменить запись не даст триггер, а удалить старые
+-- записи можно только через audit_log_purge.
+
+CREATE TABLE IF NOT EXISTS public.audit_log (
+    id          BIGSERIAL PRIMARY KEY,
+    actor_id    INTEGER,  -- без внешнего ключа: SET NULL упёрся бы в триггер
+    action      TEXT NOT NULL,
+    target_type TEXT,
+    target_id   INTEGER,
+    before      JSONB,
+    after       JSONB,
+    ip          TEXT,
+    created     BIGINT NOT NULL  -- utc timestamp
+);
+
+ALTER TABLE public.audit_log DROP CONSTRAINT IF EXISTS audit_log_actor_id_fkey;
+
+CREATE INDEX IF NOT EXISTS audit_log_actor_idx ON public.audit_log (actor_id, id);
+CREATE INDEX IF NOT EXISTS audit_log_target_idx ON public.audit_log (target_type, target_id, id);
+CREATE INDEX IF NOT EXISTS audit_log_created_idx ON public.audit_log (created);
+
+CREATE OR REPLACE FUNCTION public.audit_log_append_only() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'DELETE' AND current_setting('audit_log.purging', true) = 'on' THEN
+        RETURN OLD;
+    END IF;
+    RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS audit_log_append_only ON public.audit_log;
+CREATE TRIGGER audit_log_append_only
+    BEFORE UPDATE OR DELETE ON public.audit_log
+    FOR EACH ROW EXECUTE FUNCTION public.audit_log_append_only();
+
+-- Удаляет записи старше keep_days дней и возвращает, сколько удалено.
+-- Меньше 30 дней хранить нельзя, чтобы ошибкой не стереть свежий журнал.
+CREATE OR REPLACE FUNCTION public.audit_log_purge(keep_days INTEGER) RETURNS BIGINT AS $$
+DECLARE
+    removed BIGINT;
+BEGIN
+    IF keep_days < 30 THEN
+        RAISE EXCEPTION 'audit_log must be kept for at least 30 days';
+    END IF;
+    PERFORM set_config('audit_log.purging', 'on', true);
+    DELETE FROM public.audit_log
+        WHERE created < extract(epoch FROM now())::BIGINT - keep_days::BIGINT * 86400;
+    GET DIAGNOSTICS removed = ROW_COUNT;
+    PERFORM set_config('audit_log.purging', 'off', true);
+    RETURN removed;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,21 @@
+-- Сроки жизни сессий: абсолютный (expires) и по бездействию
+-- (last_seen + idle_timeout, NULL -- без ограничения по бездействию).
+
+ALTER TABLE public.sessions
+    ALTER COLUMN expires TYPE BIGINT,
+    ADD COLUMN IF NOT EXISTS created BIGINT,
+    ADD COLUMN IF NOT EXISTS last_seen BIGINT,
+    ADD COLUMN IF NOT EXISTS idle_timeout BIGINT;
+
+-- старые сессии были вечными, дадим им неделю
+UPDATE public.sessions SET
+    created = COALESCE(created, EXTRACT(EPOCH FROM now())::BIGINT),
+    last_seen = COALESCE(last_seen, EXTRACT(EPOCH FROM now())::BIGINT),
+    expires = COALESCE(expires, EXTRACT(EPOCH FROM now())::BIGINT + 7 * 24 * 60 * 60);
+
+ALTER TABLE public.sessions
+    ALTER COLUMN created SET NOT NULL,
+    ALTER COLUMN last_seen SET NOT NULL,
+    ALTER COLUMN expires SET NOT NULL;
+
+CREATE INDEX IF NOT EXISTS sessions_expires_idx ON public.sessions (expires);
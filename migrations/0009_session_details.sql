@@ -0,0 +1,8 @@
+-- Подробности сессий для страницы «мои сессии»
+
+ALTER TABLE public.sessions
+    ADD COLUMN IF NOT EXISTS id SERIAL UNIQUE,
+    ADD COLUMN IF NOT EXISTS user_agent TEXT,
+    ADD COLUMN IF NOT EXISTS ip TEXT;
+
+CREATE INDEX IF NOT EXISTS sessions_user_id_idx ON public.sessions (user_id);
@@ -0,0 +1,10 @@
+-- Данные сессий actix-session на сервере (SESSIONS.STORE=postgres):
+-- в печеньке остаётся только ключ, а состояние лежит здесь.
+
+CREATE TABLE IF NOT EXISTS public.session_store (
+    key TEXT PRIMARY KEY,
+    state JSONB NOT NULL,
+    expires BIGINT NOT NULL -- utc timestamp
+);
+
+CREATE INDEX IF NOT EXISTS session_store_expires_idx ON public.session_store (expires);
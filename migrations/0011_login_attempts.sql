@@ -0,0 +1,12 @@
+-- Неудачные попытки входа: отдельно по адресу и по логину.
+-- Логин учитывается, даже если такого пользователя нет, чтобы по
+-- блокировке нельзя было узнать, кто зарегистрирован.
+
+CREATE TABLE IF NOT EXISTS public.login_attempts (
+    kind TEXT NOT NULL,                  -- 'ip' или 'login'
+    subject TEXT NOT NULL,               -- адрес или логин в нижнем регистре
+    failures INTEGER NOT NULL DEFAULT 0, -- неудач подряд
+    last_failure BIGINT NOT NULL,        -- utc timestamp
+    locked_until BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (kind, subject)
+);
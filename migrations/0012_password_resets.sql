@@ -0,0 +1,16 @@
+-- Почта для восстановления пароля и одноразовые токены сброса.
+-- Хранится только хеш токена, как и у токенов API.
+
+ALTER TABLE public.users
+    ADD COLUMN IF NOT EXISTS email TEXT;
+
+CREATE TABLE IF NOT EXISTS public.password_resets (
+    id SERIAL PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES public.users (id) ON DELETE CASCADE,
+    token_hash TEXT NOT NULL UNIQUE,
+    created BIGINT NOT NULL, -- utc timestamp
+    expires BIGINT NOT NULL, -- utc timestamp
+    used BIGINT              -- utc timestamp, NULL -- ещё не использован
+);
+
+CREATE INDEX IF NOT EXISTS password_resets_user_id_idx ON public.password_resets (user_id);
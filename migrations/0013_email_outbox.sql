@@ -0,0 +1,18 @@
+-- Исходящие письма. Письмо сначала ложится сюда, а фоновая задача
+-- отправляет его и при неудаче пробует ещё, каждый раз выжидая дольше.
+
+CREATE TABLE IF NOT EXISTS public.email_outbox (
+    id BIGSERIAL PRIMARY KEY,
+    recipient TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    text_body TEXT NOT NULL,
+    html_body TEXT,
+    created BIGINT NOT NULL,              -- utc timestamp
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt BIGINT NOT NULL,         -- utc timestamp
+    sent BIGINT,                          -- utc timestamp, NULL -- ещё не отправлено
+    last_error TEXT
+);
+
+CREATE INDEX IF NOT EXISTS email_outbox_due_idx
+    ON public.email_outbox (next_attempt) WHERE sent IS NULL;
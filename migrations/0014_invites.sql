@@ -0,0 +1,29 @@
+-- Коды приглашений: без кода зарегистрироваться нельзя.
+-- Код может быть одноразовым или многоразовым, со сроком и заранее
+-- заданными ролью и клубом нового пользователя.
+
+ALTER TABLE public.users
+    ADD COLUMN IF NOT EXISTS club TEXT;
+
+CREATE TABLE IF NOT EXISTS public.invites (
+    id SERIAL PRIMARY KEY,
+    code TEXT NOT NULL UNIQUE,
+    created_by INTEGER REFERENCES public.users (id) ON DELETE SET NULL,
+    role INTEGER REFERENCES public.roles (id), -- NULL -- роль по умолчанию
+    club TEXT,
+    max_uses INTEGER,                          -- NULL -- без ограничения
+    uses INTEGER NOT NULL DEFAULT 0,
+    created BIGINT NOT NULL,                   -- utc timestamp
+    expires BIGINT,                            -- utc timestamp, NULL -- бессрочный
+    revoked BIGINT                             -- utc timestamp
+);
+
+CREATE INDEX IF NOT EXISTS invites_created_by_idx ON public.invites (created_by);
+
+-- кто по какому коду зарегистрировался
+CREATE TABLE IF NOT EXISTS public.invite_uses (
+    invite_id INTEGER NOT NULL REFERENCES public.invites (id) ON DELETE CASCADE,
+    user_id INTEGER NOT NULL REFERENCES public.users (id) ON DELETE CASCADE,
+    used BIGINT NOT NULL, -- utc timestamp
+    PRIMARY KEY (invite_id, user_id)
+);
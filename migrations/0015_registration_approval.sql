@@ -0,0 +1,11 @@
+-- Модерация регистраций: новая учётная запись ждёт решения администратора.
+-- Пока заявка не одобрена, войти можно, а бронировать нельзя;
+-- с отклонённой заявкой не войти.
+
+ALTER TABLE public.users
+    ADD COLUMN IF NOT EXISTS approval TEXT NOT NULL DEFAULT 'approved'
+        CHECK (approval IN ('pending', 'approved', 'rejected')),
+    ADD COLUMN IF NOT EXISTS approval_reason TEXT, -- почему отклонена
+    ADD COLUMN IF NOT EXISTS registered BIGINT;    -- utc timestamp, у старых пользователей NULL
+
+CREATE INDEX IF NOT EXISTS users_pending_idx ON public.users (registered) WHERE approval = 'pending';
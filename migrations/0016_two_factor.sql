@@ -0,0 +1,30 @@
+-- Двухфакторный вход по TOTP (RFC 6238) и одноразовые коды восстановления.
+-- Секрет хранится в base32, как его показывают приложению-аутентификатору;
+-- последний принятый шаг не даёт войти второй раз тем же кодом.
+
+ALTER TABLE public.users
+    ADD COLUMN IF NOT EXISTS totp_secret TEXT,       -- NULL -- второй фактор не включён
+    ADD COLUMN IF NOT EXISTS totp_last_step BIGINT;  -- номер 30-секундного шага
+
+-- сами коды знает только пользователь, храним хеши argon2, как у паролей
+CREATE TABLE IF NOT EXISTS public.recovery_codes (
+    id        SERIAL PRIMARY KEY,
+    user_id   INTEGER NOT NULL REFERENCES public.users (id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    used      BIGINT, -- utc timestamp, NULL -- не использован
+    UNIQUE (user_id, code_hash)
+);
+
+-- первые коды хешировались sha512 без соли и уникальными были по всей таблице;
+-- такие коды проверить больше нечем, их придётся выпустить заново
+ALTER TABLE public.recovery_codes DROP CONSTRAINT IF EXISTS recovery_codes_code_hash_key;
+DELETE FROM public.recovery_codes WHERE code_hash NOT LIKE '$argon2%';
+CREATE UNIQUE INDEX IF NOT EXISTS recovery_codes_user_id_code_hash_key
+    ON public.recovery_codes (user_id, code_hash);
+
+-- коды пользователя находит уникальный индекс, отдельный больше не нужен
+DROP INDEX IF EXISTS public.recovery_codes_user_idx;
+
+-- роли, которым без второго фактора нельзя ничего, кроме его включения
+ALTER TABLE public.roles
+    ADD COLUMN IF NOT EXISTS require_totp BOOLEAN NOT NULL DEFAULT false;
@@ -5,4 +5,200 @@ use serde::Deserialize;
 pub struct ServerConfig {
     pub server_addr: String,
     pub pg: deadpool_postgres::Config,
+    /// Режим разработки (`DEV=true`): разрешает нулевой ключ сессий
+    #[serde(default)]
+    pub dev: bool,
+    #[serde(default)]
+    pub sessions: SessionConfig,
+    #[serde(default)]
+    pub login: LoginConfig,
+    #[serde(default)]
+    pub notify: NotifyConfig,
+    #[serde(default)]
+    pub registration: RegistrationConfig,
+}
+
+/// Где actix-session держит данные сессии (`SESSIONS.STORE`)
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreKind {
+    /// В зашифрованной печеньке
+    #[default]
+    Cookie,
+    /// В таблице `session_store`, в печеньке только ключ
+    Postgres,
+}
+
+/// Сроки жизни сессий, в секундах (`SESSIONS.IDLE_TIMEOUT` и т.п.)
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// Сколько можно бездействовать, прежде чем сессия закончится
+    pub idle_timeout: i64,
+    /// Сколько сессия живёт в любом случае
+    pub absolute_timeout: i64,
+    /// Сколько живёт сессия с галочкой «запомнить меня»
+    pub remember_timeout: i64,
+    /// Как часто вычищать закончившиеся сессии из базы
+    pub sweep_interval: u64,
+    /// Ключ печенек в hex, не меньше 64 байт. Если не задан, читается из `key_file`
+    pub key: Option<String>,
+    /// Файл с ключом; создаётся при первом запуске
+    pub key_file: String,
+    /// Прежний ключ после смены: печеньки с ним ещё принимаются
+    pub previous_key: Option<String>,
+    /// Хранилище данных сессии
+    pub store: SessionStoreKind,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig {
+            idle_timeout: 2 * 60 * 60,
+            absolute_timeout: 7 * 24 * 60 * 60,
+            remember_timeout: 30 * 24 * 60 * 60,
+            sweep_interval: 60 * 60,
+            key: None,
+            key_file: "session.key".into(),
+            previous_key: None,
+            store: SessionStoreKind::Cookie,
+        }
+    }
+}
+
+/// Ограничения на попытки входа (`LOGIN.FREE_ATTEMPTS` и т.п.), сроки в секундах
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct LoginConfig {
+    /// Сколько неудач под одним логином прощается без задержки
+    pub free_attempts: i32,
+    /// После скольких неудач под одним логином вход закрывается на `lockout_duration`
+    pub lockout_after: i32,
+    /// То же для одного адреса: за ним может сидеть целый клуб
+    pub ip_free_attempts: i32,
+    pub ip_lockout_after: i32,
+    /// Задержка после первой лишней неудачи; дальше она удваивается
+    pub base_delay: i64,
+    /// Потолок задержки
+    pub max_delay: i64,
+    /// На сколько закрывается вход
+    pub lockout_duration: i64,
+    /// Через сколько без неудач счётчик начинается заново
+    pub window: i64,
+    /// Сколько действует ссылка для сброса пароля
+    pub reset_token_ttl: i64,
+    /// Сколько ссылок для сброса можно запросить с одного адреса за `window`
+    pub reset_ip_limit: i32,
+}
+
+impl Default for LoginConfig {
+    fn default() -> Self {
+        LoginConfig {
+            free_attempts: 3,
+            lockout_after: 10,
+            ip_free_attempts: 10,
+            ip_lockout_after: 50,
+            base_delay: 2,
+            max_delay: 5 * 60,
+            lockout_duration: 15 * 60,
+            window: 60 * 60,
+            reset_token_ttl: 60 * 60,
+            reset_ip_limit: 5,
+        }
+    }
+}
+
+/// Как доставлять сообщения пользователям (`NOTIFY.KIND`)
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifierKind {
+    /// Писать в лог, для разработки
+    #[default]
+    Console,
+    /// Письмом через SMTP-релей
+    Smtp,
+}
+
+/// Шифрование соединения с релеем (`NOTIFY.SMTP_TLS`)
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Без шифрования, только для локальной ловушки писем
+    #[default]
+    None,
+    /// Обычное соединение, затем обязательный STARTTLS (порт 587)
+    Starttls,
+    /// TLS с самого начала (порт 465)
+    Tls,
+}
+
+/// Настройки уведомлений и очереди писем (`NOTIFY.SMTP_HOST` и т.п.)
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct NotifyConfig {
+    pub kind: NotifierKind,
+    /// Адрес сайта для ссылок в письмах. Брать его из `Host` запроса нельзя:
+    /// подменив заголовок, можно увести ссылку сброса пароля на свой сайт
+    pub public_url: String,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_tls: SmtpTls,
+    /// Логин и пароль для AUTH; без логина входим анонимно
+    pub smtp_user: Option<String>,
+    pub smtp_password: Option<String>,
+    /// Имя, которым представляемся релею
+    pub smtp_helo: String,
+    pub smtp_from: String,
+    /// Сколько ждать релей, в секундах
+    pub smtp_timeout: u64,
+    /// Как часто заглядывать в очередь писем, в секундах
+    pub poll_interval: u64,
+    /// Сколько писем отправлять за раз
+    pub batch_size: i64,
+    /// Сколько раз пробовать отправить письмо, прежде чем сдаться
+    pub max_attempts: i32,
+    /// Пауза после первой неудачи, в секундах; дальше она удваивается
+    pub retry_base: i64,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        NotifyConfig {
+            kind: NotifierKind::Console,
+            public_url: "http://localhost:8080".into(),
+            smtp_host: "127.0.0.1".into(),
+            smtp_port: 25,
+            smtp_tls: SmtpTls::None,
+            smtp_user: None,
+            smtp_password: None,
+            smtp_helo: "localhost".into(),
+            smtp_from: "noreply@localhost".into(),
+            smtp_timeout: 30,
+            poll_interval: 10,
+            batch_size: 20,
+            max_attempts: 8,
+            retry_base: 60,
+        }
+    }
+}
+
+/// Кто может зарегистрироваться (`REGISTRATION.MODE`)
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistrationMode {
+    /// Кто угодно из сети
+    Open,
+    /// Только с кодом приглашения
+    #[default]
+    Invite,
+    /// Кто угодно, но бронировать можно только после одобрения администратором.
+    /// С кодом приглашения одобрение не нужно.
+    Moderated,
+}
+
+/// Настройки регистрации (`REGISTRATION.MODE` и т.п.)
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RegistrationConfig {
+    pub mode: RegistrationMode,
 }
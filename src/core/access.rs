@@ -0,0 +1,159 @@
+use std::marker::PhantomData;
+use actix_session::Session;
+use actix_web::{dev::Payload, FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError, web};
+use actix_web::error::InternalError;
+use actix_web::http::header::{self, ContentType};
+use actix_web::http::StatusCode;
+use derive_more::Display;
+use chrono::Utc;
+use deadpool_postgres::Client;
+use futures_util::future::LocalBoxFuture;
+use crate::AppState;
+use crate::core::{templator, RequestClient};
+use crate::core::errors::{ApiError, AuthError, DbError};
+use crate::core::users::{get_current_user, needs_totp};
+use crate::models::api_token::{use_api_token, TokenScope};
+use crate::models::roles::Permission;
+use crate::models::user::{get_user_by_id, Approval, User};
+
+/// Право, которое проверяет `Authorized`
+pub(crate) trait Requirement {
+    const PERMISSION: Permission;
+}
+
+/// Заводит тип-маркер для `Authorized<...>`
+macro_rules! requirement {
+    ($name:ident => $permission:expr) => {
+        pub(crate) struct $name;
+
+        impl Requirement for $name {
+            const PERMISSION: Permission = $permission;
+        }
+    };
+}
+
+requirement!(CanBook => Permission::Book);
+requirement!(CanManageBookings => Permission::ManageBookings);
+requirement!(CanManageSeats => Permission::ManageSeats);
+requirement!(CanManageUsers => Permission::ManageUsers);
+requirement!(CanViewAudit => Permission::ViewAudit);
+requirement!(CanPurgeAudit => Permission::PurgeAudit);
+requirement!(CanManageInvites => Permission::ManageInvites);
+
+/// Почему запрос не пустили
+#[derive(Debug, Display)]
+pub(crate) enum AccessError {
+    #[display(fmt = "authentication required")]
+    Unauthenticated,
+    #[display(fmt = "permission {:?} required", _0)]
+    Forbidden(Permission),
+    #[display(fmt = "two-factor authentication required")]
+    TwoFactorRequired,
+    #[display(fmt = "{}", _0)]
+    Db(DbError)
+}
+
+impl ResponseError for AccessError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AccessError::Unauthenticated => StatusCode::FOUND,
+            AccessError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AccessError::TwoFactorRequired => StatusCode::FOUND,
+            AccessError::Db(err) => err.status_code()
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            AccessError::Unauthenticated => HttpResponse::Found()
+                .insert_header((header::LOCATION, "/login"))
+                .finish(),
+            AccessError::Forbidden(_) => HttpResponse::build(StatusCode::FORBIDDEN)
+                .content_type(ContentType::html())
+                .body("Сюда вашей роли нельзя"),
+            AccessError::TwoFactorRequired => HttpResponse::Found()
+                .insert_header((header::LOCATION, "/account/two-factor"))
+                .finish(),
+            AccessError::Db(err) => err.error_response()
+        }
+    }
+}
+
+/// Токен из заголовка `Authorization: Bearer ...`, если он есть
+pub(crate) fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers().get(header::AUTHORIZATION)?
+        .to_str().ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+/// Владелец токена доступа, если токен жив и разрешает `scope`
+pub(crate) async fn token_user(client: &Client, token: &str, scope: TokenScope)
+    -> Result<User, ApiError>
+{
+    let api_token = match use_api_token(client, token, Utc::now().timestamp()).await {
+        Ok(api_token) => api_token,
+        Err(DbError::NotFound) => return Err(ApiError::Unauthorized),
+        Err(e) => return Err(e.into())
+    };
+    if !api_token.allows(scope) {
+        return Err(ApiError::Forbidden);
+    }
+    match get_user_by_id(client, api_token.user_id.unwrap_or_default()).await {
+        Ok(user) if user.banned == Some(true) => Err(ApiError::Auth(AuthError::Banned)),
+        Ok(user) if user.approval() == Approval::Rejected => Err(ApiError::Auth(AuthError::Rejected)),
+        Ok(user) => Ok(user),
+        Err(DbError::NotFound) => Err(ApiError::Unauthorized),
+        Err(e) => Err(e.into())
+    }
+}
+
+/// Текущий пользователь, у роли которого есть право `R::PERMISSION`.
+/// Без входа отправляет на `/login`, без права отвечает `403`; если роли нужен
+/// второй фактор, а он не включён, отправляет его включать.
+/// С токеном доступа вместо сессии токен должен разрешать область для этого права,
+/// а ошибки отдаются в JSON, как в API.
+/// Взятое из пула соединение остаётся запросу, и `ServiceData::new` берёт его же.
+pub(crate) struct Authorized<R: Requirement> {
+    pub(crate) user: User,
+    requirement: PhantomData<R>
+}
+
+impl<R: Requirement> FromRequest for Authorized<R> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        let app_state = req.app_data::<web::Data<AppState<'static>>>().cloned();
+        let session = Session::from_request(&req, payload).into_inner();
+        Box::pin(async move {
+            // скрипту навигации нужен не 302, а фрагмент с адресом
+            let unauthenticated = || InternalError::from_response(
+                AccessError::Unauthenticated, templator::redirect(&req, "/login")).into();
+            let (Some(app_state), Ok(session)) = (app_state, session) else {
+                return Err(unauthenticated());
+            };
+            let client = app_state.db_pool.get().await
+                .map_err(|e| AccessError::Db(DbError::PoolError(e)))?;
+            let bearer = bearer_token(&req);
+            let user = match bearer {
+                Some(token) => token_user(&client, token, TokenScope::for_permission(R::PERMISSION)).await?,
+                None => get_current_user(&client, session).await
+                    .map_err(|_| unauthenticated())?
+            };
+            if needs_totp(&client, &user).await.map_err(AccessError::Db)? {
+                if bearer.is_some() {
+                    return Err(ApiError::Auth(AuthError::TotpRequired).into());
+                }
+                return Err(InternalError::from_response(
+                    AccessError::TwoFactorRequired, templator::redirect(&req, "/account/two-factor")).into());
+            }
+            if !user.can(R::PERMISSION) {
+                return Err(AccessError::Forbidden(R::PERMISSION).into());
+            }
+            req.extensions_mut().insert(RequestClient(client));
+            Ok(Authorized { user, requirement: PhantomData })
+        })
+    }
+}
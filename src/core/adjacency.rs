@@ -0,0 +1,110 @@
+use std::collections::{HashMap, HashSet};
+use crate::models::seat::Seat;
+
+/// Расстояние на карте (в единицах svg), на котором места считаются соседними
+pub(crate) const NEIGHBOUR_DISTANCE: f64 = 1600.0;
+
+pub(crate) fn distance(a: &Seat, b: &Seat) -> f64 {
+    match (a.position(), b.position()) {
+        (Some((ax, ay)), Some((bx, by))) => ((ax - bx).powi(2) + (ay - by).powi(2)).sqrt(),
+        _ => f64::INFINITY
+    }
+}
+
+/// Граф соседства мест.
+///
+/// Соседи места берутся из `seat_neighbours`, если для него там что-то
+/// задано, иначе соседними считаются места ближе `NEIGHBOUR_DISTANCE`.
+pub(crate) struct Adjacency {
+    neighbours: HashMap<i32, HashSet<i32>>
+}
+
+impl Adjacency {
+    pub(crate) fn build(seats: &[Seat], manual: &[(i32, i32)]) -> Adjacency {
+        let mut neighbours: HashMap<i32, HashSet<i32>> = HashMap::new();
+        for &(a, b) in manual {
+            neighbours.entry(a).or_default().insert(b);
+            neighbours.entry(b).or_default().insert(a);
+        }
+        let manual_seats: HashSet<i32> = neighbours.keys().copied().collect();
+
+        for a in seats {
+            let Some(a_id) = a.id.filter(|id| !manual_seats.contains(id)) else {
+                continue;
+            };
+            let computed: HashSet<i32> = seats.iter()
+                .filter(|b| b.id.is_some_and(|id| id != a_id && !manual_seats.contains(&id)))
+                .filter(|b| distance(a, b) <= NEIGHBOUR_DISTANCE)
+                .filter_map(|b| b.id)
+                .collect();
+            neighbours.insert(a_id, computed);
+        }
+
+        Adjacency { neighbours }
+    }
+
+    pub(crate) fn are_neighbours(&self, a: i32, b: i32) -> bool {
+        self.neighbours.get(&a).is_some_and(|n| n.contains(&b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seat(id: i32, x: f64, y: f64) -> Seat {
+        Seat {
+            id: Some(id),
+            name: None,
+            seat_type: None,
+            availability: None,
+            default_x: Some(x),
+            default_y: Some(y),
+            default_rot: None,
+            x: None,
+            y: None,
+            rot: None,
+            amenities: None
+        }
+    }
+
+    #[test]
+    fn computed_neighbours_are_within_distance() {
+        let seats = [seat(1, 0.0, 0.0), seat(2, NEIGHBOUR_DISTANCE, 0.0), seat(3, NEIGHBOUR_DISTANCE + 1.0, 0.0)];
+        let adjacency = Adjacency::build(&seats, &[]);
+        assert!(adjacency.are_neighbours(1, 2));
+        assert!(adjacency.are_neighbours(2, 1));
+        assert!(!adjacency.are_neighbours(1, 3));
+        assert!(!adjacency.are_neighbours(1, 1));
+    }
+
+    #[test]
+    fn moved_seat_uses_current_position() {
+        let mut far = seat(2, 10000.0, 0.0);
+        far.x = Some(100.0);
+        far.y = Some(0.0);
+        let adjacency = Adjacency::build(&[seat(1, 0.0, 0.0), far], &[]);
+        assert!(adjacency.are_neighbours(1, 2));
+    }
+
+    #[test]
+    fn manual_neighbours_replace_computed_ones() {
+        // 1 и 2 рядом, но для 1 соседство задано вручную: только 3
+        let seats = [seat(1, 0.0, 0.0), seat(2, 100.0, 0.0), seat(3, 10000.0, 0.0), seat(4, 200.0, 0.0)];
+        let adjacency = Adjacency::build(&seats, &[(1, 3)]);
+        assert!(adjacency.are_neighbours(1, 3));
+        assert!(adjacency.are_neighbours(3, 1));
+        assert!(!adjacency.are_neighbours(1, 2));
+        assert!(!adjacency.are_neighbours(2, 1));
+        // у мест без ручных записей соседство по-прежнему по расстоянию
+        assert!(adjacency.are_neighbours(2, 4));
+    }
+
+    #[test]
+    fn seats_without_position_have_no_computed_neighbours() {
+        let mut nowhere = seat(2, 0.0, 0.0);
+        nowhere.default_x = None;
+        let adjacency = Adjacency::build(&[seat(1, 0.0, 0.0), nowhere], &[]);
+        assert!(!adjacency.are_neighbours(1, 2));
+    }
+}
@@ -0,0 +1,157 @@
+use std::fmt::{Display, Formatter};
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::Value;
+use crate::core::ServiceData;
+use crate::models::audit::{add_audit_record, AuditRecord};
+use crate::models::user::User;
+
+/// Что произошло
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum AuditAction {
+    Login,
+    LoginFailed,
+    Logout,
+    Register,
+    BookingCreated,
+    BookingCancelled,
+    BookingExtended,
+    BookingReassigned,
+    BookingCheckedIn,
+    UserRoleChanged,
+    UserBanned,
+    UserUnbanned,
+    UserPasswordReset,
+    UserUnlocked,
+    PasswordChanged,
+    PasswordResetRequested,
+    EmailChanged,
+    SeatCreated,
+    SeatUpdated,
+    SeatDeleted,
+    TokenCreated,
+    TokenRevoked,
+    AuditPurged,
+    InviteCreated,
+    InviteRevoked,
+    RegistrationApproved,
+    RegistrationRejected,
+    TwoFactorEnabled,
+    TwoFactorDisabled,
+    RecoveryCodesRegenerated,
+    RoleTwoFactorChanged
+}
+
+impl AuditAction {
+    pub(crate) const ALL: [AuditAction; 31] = [
+        AuditAction::Login, AuditAction::LoginFailed, AuditAction::Logout, AuditAction::Register,
+        AuditAction::BookingCreated, AuditAction::BookingCancelled, AuditAction::BookingExtended,
+        AuditAction::BookingReassigned, AuditAction::BookingCheckedIn, AuditAction::UserRoleChanged,
+        AuditAction::UserBanned, AuditAction::UserUnbanned, AuditAction::UserPasswordReset,
+        AuditAction::UserUnlocked, AuditAction::PasswordChanged, AuditAction::PasswordResetRequested,
+        AuditAction::EmailChanged, AuditAction::SeatCreated, AuditAction::SeatUpdated,
+        AuditAction::SeatDeleted, AuditAction::TokenCreated, AuditAction::TokenRevoked,
+        AuditAction::AuditPurged,
+        AuditAction::InviteCreated, AuditAction::InviteRevoked,
+        AuditAction::RegistrationApproved, AuditAction::RegistrationRejected,
+        AuditAction::TwoFactorEnabled, AuditAction::TwoFactorDisabled, AuditAction::RecoveryCodesRegenerated,
+        AuditAction::RoleTwoFactorChanged
+    ];
+}
+
+impl Display for AuditAction {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            AuditAction::Login => write!(f, "login"),
+            AuditAction::LoginFailed => write!(f, "login_failed"),
+            AuditAction::Logout => write!(f, "logout"),
+            AuditAction::Register => write!(f, "register"),
+            AuditAction::BookingCreated => write!(f, "booking_created"),
+            AuditAction::BookingCancelled => write!(f, "booking_cancelled"),
+            AuditAction::BookingExtended => write!(f, "booking_extended"),
+            AuditAction::BookingReassigned => write!(f, "booking_reassigned"),
+            AuditAction::BookingCheckedIn => write!(f, "booking_checked_in"),
+            AuditAction::UserRoleChanged => write!(f, "user_role_changed"),
+            AuditAction::UserBanned => write!(f, "user_banned"),
+            AuditAction::UserUnbanned => write!(f, "user_unbanned"),
+            AuditAction::UserPasswordReset => write!(f, "user_password_reset"),
+            AuditAction::UserUnlocked => write!(f, "user_unlocked"),
+            AuditAction::PasswordChanged => write!(f, "password_changed"),
+            AuditAction::PasswordResetRequested => write!(f, "password_reset_requested"),
+            AuditAction::EmailChanged => write!(f, "email_changed"),
+            AuditAction::SeatCreated => write!(f, "seat_created"),
+            AuditAction::SeatUpdated => write!(f, "seat_updated"),
+            AuditAction::SeatDeleted => write!(f, "seat_deleted"),
+            AuditAction::TokenCreated => write!(f, "token_created"),
+            AuditAction::TokenRevoked => write!(f, "token_revoked"),
+            AuditAction::AuditPurged => write!(f, "audit_purged"),
+            AuditAction::InviteCreated => write!(f, "invite_created"),
+            AuditAction::InviteRevoked => write!(f, "invite_revoked"),
+            AuditAction::RegistrationApproved => write!(f, "registration_approved"),
+            AuditAction::RegistrationRejected => write!(f, "registration_rejected"),
+            AuditAction::TwoFactorEnabled => write!(f, "two_factor_enabled"),
+            AuditAction::TwoFactorDisabled => write!(f, "two_factor_disabled"),
+            AuditAction::RecoveryCodesRegenerated => write!(f, "recovery_codes_regenerated"),
+            AuditAction::RoleTwoFactorChanged => write!(f, "role_two_factor_changed")
+        }
+    }
+}
+
+/// Над чем
+#[derive(Clone, Copy)]
+pub(crate) enum AuditTarget {
+    Nothing,
+    User(i32),
+    Seat(i32),
+    Booking(i32),
+    Token(i32),
+    Invite(i32)
+}
+
+impl AuditTarget {
+    pub(crate) const TYPES: [&'static str; 5] = ["user", "seat", "booking", "token", "invite"];
+
+    fn parts(&self) -> (Option<&'static str>, Option<i32>) {
+        match *self {
+            AuditTarget::Nothing => (None, None),
+            AuditTarget::User(id) => (Some("user"), Some(id)),
+            AuditTarget::Seat(id) => (Some("seat"), Some(id)),
+            AuditTarget::Booking(id) => (Some("booking"), Some(id)),
+            AuditTarget::Token(id) => (Some("token"), Some(id)),
+            AuditTarget::Invite(id) => (Some("invite"), Some(id))
+        }
+    }
+}
+
+/// Снимок значения для `before`/`after`
+pub(crate) fn snapshot<T: Serialize>(value: &T) -> Option<Value> {
+    serde_json::to_value(value).ok()
+}
+
+/// Дописывает запись в журнал. Адрес берётся у соединения, а не из заголовков,
+/// которые клиент может подделать. Сбой журнала пишется в лог и не мешает
+/// самому действию.
+pub(crate) async fn record(service_data: &ServiceData<'_>,
+                           actor: Option<&User>,
+                           action: AuditAction,
+                           target: AuditTarget,
+                           before: Option<Value>,
+                           after: Option<Value>)
+{
+    let (target_type, target_id) = target.parts();
+    let record = AuditRecord {
+        id: None,
+        actor_id: actor.and_then(|user| user.id),
+        action: Some(action.to_string()),
+        target_type: target_type.map(str::to_string),
+        target_id,
+        before,
+        after,
+        ip: service_data.req.peer_addr().map(|addr| addr.ip().to_string()),
+        created: Some(Utc::now().timestamp())
+    };
+    log::info!("audit: {} by {:?} on {:?} {:?}", action, record.actor_id, target_type, target_id);
+    if let Err(e) = add_audit_record(&service_data.client, &record).await {
+        log::warn!("failed to write audit record {action}: {e}");
+    }
+}
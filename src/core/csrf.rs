@@ -0,0 +1,141 @@
+use actix_session::{Session, SessionExt};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::PayloadError;
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, HttpResponse};
+use actix_web_lab::middleware::Next;
+use handlebars::{Context, Handlebars, Helper, HelperResult, Output, RenderContext};
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::Value;
+use crate::core::errors::{ApiErrorBody, ApiErrorDetails};
+use crate::core::templator;
+use crate::core::users::constant_time_eq;
+
+/// Ключ токена в сессии, в данных шаблона и имя поля формы
+pub(crate) const CSRF_KEY: &str = "csrf_token";
+
+/// Заголовок с токеном для запросов из скриптов
+pub(crate) const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// Что сказать, когда токен не подошёл
+const CSRF_FAILED: &str = "Страница устарела, обновите её и попробуйте ещё раз";
+
+#[derive(Deserialize)]
+struct CsrfForm {
+    csrf_token: Option<String>
+}
+
+/// Токен сессии; при первом обращении заводится новый
+pub(crate) fn csrf_token(session: &Session) -> String {
+    if let Ok(Some(token)) = session.get::<String>(CSRF_KEY) {
+        return token;
+    }
+    let bytes: [u8; 32] = rand::thread_rng().gen();
+    let token: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    if let Err(e) = session.insert(CSRF_KEY, &token) {
+        log::warn!("failed to store csrf token: {e}");
+    }
+    token
+}
+
+/// Добавляет токен в данные шаблона, чтобы его подхватил `{{csrf_field}}`
+pub(crate) fn with_csrf_token(session: &Session, data: &Value) -> Value {
+    let mut data = data.clone();
+    if let Value::Object(map) = &mut data {
+        map.insert(CSRF_KEY.to_string(), Value::String(csrf_token(session)));
+    }
+    data
+}
+
+/// `{{csrf_field}}` -- скрытое поле с токеном из корня данных шаблона
+pub(crate) fn csrf_field(_: &Helper,
+                         _: &Handlebars,
+                         ctx: &Context,
+                         _: &mut RenderContext,
+                         out: &mut dyn Output)
+    -> HelperResult
+{
+    let token = ctx.data().get(CSRF_KEY).and_then(Value::as_str).unwrap_or_default();
+    out.write(&format!(r#"<input type="hidden" name="{CSRF_KEY}" value="{token}" />"#))?;
+    Ok(())
+}
+
+/// Запрос с токеном API в `Authorization` не опирается на печеньку,
+/// подделать его чужой страницей нельзя
+fn is_token_auth(req: &ServiceRequest) -> bool {
+    req.headers().get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("Bearer "))
+}
+
+/// Токен из заголовка или из поля формы. Тело формы приходится прочитать целиком
+/// и вернуть в запрос, чтобы его разобрал обработчик.
+async fn submitted_token(req: &mut ServiceRequest) -> Result<Option<String>, actix_web::Error> {
+    if let Some(token) = req.headers().get(CSRF_HEADER).and_then(|value| value.to_str().ok()) {
+        return Ok(Some(token.to_string()));
+    }
+    let is_form = req.headers().get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+    if !is_form {
+        return Ok(None);
+    }
+
+    let body = req.extract::<Bytes>().await?;
+    let token = serde_urlencoded::from_bytes::<CsrfForm>(&body)
+        .ok()
+        .and_then(|form| form.csrf_token);
+    let stream = futures_util::stream::once(async move { Ok::<_, PayloadError>(body) });
+    req.set_payload(Payload::Stream { payload: Box::pin(stream) });
+    Ok(token)
+}
+
+/// Пропускает небезопасные запросы (всё, кроме GET, HEAD, OPTIONS и TRACE)
+/// только с токеном сессии. Запросы к API с токеном доступа не проверяются.
+pub(crate) async fn verify_csrf(mut req: ServiceRequest, next: Next<impl MessageBody>)
+    -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error>
+{
+    if req.method().is_safe() || is_token_auth(&req) {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    let expected: Option<String> = req.get_session().get(CSRF_KEY).ok().flatten();
+    let submitted = submitted_token(&mut req).await?;
+    let valid = match (expected, submitted) {
+        (Some(expected), Some(submitted)) => constant_time_eq(expected.as_bytes(), submitted.as_bytes()),
+        _ => false
+    };
+    if !valid {
+        log::warn!("csrf check failed for {} {}", req.method(), req.path());
+        let response = rejection(req.request());
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+/// Ответ на запрос без верного токена. Клиенту JSON -- ошибка в формате API,
+/// браузеру и скрипту навигации -- сообщение и возврат на страницу, с которой
+/// пришёл запрос: там уже будет свежий токен.
+fn rejection(req: &HttpRequest) -> HttpResponse {
+    if templator::wants_json(req) && !templator::is_fragment_request(req) {
+        return HttpResponse::Forbidden().json(ApiErrorBody {
+            error: ApiErrorDetails { code: "csrf_failed", message: CSRF_FAILED.to_string() }
+        });
+    }
+    templator::push_flash(&req.get_session(), CSRF_FAILED);
+    templator::redirect(req, &back_location(req))
+}
+
+/// Путь из `Referer`, если он ведёт на этот же сайт, иначе главная
+fn back_location(req: &HttpRequest) -> String {
+    let host = req.connection_info().host().to_string();
+    req.headers().get(header::REFERER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|referer| referer.split_once("://"))
+        .and_then(|(_, rest)| rest.strip_prefix(host.as_str()))
+        .filter(|path| path.starts_with('/') && !path.starts_with("//"))
+        .map_or_else(|| "/".to_string(), str::to_string)
+}
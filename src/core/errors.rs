@@ -1,12 +1,16 @@
 use actix_web::{HttpResponse, ResponseError};
+use actix_web::http::StatusCode;
 use deadpool_postgres::PoolError;
 use derive_more::{Display, Error, From};
+use serde::Serialize;
+use utoipa::ToSchema;
 use tokio_pg_mapper::Error as PGMError;
 use tokio_postgres::error::Error as PGError;
 
 #[derive(Debug, Display, Error, From)]
 pub enum DbError {
     NotFound,
+    Conflict,
     PGError(PGError),
     PGMError(PGMError),
     PoolError(PoolError),
@@ -16,10 +20,126 @@ impl ResponseError for DbError {
     fn error_response(&self) -> HttpResponse {
         match *self {
             DbError::NotFound => HttpResponse::NotFound().finish(),
+            DbError::Conflict => HttpResponse::Conflict().finish(),
             DbError::PoolError(ref err) => {
                 HttpResponse::InternalServerError().body(err.to_string())
             }
             _ => HttpResponse::InternalServerError().finish(),
         }
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Display, PartialEq)]
+pub enum AuthError {
+    BadName,
+    BadLogin,
+    BadPassword,
+    BadEmail,
+    BadInvite,
+    AlreadyExists,
+    NotFound,
+    Banned,
+    Rejected,
+    BadTotp,
+    TotpRequired,
+    TokenNotGenerated,
+    CookieNotWrote,
+    PasswordNotHashed
+}
+
+impl AuthError {
+    fn code(&self) -> &'static str {
+        match self {
+            AuthError::BadName => "bad_name",
+            AuthError::BadLogin => "bad_login",
+            AuthError::BadPassword => "bad_password",
+            AuthError::BadEmail => "bad_email",
+            AuthError::BadInvite => "bad_invite",
+            AuthError::AlreadyExists => "already_exists",
+            AuthError::NotFound => "unauthorized",
+            AuthError::Banned => "banned",
+            AuthError::Rejected => "rejected",
+            AuthError::BadTotp => "bad_totp",
+            AuthError::TotpRequired => "totp_required",
+            AuthError::TokenNotGenerated => "token_not_generated",
+            AuthError::CookieNotWrote => "cookie_not_wrote",
+            AuthError::PasswordNotHashed => "password_not_hashed"
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ApiErrorDetails {
+    /// Машиночитаемый код, например `not_found` или `conflict`
+    pub code: &'static str,
+    pub message: String
+}
+
+/// Тело ответа с ошибкой JSON API
+#[derive(Serialize, ToSchema)]
+pub struct ApiErrorBody {
+    pub error: ApiErrorDetails
+}
+
+/// Ошибки JSON API. Тело ответа всегда
+/// `{"error": {"code": "...", "message": "..."}}`.
+#[derive(Debug, Display, From)]
+pub enum ApiError {
+    #[display(fmt = "authentication required")]
+    Unauthorized,
+    #[display(fmt = "not allowed for this token")]
+    Forbidden,
+    #[display(fmt = "{}", _0)]
+    #[from(ignore)]
+    BadRequest(String),
+    #[display(fmt = "{}", _0)]
+    Db(DbError),
+    #[display(fmt = "{}", _0)]
+    Auth(AuthError)
+}
+
+impl ApiError {
+    fn code(&self) -> &'static str {
+        match self {
+            ApiError::Unauthorized => "unauthorized",
+            ApiError::Forbidden => "forbidden",
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Db(DbError::NotFound) => "not_found",
+            ApiError::Db(DbError::Conflict) => "conflict",
+            ApiError::Db(_) => "database_error",
+            ApiError::Auth(err) => err.code()
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden => StatusCode::FORBIDDEN,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Db(DbError::NotFound) => StatusCode::NOT_FOUND,
+            ApiError::Db(DbError::Conflict) => StatusCode::CONFLICT,
+            ApiError::Db(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Auth(AuthError::AlreadyExists) => StatusCode::CONFLICT,
+            ApiError::Auth(AuthError::NotFound) => StatusCode::UNAUTHORIZED,
+            ApiError::Auth(AuthError::Banned | AuthError::Rejected | AuthError::TotpRequired) =>
+                StatusCode::FORBIDDEN,
+            ApiError::Auth(AuthError::TokenNotGenerated | AuthError::CookieNotWrote
+                           | AuthError::PasswordNotHashed) =>
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Auth(_) => StatusCode::BAD_REQUEST
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        // подробности ошибок базы наружу не отдаём
+        let message = match self {
+            ApiError::Db(DbError::PGError(_) | DbError::PGMError(_) | DbError::PoolError(_)) =>
+                "internal database error".to_string(),
+            _ => self.to_string()
+        };
+        HttpResponse::build(self.status_code())
+            .json(ApiErrorBody { error: ApiErrorDetails { code: self.code(), message } })
+    }
+}
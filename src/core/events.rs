@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use actix_web::rt;
+use chrono::Utc;
+use deadpool_postgres::{Client, Pool};
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio_postgres::{AsyncMessage, NoTls};
+use crate::core::errors::DbError;
+use crate::core::search::availability_at;
+use crate::models::booking::{get_bookings_in_range, get_bookings_started_or_ended, get_checked_in_seats, Booking};
+use crate::models::seat::availability_status::AvailabilityStatus;
+use crate::models::seat::{get_all_seats, get_seat_by_id};
+
+/// Канал Postgres, через который изменения мест расходятся по всем процессам
+const NOTIFY_CHANNEL: &str = "seat_changes";
+
+/// Максимальная пауза между попытками переподключить слушателя
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Сколько событий может накопить отстающий подписчик, прежде чем начнёт их терять
+const CHANNEL_CAPACITY: usize = 256;
+
+/// Как часто проверять, не началась ли или не закончилась ли чья-то бронь
+const SCHEDULE_TICK: Duration = Duration::from_secs(30);
+
+/// Почему изменилось место
+#[derive(Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SeatEventReason {
+    Booked,
+    Cancelled,
+    Extended,
+    /// Пользователь отметился, что пришёл
+    CheckedIn,
+    /// Наступило время брони
+    Started,
+    /// Время брони вышло
+    Ended,
+    /// Место поменяли в админке
+    Updated,
+    /// Полное состояние карты для подписчика, потерявшего часть событий
+    Resync
+}
+
+/// Изменение доступности места на карте
+#[derive(Clone, Deserialize, Serialize)]
+pub(crate) struct SeatEvent {
+    pub(crate) seat_id: i32,
+    pub(crate) availability: AvailabilityStatus,
+    /// Занявший место уже на нём
+    pub(crate) checked_in: bool,
+    pub(crate) reason: SeatEventReason
+}
+
+/// Рассылка изменений мест всем подписчикам внутри процесса
+#[derive(Clone)]
+pub(crate) struct SeatEvents {
+    sender: broadcast::Sender<SeatEvent>
+}
+
+impl SeatEvents {
+    pub(crate) fn new() -> SeatEvents {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        SeatEvents { sender }
+    }
+
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<SeatEvent> {
+        self.sender.subscribe()
+    }
+
+    pub(crate) fn publish(&self, event: SeatEvent) {
+        // нет подписчиков -- нет и проблемы
+        let _ = self.sender.send(event);
+    }
+}
+
+/// Доступность места на текущий момент
+async fn seat_event(client: &Client, seat_id: i32, reason: SeatEventReason)
+    -> Result<SeatEvent, DbError>
+{
+    let now = Utc::now().timestamp();
+    let seat = get_seat_by_id(client, seat_id).await?;
+    let bookings = get_bookings_in_range(client, now, now + 1).await?;
+    let checked_in = get_checked_in_seats(client, now).await?;
+    Ok(SeatEvent {
+        seat_id,
+        availability: availability_at(&seat, &bookings, now),
+        checked_in: checked_in.contains(&seat_id),
+        reason
+    })
+}
+
+/// Доступность всех мест на текущий момент
+pub(crate) async fn snapshot(client: &Client) -> Result<Vec<SeatEvent>, DbError> {
+    let now = Utc::now().timestamp();
+    let seats = get_all_seats(client).await?;
+    let bookings = get_bookings_in_range(client, now, now + 1).await?;
+    let checked_in = get_checked_in_seats(client, now).await?;
+    Ok(seats.iter()
+        .filter_map(|seat| Some(SeatEvent {
+            seat_id: seat.id?,
+            availability: availability_at(seat, &bookings, now),
+            checked_in: seat.id.is_some_and(|id| checked_in.contains(&id)),
+            reason: SeatEventReason::Resync
+        }))
+        .collect())
+}
+
+/// Отправляет событие через `NOTIFY`, откуда его получат слушатели всех
+/// процессов, включая этот
+async fn notify(client: &Client, event: &SeatEvent) -> Result<(), DbError> {
+    let payload = serde_json::to_string(event).unwrap_or_default();
+    let stmt = include_str!("../models/sql/events/notify.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&NOTIFY_CHANNEL, &payload]).await?;
+    Ok(())
+}
+
+/// Пересчитывает доступность места на текущий момент и рассылает её
+pub(crate) async fn seat_changed(client: &Client,
+                                 events: &SeatEvents,
+                                 seat_id: i32,
+                                 reason: SeatEventReason)
+    -> Result<(), DbError>
+{
+    let event = seat_event(client, seat_id, reason).await?;
+    if let Err(e) = notify(client, &event).await {
+        // хотя бы этот процесс узнает об изменении
+        log::warn!("failed to notify about seat {seat_id} change: {e}");
+        events.publish(event);
+    }
+    Ok(())
+}
+
+/// Сообщает открытым картам, что бронь места изменилась.
+/// Карта показывает текущий момент, так что будущие брони не рассылаются:
+/// о них скажет `watch_schedule`, когда бронь начнётся.
+/// Ошибка рассылки не должна ломать саму бронь, поэтому только пишется в лог.
+pub(crate) async fn booking_changed(client: &Client,
+                                    events: &SeatEvents,
+                                    booking: &Booking,
+                                    reason: SeatEventReason)
+{
+    let now = Utc::now().timestamp();
+    let Some(seat_id) = booking.seat_id.filter(|_| booking.overlaps(now, now + 1)) else {
+        return;
+    };
+    if let Err(e) = seat_changed(client, events, seat_id, reason).await {
+        log::warn!("failed to publish seat {seat_id} change: {e}");
+    }
+}
+
+/// Сообщает о местах, у которых бронь началась или закончилась с прошлой проверки.
+/// Сами брони в этот момент никто не трогает, так что кроме этой задачи узнать об этом некому.
+/// Такая задача есть в каждом процессе, поэтому события идут только своим подписчикам, мимо `NOTIFY`.
+pub(crate) async fn watch_schedule(pool: Pool, events: SeatEvents) {
+    let mut checked = Utc::now().timestamp();
+    loop {
+        rt::time::sleep(SCHEDULE_TICK).await;
+        let client = match pool.get().await {
+            Ok(client) => client,
+            Err(e) => {
+                log::warn!("seat schedule: no database connection: {e}");
+                continue;
+            }
+        };
+        let now = Utc::now().timestamp();
+        let bookings = match get_bookings_started_or_ended(&client, checked, now).await {
+            Ok(bookings) => bookings,
+            Err(e) => {
+                // не сдвигаем checked, чтобы на следующем тике забрать пропущенное
+                log::warn!("seat schedule check failed: {e}");
+                continue;
+            }
+        };
+        checked = now;
+
+        // одно событие на место, даже если одна бронь кончилась, а следующая началась
+        let mut changed = BTreeMap::new();
+        for booking in bookings {
+            let Some(seat_id) = booking.seat_id else {
+                continue;
+            };
+            let reason = match booking.ends {
+                Some(ends) if ends <= now => SeatEventReason::Ended,
+                _ => SeatEventReason::Started
+            };
+            changed.entry(seat_id)
+                .and_modify(|r| if let SeatEventReason::Started = reason { *r = reason })
+                .or_insert(reason);
+        }
+        for (seat_id, reason) in changed {
+            match seat_event(&client, seat_id, reason).await {
+                Ok(event) => events.publish(event),
+                Err(e) => log::warn!("failed to publish seat {seat_id} schedule change: {e}")
+            }
+        }
+    }
+}
+
+/// Одно подключение слушателя: `LISTEN` и пересылка уведомлений подписчикам,
+/// пока соединение живо
+async fn listen_once(pg_config: &tokio_postgres::Config, events: &SeatEvents)
+    -> Result<(), tokio_postgres::Error>
+{
+    let (client, mut connection) = pg_config.connect(NoTls).await?;
+
+    let forward_events = events.clone();
+    let forward = rt::spawn(async move {
+        let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+        while let Some(message) = messages.next().await {
+            match message? {
+                AsyncMessage::Notification(notification) => {
+                    match serde_json::from_str::<SeatEvent>(notification.payload()) {
+                        Ok(event) => forward_events.publish(event),
+                        Err(e) => log::warn!("bad seat change notification: {e}")
+                    }
+                },
+                AsyncMessage::Notice(notice) => log::info!("seat listener notice: {notice}"),
+                _ => {}
+            }
+        }
+        Ok(())
+    });
+
+    client.batch_execute(&format!("LISTEN {NOTIFY_CHANNEL}")).await?;
+    log::info!("listening for seat changes on channel {NOTIFY_CHANNEL}");
+
+    // соединение живёт, пока жив клиент, так что держим его до конца пересылки
+    let result = forward.await.unwrap_or(Ok(()));
+    drop(client);
+    result
+}
+
+/// Слушает изменения мест на отдельном соединении вне пула и пересылает их
+/// подписчикам этого процесса. Соединение переподключается само.
+pub(crate) async fn listen(pg_config: tokio_postgres::Config, events: SeatEvents) {
+    let mut delay = Duration::from_secs(1);
+    loop {
+        match listen_once(&pg_config, &events).await {
+            Ok(()) => {
+                log::warn!("seat listener connection closed, reconnecting");
+                delay = Duration::from_secs(1);
+            },
+            Err(e) => log::warn!("seat listener failed: {e}, reconnecting in {delay:?}")
+        }
+        rt::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Datelike, Duration, Local, NaiveTime, TimeZone, Timelike, Weekday};
+use crate::models::booking::Booking;
+
+/// Место, день недели (с понедельника), время начала и длительность
+type PatternKey = (i32, u32, NaiveTime, i64);
+
+/// Самая частая привычка пользователя: место, день недели, время и длительность
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) struct Usual {
+    pub(crate) seat_id: i32,
+    pub(crate) weekday: Weekday,
+    pub(crate) time: NaiveTime,
+    pub(crate) duration: i64
+}
+
+impl Usual {
+    fn of(booking: &Booking) -> Option<Usual> {
+        let starts = Local.timestamp_opt(booking.starts?, 0).single()?;
+        Some(Usual {
+            seat_id: booking.seat_id?,
+            weekday: starts.weekday(),
+            time: NaiveTime::from_hms_opt(starts.hour(), starts.minute(), 0)?,
+            duration: booking.ends? - booking.starts?
+        })
+    }
+
+    /// Ближайший будущий интервал `[starts, ends)` по этой привычке
+    pub(crate) fn next_interval(&self, now: DateTime<Local>) -> Option<(i64, i64)> {
+        (0..=7)
+            .filter_map(|days| {
+                let date = now.date_naive() + Duration::days(days);
+                if date.weekday() != self.weekday {
+                    return None;
+                }
+                Local.from_local_datetime(&date.and_time(self.time)).earliest()
+            })
+            .find(|starts| *starts > now)
+            .map(|starts| (starts.timestamp(), starts.timestamp() + self.duration))
+    }
+}
+
+/// Ищет самую частую привычку в прошлых бронях.
+///
+/// Брони должны идти от новых к старым: при равенстве побеждает та
+/// привычка, что встречалась позже.
+pub(crate) fn usual_pattern(bookings: &[Booking]) -> Option<Usual> {
+    let mut counts: HashMap<PatternKey, (usize, usize, Usual)> = HashMap::new();
+    for (index, usual) in bookings.iter().filter_map(Usual::of).enumerate() {
+        let key: PatternKey = (usual.seat_id, usual.weekday.num_days_from_monday(), usual.time, usual.duration);
+        counts.entry(key).or_insert((0, index, usual)).0 += 1;
+    }
+    counts.into_values()
+        .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))
+        .map(|(_, _, usual)| usual)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(year, month, day, hour, minute, 0).unwrap()
+    }
+
+    fn booking(seat_id: i32, starts: DateTime<Local>, hours: i64) -> Booking {
+        Booking {
+            id: None,
+            seat_id: Some(seat_id),
+            user_id: Some(1),
+            starts: Some(starts.timestamp()),
+            ends: Some(starts.timestamp() + hours * 3600)
+        }
+    }
+
+    fn usual(weekday: Weekday, hour: u32, minute: u32) -> Usual {
+        Usual { seat_id: 1, weekday, time: NaiveTime::from_hms_opt(hour, minute, 0).unwrap(), duration: 3600 }
+    }
+
+    #[test]
+    fn most_frequent_pattern_wins() {
+        // 2024-03-04 -- понедельник
+        let bookings = [
+            booking(2, at(2024, 3, 26, 10, 0), 1), // вторник
+            booking(1, at(2024, 3, 18, 18, 0), 2),
+            booking(1, at(2024, 3, 11, 18, 0), 2),
+            booking(1, at(2024, 3, 4, 18, 0), 2)
+        ];
+        let found = usual_pattern(&bookings).unwrap();
+        assert_eq!((found.seat_id, found.weekday, found.duration), (1, Weekday::Mon, 7200));
+        assert_eq!(found.time, NaiveTime::from_hms_opt(18, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn ties_go_to_the_newest_pattern() {
+        let bookings = [
+            booking(2, at(2024, 3, 12, 10, 0), 1),
+            booking(1, at(2024, 3, 11, 18, 0), 1)
+        ];
+        assert_eq!(usual_pattern(&bookings).map(|u| u.seat_id), Some(2));
+        assert!(usual_pattern(&[]).is_none());
+    }
+
+    #[test]
+    fn other_duration_is_other_pattern() {
+        let bookings = [
+            booking(1, at(2024, 3, 18, 18, 0), 1),
+            booking(1, at(2024, 3, 11, 18, 0), 3),
+            booking(1, at(2024, 3, 4, 18, 0), 3)
+        ];
+        assert_eq!(usual_pattern(&bookings).map(|u| u.duration), Some(3 * 3600));
+    }
+
+    #[test]
+    fn booking_over_midnight_keeps_its_start_day() {
+        let found = usual_pattern(&[booking(1, at(2024, 3, 10, 23, 30), 2)]).unwrap();
+        assert_eq!(found.weekday, Weekday::Sun);
+        assert_eq!(found.duration, 7200);
+    }
+
+    #[test]
+    fn next_interval_later_today() {
+        let now = at(2024, 3, 4, 10, 0);
+        let (starts, ends) = usual(Weekday::Mon, 11, 0).next_interval(now).unwrap();
+        assert_eq!(starts, at(2024, 3, 4, 11, 0).timestamp());
+        assert_eq!(ends - starts, 3600);
+    }
+
+    #[test]
+    fn next_interval_already_passed_today_is_next_week() {
+        let now = at(2024, 3, 4, 10, 0);
+        let (starts, _) = usual(Weekday::Mon, 9, 0).next_interval(now).unwrap();
+        assert_eq!(starts, at(2024, 3, 11, 9, 0).timestamp());
+        // ровно сейчас -- тоже уже прошло
+        let (starts, _) = usual(Weekday::Mon, 10, 0).next_interval(now).unwrap();
+        assert_eq!(starts, at(2024, 3, 11, 10, 0).timestamp());
+    }
+
+    #[test]
+    fn next_interval_across_week_boundary() {
+        // воскресенье поздно вечером, привычка -- понедельник сразу после полуночи
+        let now = at(2024, 3, 10, 23, 50);
+        let (starts, _) = usual(Weekday::Mon, 0, 15).next_interval(now).unwrap();
+        assert_eq!(starts, at(2024, 3, 11, 0, 15).timestamp());
+        let (starts, _) = usual(Weekday::Sun, 23, 0).next_interval(now).unwrap();
+        assert_eq!(starts, at(2024, 3, 17, 23, 0).timestamp());
+    }
+}
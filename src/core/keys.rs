@@ -0,0 +1,132 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use actix_web::body::MessageBody;
+use actix_web::cookie::{Cookie, CookieJar, Key};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::web;
+use actix_web_lab::middleware::Next;
+use crate::AppState;
+use crate::config::SessionConfig;
+
+/// Имя печеньки, в которой actix-session хранит сессию
+pub(crate) const SESSION_COOKIE: &str = "id";
+
+/// Ключи печенек сессии. Шифруем только текущим, а предыдущий после смены ключа
+/// ещё принимаем, чтобы не разлогинить всех разом.
+#[derive(Clone)]
+pub(crate) struct SessionKeys {
+    pub(crate) current: Key,
+    pub(crate) previous: Option<Key>
+}
+
+fn encode_key(key: &Key) -> String {
+    key.master().iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode_key(hex: &str) -> io::Result<Key> {
+    let hex = hex.trim();
+    let bad = || io::Error::new(io::ErrorKind::InvalidData,
+                                "session key must be at least 64 bytes in hex");
+    if !hex.len().is_multiple_of(2) || hex.len() < 128 {
+        return Err(bad());
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|b| u8::from_str_radix(b, 16).ok()))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(bad)?;
+    Ok(Key::from(&bytes))
+}
+
+fn is_zero(key: &Key) -> bool {
+    key.master().iter().all(|b| *b == 0)
+}
+
+/// Ключ из файла; если файла нет, создаём его с новым случайным ключом
+fn load_or_generate(path: &Path) -> io::Result<Key> {
+    match std::fs::read_to_string(path) {
+        Ok(hex) => decode_key(&hex),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let key = Key::generate();
+            let mut options = OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            let mut file = options.open(path)?;
+            writeln!(file, "{}", encode_key(&key))?;
+            log::info!("generated new session key in {}", path.display());
+            Ok(key)
+        }
+        Err(e) => Err(e)
+    }
+}
+
+/// Ключ берётся из `SESSIONS.KEY`, иначе из `SESSIONS.KEY_FILE`.
+/// С нулевым ключом печеньку подделает кто угодно, поэтому он разрешён только в `DEV`.
+pub(crate) fn load_session_keys(config: &SessionConfig, dev: bool) -> io::Result<SessionKeys> {
+    let current = match &config.key {
+        Some(hex) => decode_key(hex)?,
+        None => load_or_generate(Path::new(&config.key_file))?
+    };
+    let previous = config.previous_key.as_deref().map(decode_key).transpose()?;
+
+    if !dev && (is_zero(&current) || previous.as_ref().is_some_and(is_zero)) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  "refusing to start with an all-zero session key outside dev mode"));
+    }
+    Ok(SessionKeys { current, previous })
+}
+
+/// Расшифровывает печеньку старым ключом и шифрует текущим.
+/// `None`, если перешифровывать нечего.
+fn reencrypt(cookie: Cookie<'static>, keys: &SessionKeys) -> Option<Cookie<'static>> {
+    let previous = keys.previous.as_ref()?;
+    let mut jar = CookieJar::new();
+    if jar.private(&keys.current).decrypt(cookie.clone()).is_some() {
+        return None;
+    }
+    let plain = jar.private(previous).decrypt(cookie)?;
+    jar.private_mut(&keys.current).add(plain);
+    jar.get(SESSION_COOKIE).cloned()
+}
+
+/// Подменяет печеньку сессии, зашифрованную предыдущим ключом, на зашифрованную текущим,
+/// до того как её увидит `SessionMiddleware`. Браузер получит новую печеньку,
+/// когда сессия в следующий раз изменится.
+pub(crate) async fn rotate_session_cookie(mut req: ServiceRequest, next: Next<impl MessageBody>)
+    -> Result<ServiceResponse<impl MessageBody>, actix_web::Error>
+{
+    let keys = req.app_data::<web::Data<AppState<'static>>>()
+        .map(|state| state.session_keys.clone());
+    let Some(keys) = keys.filter(|keys| keys.previous.is_some()) else {
+        return next.call(req).await;
+    };
+
+    let mut changed = false;
+    let cookies: Vec<String> = req.headers()
+        .get_all(header::COOKIE)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .map(str::trim)
+        .filter(|raw| !raw.is_empty())
+        .map(|raw| match Cookie::parse_encoded(raw.to_owned()) {
+            Ok(cookie) if cookie.name() == SESSION_COOKIE => match reencrypt(cookie, &keys) {
+                Some(cookie) => {
+                    changed = true;
+                    cookie.encoded().stripped().to_string()
+                }
+                None => raw.to_owned()
+            },
+            _ => raw.to_owned()
+        })
+        .collect();
+
+    if changed {
+        if let Ok(value) = HeaderValue::from_str(&cookies.join("; ")) {
+            req.headers_mut().insert(header::COOKIE, value);
+        }
+    }
+    next.call(req).await
+}
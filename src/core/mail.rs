@@ -0,0 +1,116 @@
+use chrono::Utc;
+use deadpool_postgres::Pool;
+use derive_more::{Display, From};
+use serde_json::Value;
+use actix_web::rt;
+use crate::config::NotifyConfig;
+use crate::core::ServiceData;
+use crate::core::errors::DbError;
+use crate::core::notify::{send, Notification};
+use crate::models::email::{add_outbox_email, claim_outbox_emails, set_email_failed, set_email_rejected,
+                          set_email_sent};
+
+#[derive(Debug, Display, From)]
+pub(crate) enum MailError {
+    Render(handlebars::RenderError),
+    Db(DbError)
+}
+
+/// Самая долгая пауза между попытками, в секундах
+const MAX_RETRY_DELAY: i64 = 24 * 60 * 60;
+
+/// Собирает письмо из шаблонов `views/emails/{template}.txt.hbs` и, если есть,
+/// `{template}.html.hbs` и кладёт его в очередь. Отправит его `deliver_outbox`.
+pub(crate) async fn queue_email(service_data: &ServiceData<'_>,
+                                to: &str,
+                                subject: &str,
+                                template: &str,
+                                data: &Value)
+    -> Result<(), MailError>
+{
+    let handlebars = &service_data.app_state.handlebars;
+    let text = handlebars.render(&format!("emails/{template}.txt"), data)?;
+    let html_template = format!("emails/{template}.html");
+    let html = match handlebars.has_template(&html_template) {
+        true => Some(handlebars.render(&html_template, data)?),
+        false => None
+    };
+    add_outbox_email(&service_data.client, to, subject, &text, html.as_deref(),
+                     Utc::now().timestamp()).await?;
+    Ok(())
+}
+
+/// Пауза перед следующей попыткой: `retry_base`, потом вдвое дольше и так далее
+fn retry_delay(config: &NotifyConfig, attempts: i32) -> i64 {
+    let doublings = attempts.clamp(1, 32) as u32 - 1;
+    config.retry_base.saturating_mul(1i64 << doublings).min(MAX_RETRY_DELAY)
+}
+
+/// Время от времени отправляет письма из очереди. Неудачные пробует снова,
+/// пока не кончатся `max_attempts` попыток; отвергнутые релеем насовсем не повторяет.
+pub(crate) async fn deliver_outbox(pool: Pool, config: NotifyConfig) {
+    let every = std::time::Duration::from_secs(config.poll_interval);
+    // письма пачки отправляются по очереди, и каждое может ждать релей до таймаута
+    let lease = config.smtp_timeout as i64 * config.batch_size + 60;
+    loop {
+        rt::time::sleep(every).await;
+        let client = match pool.get().await {
+            Ok(client) => client,
+            Err(e) => {
+                log::warn!("outbox: no database connection: {e}");
+                continue;
+            }
+        };
+        let emails = match claim_outbox_emails(&client, Utc::now().timestamp(), lease,
+                                               config.max_attempts, config.batch_size).await {
+            Ok(emails) => emails,
+            Err(e) => {
+                log::warn!("outbox: failed to fetch emails: {e}");
+                continue;
+            }
+        };
+        for email in emails {
+            let (id, attempts) = (email.id, email.attempts + 1);
+            let result = match send(&config, &Notification::from(email)).await {
+                Ok(()) => set_email_sent(&client, id, Utc::now().timestamp()).await,
+                Err(e) if e.is_permanent() => {
+                    log::warn!("outbox: email {id} rejected, giving up: {e}");
+                    set_email_rejected(&client, id, config.max_attempts, &e.to_string()).await
+                }
+                Err(e) => {
+                    if attempts >= config.max_attempts {
+                        log::warn!("outbox: giving up on email {id} after {attempts} attempts: {e}");
+                    } else {
+                        log::info!("outbox: email {id} failed, attempt {attempts}: {e}");
+                    }
+                    let next_attempt = Utc::now().timestamp() + retry_delay(&config, attempts);
+                    set_email_failed(&client, id, next_attempt, &e.to_string()).await
+                }
+            };
+            if let Err(e) = result {
+                log::warn!("outbox: failed to update email {id}: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_delay_doubles() {
+        let config = NotifyConfig { retry_base: 60, ..NotifyConfig::default() };
+        assert_eq!(retry_delay(&config, 1), 60);
+        assert_eq!(retry_delay(&config, 2), 120);
+        assert_eq!(retry_delay(&config, 5), 960);
+    }
+
+    #[test]
+    fn retry_delay_is_capped() {
+        let config = NotifyConfig { retry_base: 60, ..NotifyConfig::default() };
+        assert_eq!(retry_delay(&config, 20), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(&config, i32::MAX), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(&config, 0), 60);
+    }
+}
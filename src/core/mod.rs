@@ -1,12 +1,28 @@
 use actix_session::Session;
-use actix_web::{HttpRequest, web};
+use actix_web::{HttpMessage, HttpRequest, web};
 use deadpool_postgres::Client;
 use crate::AppState;
 use crate::core::errors::DbError;
 
+pub mod access;
+pub mod adjacency;
+pub mod audit;
+pub mod csrf;
 pub mod errors;
+pub mod events;
+pub mod habits;
+pub mod remember;
+pub mod mail;
+pub mod notify;
+pub mod qr;
+pub mod keys;
 pub mod db;
+pub mod search;
+pub mod session_store;
+pub mod smtp;
 pub mod templator;
+pub mod throttle;
+pub mod totp;
 pub mod users;
 
 pub(crate) struct ServiceData<'a> {
@@ -16,9 +32,16 @@ pub(crate) struct ServiceData<'a> {
     pub(crate) client: Client
 }
 
+/// Соединение, которое уже взял для запроса экстрактор вроде `Authorized`
+pub(crate) struct RequestClient(pub(crate) Client);
+
 impl ServiceData<'_> {
     pub(crate) async fn new(req: HttpRequest, app_state: web::Data<AppState<'_>>, session: Session) -> Result<ServiceData, DbError> {
-        let client = app_state.db_pool.get().await.map_err(DbError::PoolError)?;
+        let shared = req.extensions_mut().remove::<RequestClient>();
+        let client = match shared {
+            Some(RequestClient(client)) => client,
+            None => app_state.db_pool.get().await.map_err(DbError::PoolError)?
+        };
         let data = ServiceData {
             req,
             app_state,
@@ -0,0 +1,37 @@
+use crate::config::{NotifierKind, NotifyConfig};
+use crate::core::smtp::{send_mail, SmtpError};
+use crate::models::email::OutboxEmail;
+
+/// Сообщение пользователю
+pub(crate) struct Notification {
+    /// Адрес почты
+    pub(crate) to: String,
+    pub(crate) subject: String,
+    pub(crate) text: String,
+    pub(crate) html: Option<String>
+}
+
+impl From<OutboxEmail> for Notification {
+    fn from(email: OutboxEmail) -> Self {
+        Notification {
+            to: email.recipient,
+            subject: email.subject,
+            text: email.text_body,
+            html: email.html_body
+        }
+    }
+}
+
+/// Доставляет сообщение способом из `NOTIFY.KIND`: письмом через SMTP
+/// или, для разработки, строкой в логе
+pub(crate) async fn send(config: &NotifyConfig, notification: &Notification) -> Result<(), SmtpError> {
+    match config.kind {
+        NotifierKind::Console => {
+            log::info!("notification to {}: {}\n{}",
+                       notification.to, notification.subject, notification.text);
+            Ok(())
+        }
+        NotifierKind::Smtp => send_mail(config, &notification.to, &notification.subject,
+                                        &notification.text, notification.html.as_deref()).await
+    }
+}
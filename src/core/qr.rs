@@ -0,0 +1,14 @@
+//! QR-код для ссылки `otpauth://` в виде SVG: его сканирует приложение-аутентификатор
+
+use qrcode::{EcLevel, QrCode};
+use qrcode::render::svg;
+
+/// Объявление XML, с которого начинается SVG; внутри страницы оно не нужно
+const XML_DECLARATION: &str = r#"<?xml version="1.0" standalone="yes"?>"#;
+
+/// QR-код `text` в виде SVG с уровнем коррекции M; `None`, если текст не влезает в код
+pub(crate) fn qr_svg(text: &str) -> Option<String> {
+    let code = QrCode::with_error_correction_level(text, EcLevel::M).ok()?;
+    let svg = code.render::<svg::Color>().min_dimensions(200, 200).build();
+    Some(svg.trim_start_matches(XML_DECLARATION).to_string())
+}
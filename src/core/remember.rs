@@ -0,0 +1,68 @@
+use actix_session::{Session, SessionExt};
+use actix_web::body::MessageBody;
+use actix_web::cookie::Cookie;
+use actix_web::cookie::time::Duration;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{HttpMessage, web};
+use actix_web_lab::middleware::Next;
+use crate::AppState;
+use crate::core::keys::SESSION_COOKIE;
+
+/// Ключ в сессии: вход был с галочкой «запомнить меня»
+const REMEMBER_KEY: &str = "remember";
+
+/// Метка запроса, что печенька сессии должна пережить закрытие браузера
+struct Remembered;
+
+/// Запоминает в сессии, просил ли пользователь его запомнить. Флаг ставится
+/// при каждом входе, так что от прошлого входа он не остаётся.
+pub(crate) fn set_remembered(session: &Session, remember: bool) {
+    if let Err(e) = session.insert(REMEMBER_KEY, remember) {
+        log::warn!("failed to store remember flag: {e}");
+    }
+}
+
+/// Переносит флаг из сессии в запрос, пока `SessionMiddleware` не забрал её данные.
+/// Должен стоять внутри `SessionMiddleware`.
+pub(crate) async fn mark_remembered(req: ServiceRequest, next: Next<impl MessageBody>)
+    -> Result<ServiceResponse<impl MessageBody>, actix_web::Error>
+{
+    let res = next.call(req).await?;
+    let session = res.request().get_session();
+    let logged_in = session.get::<String>("token").ok().flatten().is_some();
+    if logged_in && session.get::<bool>(REMEMBER_KEY).ok().flatten() == Some(true) {
+        res.request().extensions_mut().insert(Remembered);
+    }
+    Ok(res)
+}
+
+/// Печенька сессии по умолчанию живёт до закрытия браузера. Запомненным входам
+/// она выдаётся со сроком `SESSIONS.REMEMBER_TIMEOUT` всякий раз, когда
+/// `SessionMiddleware` её переписывает. Должен стоять снаружи `SessionMiddleware`.
+pub(crate) async fn remember_session_cookie(req: ServiceRequest, next: Next<impl MessageBody>)
+    -> Result<ServiceResponse<impl MessageBody>, actix_web::Error>
+{
+    let ttl = req.app_data::<web::Data<AppState<'static>>>()
+        .map(|state| state.sessions.remember_timeout);
+    let mut res = next.call(req).await?;
+    let Some(ttl) = ttl.filter(|_| res.request().extensions().contains::<Remembered>()) else {
+        return Ok(res);
+    };
+
+    let headers = res.headers_mut();
+    let cookies: Vec<HeaderValue> = headers.get_all(header::SET_COOKIE).cloned().collect();
+    headers.remove(header::SET_COOKIE);
+    for value in cookies {
+        let value = match value.to_str().ok().map(|raw| Cookie::parse_encoded(raw.to_owned())) {
+            // пустое значение -- печеньку удаляют, срок ей не нужен
+            Some(Ok(mut cookie)) if cookie.name() == SESSION_COOKIE && !cookie.value().is_empty() => {
+                cookie.set_max_age(Duration::seconds(ttl));
+                HeaderValue::from_str(&cookie.encoded().to_string()).unwrap_or(value)
+            },
+            _ => value
+        };
+        headers.append(header::SET_COOKIE, value);
+    }
+    Ok(res)
+}
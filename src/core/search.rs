@@ -0,0 +1,276 @@
+use std::collections::HashSet;
+use serde::Serialize;
+use crate::core::adjacency::{Adjacency, distance};
+use crate::models::booking::Booking;
+use crate::models::seat::Seat;
+use crate::models::seat::availability_status::AvailabilityStatus;
+use crate::models::seat::seat_type::SeatType;
+
+/// Сколько свободного времени вокруг брони учитывать при ранжировании, в секундах
+const BUFFER_CAP: i64 = 2 * 60 * 60;
+
+/// Сколько кандидатов отдавать пользователю
+const MAX_CANDIDATES: usize = 10;
+
+pub(crate) struct SeatQuery {
+    pub(crate) starts: i64,
+    pub(crate) ends: i64,
+    pub(crate) seat_type: Option<SeatType>,
+    pub(crate) amenities: Vec<String>,
+    pub(crate) people: usize
+}
+
+impl SeatQuery {
+    /// Интервал, брони из которого нужны для поиска
+    pub(crate) fn bookings_window(&self) -> (i64, i64) {
+        (self.starts - BUFFER_CAP, self.ends + BUFFER_CAP)
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct Candidate {
+    pub(crate) seats: Vec<Seat>,
+    pub(crate) score: f64
+}
+
+/// Сколько свободного времени есть у места до и после запрошенного интервала
+fn buffer(seat: &Seat, bookings: &[Booking], query: &SeatQuery) -> i64 {
+    let (mut before, mut after) = (BUFFER_CAP, BUFFER_CAP);
+    for booking in bookings.iter().filter(|b| b.seat_id == seat.id) {
+        if let Some(ends) = booking.ends.filter(|&e| e <= query.starts) {
+            before = before.min(query.starts - ends);
+        }
+        if let Some(starts) = booking.starts.filter(|&s| s >= query.ends) {
+            after = after.min(starts - query.ends);
+        }
+    }
+    before.min(after)
+}
+
+/// Доступность места в момент `at` с учётом броней
+pub(crate) fn availability_at(seat: &Seat, bookings: &[Booking], at: i64) -> AvailabilityStatus {
+    match seat.availability {
+        Some(AvailabilityStatus::Unavailable) => AvailabilityStatus::Unavailable,
+        _ if bookings.iter().any(|b| b.seat_id == seat.id && b.overlaps(at, at + 1)) =>
+            AvailabilityStatus::Taken,
+        Some(availability) => availability,
+        None => AvailabilityStatus::Free
+    }
+}
+
+pub(crate) fn is_suitable(seat: &Seat, bookings: &[Booking], query: &SeatQuery) -> bool {
+    seat.id.is_some()
+        && seat.availability != Some(AvailabilityStatus::Unavailable)
+        && query.seat_type.is_none_or(|t| seat.seat_type == Some(t))
+        && seat.has_amenities(&query.amenities)
+        && !bookings.iter()
+            .any(|b| b.seat_id == seat.id && b.overlaps(query.starts, query.ends))
+}
+
+/// Собирает группу из `size` соседних мест, начиная с `anchor` и добавляя
+/// ближайшее к уже выбранным место, соседнее хотя бы с одним из них
+fn grow_group<'a>(anchor: &'a Seat,
+                  free: &[&'a Seat],
+                  adjacency: &Adjacency,
+                  size: usize)
+    -> Option<Vec<&'a Seat>>
+{
+    let mut group = vec![anchor];
+    while group.len() < size {
+        let next = free.iter()
+            .filter(|s| !group.iter().any(|g| g.id == s.id))
+            .filter(|s| group.iter().any(|g| match (g.id, s.id) {
+                (Some(g), Some(s)) => adjacency.are_neighbours(g, s),
+                _ => false
+            }))
+            .map(|s| (*s, group.iter().map(|g| distance(g, s)).fold(f64::INFINITY, f64::min)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))?;
+        group.push(next.0);
+    }
+    Some(group)
+}
+
+/// Разброс группы: среднее расстояние до её центра
+fn spread(group: &[&Seat]) -> f64 {
+    let points: Vec<(f64, f64)> = group.iter().filter_map(|s| s.position()).collect();
+    if points.len() < 2 {
+        return 0.0;
+    }
+    let n = points.len() as f64;
+    let (cx, cy) = points.iter().fold((0.0, 0.0), |(x, y), p| (x + p.0 / n, y + p.1 / n));
+    points.iter().map(|p| ((p.0 - cx).powi(2) + (p.1 - cy).powi(2)).sqrt()).sum::<f64>() / n
+}
+
+/// Подбирает места под запрос и сортирует их от лучшего к худшему.
+///
+/// Чем больше свободного времени вокруг брони и чем плотнее сидит группа,
+/// тем выше место в выдаче.
+pub(crate) fn find_candidates(seats: &[Seat],
+                              bookings: &[Booking],
+                              adjacency: &Adjacency,
+                              query: &SeatQuery)
+    -> Vec<Candidate>
+{
+    let free: Vec<&Seat> = seats.iter()
+        .filter(|s| is_suitable(s, bookings, query))
+        .collect();
+
+    let mut seen: HashSet<Vec<i32>> = HashSet::new();
+    let mut candidates: Vec<Candidate> = Vec::new();
+    for anchor in &free {
+        let Some(group) = grow_group(anchor, &free, adjacency, query.people.max(1)) else {
+            continue;
+        };
+        let mut ids: Vec<i32> = group.iter().filter_map(|s| s.id).collect();
+        ids.sort_unstable();
+        if !seen.insert(ids) {
+            continue;
+        }
+        let buffer = group.iter().map(|s| buffer(s, bookings, query)).min().unwrap_or(0);
+        let score = buffer as f64 / 60.0 - spread(&group) / 100.0;
+        candidates.push(Candidate {
+            seats: group.into_iter().cloned().collect(),
+            score
+        });
+    }
+
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    candidates.truncate(MAX_CANDIDATES);
+    candidates
+}
+
+/// Ближайшее к `target` подходящее свободное место среди его соседей.
+/// Если все соседи заняты, `None`: место через весь зал «рядом» не считается.
+pub(crate) fn nearest_free<'a>(seats: &'a [Seat],
+                               bookings: &[Booking],
+                               adjacency: &Adjacency,
+                               query: &SeatQuery,
+                               target: &Seat)
+    -> Option<&'a Seat>
+{
+    let target_id = target.id?;
+    seats.iter()
+        .filter(|s| s.id.is_some_and(|id| id != target_id && adjacency.are_neighbours(target_id, id)))
+        .filter(|s| is_suitable(s, bookings, query))
+        .min_by(|a, b| distance(target, a).total_cmp(&distance(target, b)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seat(id: i32, x: f64, y: f64) -> Seat {
+        Seat {
+            id: Some(id),
+            name: Some(format!("seat {id}")),
+            seat_type: Some(SeatType::Desk),
+            availability: Some(AvailabilityStatus::Free),
+            default_x: Some(x),
+            default_y: Some(y),
+            default_rot: Some(0.0),
+            x: None,
+            y: None,
+            rot: None,
+            amenities: Some(vec!["socket".into()])
+        }
+    }
+
+    fn booking(seat_id: i32, starts: i64, ends: i64) -> Booking {
+        Booking { id: None, seat_id: Some(seat_id), user_id: Some(1), starts: Some(starts), ends: Some(ends) }
+    }
+
+    fn query(starts: i64, ends: i64, people: usize) -> SeatQuery {
+        SeatQuery { starts, ends, seat_type: None, amenities: Vec::new(), people }
+    }
+
+    #[test]
+    fn overlapping_booking_makes_seat_unsuitable() {
+        let s = seat(1, 0.0, 0.0);
+        assert!(!is_suitable(&s, &[booking(1, 900, 1100)], &query(1000, 2000, 1)));
+        assert!(!is_suitable(&s, &[booking(1, 1500, 1600)], &query(1000, 2000, 1)));
+        // брони встык не мешают
+        assert!(is_suitable(&s, &[booking(1, 0, 1000), booking(1, 2000, 3000)], &query(1000, 2000, 1)));
+        // чужие брони тоже
+        assert!(is_suitable(&s, &[booking(2, 1000, 2000)], &query(1000, 2000, 1)));
+    }
+
+    #[test]
+    fn constraints_filter_seats() {
+        let mut s = seat(1, 0.0, 0.0);
+        let mut q = query(0, 100, 1);
+        q.seat_type = Some(SeatType::Pouf);
+        assert!(!is_suitable(&s, &[], &q));
+        q.seat_type = Some(SeatType::Desk);
+        q.amenities = vec!["SOCKET".into()];
+        assert!(is_suitable(&s, &[], &q));
+        q.amenities.push("monitor".into());
+        assert!(!is_suitable(&s, &[], &q));
+        q.amenities.clear();
+        s.availability = Some(AvailabilityStatus::Unavailable);
+        assert!(!is_suitable(&s, &[], &q));
+    }
+
+    #[test]
+    fn group_grows_only_over_neighbours() {
+        // 1 и 2 рядом, 3 далеко от обоих
+        let seats = [seat(1, 0.0, 0.0), seat(2, 1000.0, 0.0), seat(3, 10000.0, 0.0)];
+        let adjacency = Adjacency::build(&seats, &[]);
+        let free: Vec<&Seat> = seats.iter().collect();
+
+        let group = grow_group(&seats[0], &free, &adjacency, 2).unwrap();
+        assert_eq!(group.iter().map(|s| s.id).collect::<Vec<_>>(), [Some(1), Some(2)]);
+        assert!(grow_group(&seats[2], &free, &adjacency, 2).is_none());
+        assert!(grow_group(&seats[0], &free, &adjacency, 3).is_none());
+    }
+
+    #[test]
+    fn group_grows_along_a_chain() {
+        let seats = [seat(1, 0.0, 0.0), seat(2, 1500.0, 0.0), seat(3, 3000.0, 0.0)];
+        let adjacency = Adjacency::build(&seats, &[]);
+        let free: Vec<&Seat> = seats.iter().collect();
+        // 3 не сосед 1, но сосед 2, уже попавшего в группу
+        assert_eq!(grow_group(&seats[0], &free, &adjacency, 3).map(|g| g.len()), Some(3));
+    }
+
+    #[test]
+    fn spread_is_mean_distance_to_centre() {
+        let (a, b) = (seat(1, 0.0, 0.0), seat(2, 200.0, 0.0));
+        assert_eq!(spread(&[&a]), 0.0);
+        assert_eq!(spread(&[&a, &b]), 100.0);
+    }
+
+    #[test]
+    fn candidates_prefer_free_time_around() {
+        let seats = [seat(1, 0.0, 0.0), seat(2, 10000.0, 0.0)];
+        let adjacency = Adjacency::build(&seats, &[]);
+        // у места 1 бронь кончается прямо перед запросом
+        let bookings = [booking(1, 0, 10000)];
+        let candidates = find_candidates(&seats, &bookings, &adjacency, &query(10000, 20000, 1));
+        let ids: Vec<_> = candidates.iter().map(|c| c.seats[0].id).collect();
+        assert_eq!(ids, [Some(2), Some(1)]);
+    }
+
+    #[test]
+    fn candidates_for_group_are_unique_and_connected() {
+        let seats = [seat(1, 0.0, 0.0), seat(2, 1000.0, 0.0), seat(3, 10000.0, 0.0)];
+        let adjacency = Adjacency::build(&seats, &[]);
+        let candidates = find_candidates(&seats, &[], &adjacency, &query(0, 100, 2));
+        // {1, 2} находится и от 1, и от 2, но в выдаче один раз; 3 не с кем объединить
+        assert_eq!(candidates.len(), 1);
+        assert!(find_candidates(&seats, &[], &adjacency, &query(0, 100, 3)).is_empty());
+    }
+
+    #[test]
+    fn nearest_free_stays_among_neighbours() {
+        let seats = [seat(1, 0.0, 0.0), seat(2, 1500.0, 0.0), seat(3, 800.0, 0.0), seat(4, 10000.0, 0.0)];
+        let adjacency = Adjacency::build(&seats, &[]);
+        let q = query(0, 100, 1);
+        assert_eq!(nearest_free(&seats, &[], &adjacency, &q, &seats[0]).and_then(|s| s.id), Some(3));
+        // ближайший сосед занят -- следующий по расстоянию сосед
+        let bookings = [booking(3, 0, 100)];
+        assert_eq!(nearest_free(&seats, &bookings, &adjacency, &q, &seats[0]).and_then(|s| s.id), Some(2));
+        // все соседи заняты, а свободное место 4 далеко
+        let bookings = [booking(2, 0, 100), booking(3, 0, 100)];
+        assert!(nearest_free(&seats, &bookings, &adjacency, &q, &seats[0]).is_none());
+    }
+}
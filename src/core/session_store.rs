@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use actix_session::storage::{CookieSessionStore, LoadError, SaveError, SessionKey, SessionStore, UpdateError};
+use actix_web::cookie::time::Duration;
+use chrono::Utc;
+use deadpool_postgres::{Client, Pool};
+use rand::distributions::{Alphanumeric, DistString};
+use crate::config::SessionStoreKind;
+use crate::core::errors::DbError;
+use crate::models::session_store::{add_session_state, get_session_state, remove_session_state,
+                                   update_session_expires, update_session_state};
+
+type SessionState = HashMap<String, String>;
+
+/// Длина ключа сессии в печеньке
+const SESSION_KEY_LENGTH: usize = 64;
+
+/// Хранит данные сессий в таблице `session_store`: их видно со всех экземпляров
+/// сервера и можно удалить, не дожидаясь, пока истечёт печенька.
+#[derive(Clone)]
+pub(crate) struct PgSessionStore {
+    pool: Pool
+}
+
+impl PgSessionStore {
+    pub(crate) fn new(pool: Pool) -> Self {
+        PgSessionStore { pool }
+    }
+
+    async fn client(&self) -> anyhow::Result<Client> {
+        Ok(self.pool.get().await.map_err(DbError::PoolError)?)
+    }
+}
+
+fn expires(ttl: &Duration) -> i64 {
+    Utc::now().timestamp() + ttl.whole_seconds()
+}
+
+impl SessionStore for PgSessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        let client = self.client().await.map_err(LoadError::Other)?;
+        let state = get_session_state(&client, session_key.as_ref(), Utc::now().timestamp())
+            .await
+            .map_err(|e| LoadError::Other(e.into()))?;
+        state.map(serde_json::from_value)
+            .transpose()
+            .map_err(|e| LoadError::Deserialization(e.into()))
+    }
+
+    async fn save(&self, session_state: SessionState, ttl: &Duration) -> Result<SessionKey, SaveError> {
+        let state = serde_json::to_value(&session_state)
+            .map_err(|e| SaveError::Serialization(e.into()))?;
+        let key = Alphanumeric.sample_string(&mut rand::thread_rng(), SESSION_KEY_LENGTH);
+        let client = self.client().await.map_err(SaveError::Other)?;
+        add_session_state(&client, &key, &state, expires(ttl))
+            .await
+            .map_err(|e| SaveError::Other(e.into()))?;
+        SessionKey::try_from(key).map_err(|e| SaveError::Other(e.into()))
+    }
+
+    async fn update(&self, session_key: SessionKey, session_state: SessionState, ttl: &Duration)
+        -> Result<SessionKey, UpdateError>
+    {
+        let state = serde_json::to_value(&session_state)
+            .map_err(|e| UpdateError::Serialization(e.into()))?;
+        let client = self.client().await.map_err(UpdateError::Other)?;
+        match update_session_state(&client, session_key.as_ref(), &state, expires(ttl)).await {
+            Ok(()) => Ok(session_key),
+            // сессию успели удалить -- заводим новую
+            Err(DbError::NotFound) => self.save(session_state, ttl).await.map_err(|e| match e {
+                SaveError::Serialization(e) => UpdateError::Serialization(e),
+                SaveError::Other(e) => UpdateError::Other(e)
+            }),
+            Err(e) => Err(UpdateError::Other(e.into()))
+        }
+    }
+
+    async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> anyhow::Result<()> {
+        let client = self.client().await?;
+        Ok(update_session_expires(&client, session_key.as_ref(), expires(ttl)).await?)
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> anyhow::Result<()> {
+        let client = self.client().await?;
+        Ok(remove_session_state(&client, session_key.as_ref()).await?)
+    }
+}
+
+/// Хранилище, выбранное в `SESSIONS.STORE`
+pub(crate) enum AppSessionStore {
+    Cookie(CookieSessionStore),
+    Postgres(PgSessionStore)
+}
+
+impl AppSessionStore {
+    pub(crate) fn new(kind: SessionStoreKind, pool: &Pool) -> Self {
+        match kind {
+            SessionStoreKind::Cookie => AppSessionStore::Cookie(CookieSessionStore::default()),
+            SessionStoreKind::Postgres => AppSessionStore::Postgres(PgSessionStore::new(pool.clone()))
+        }
+    }
+}
+
+impl SessionStore for AppSessionStore {
+    async fn load(&self, session_key: &SessionKey) -> Result<Option<SessionState>, LoadError> {
+        match self {
+            AppSessionStore::Cookie(store) => store.load(session_key).await,
+            AppSessionStore::Postgres(store) => store.load(session_key).await
+        }
+    }
+
+    async fn save(&self, session_state: SessionState, ttl: &Duration) -> Result<SessionKey, SaveError> {
+        match self {
+            AppSessionStore::Cookie(store) => store.save(session_state, ttl).await,
+            AppSessionStore::Postgres(store) => store.save(session_state, ttl).await
+        }
+    }
+
+    async fn update(&self, session_key: SessionKey, session_state: SessionState, ttl: &Duration)
+        -> Result<SessionKey, UpdateError>
+    {
+        match self {
+            AppSessionStore::Cookie(store) => store.update(session_key, session_state, ttl).await,
+            AppSessionStore::Postgres(store) => store.update(session_key, session_state, ttl).await
+        }
+    }
+
+    async fn update_ttl(&self, session_key: &SessionKey, ttl: &Duration) -> anyhow::Result<()> {
+        match self {
+            AppSessionStore::Cookie(store) => store.update_ttl(session_key, ttl).await,
+            AppSessionStore::Postgres(store) => store.update_ttl(session_key, ttl).await
+        }
+    }
+
+    async fn delete(&self, session_key: &SessionKey) -> anyhow::Result<()> {
+        match self {
+            AppSessionStore::Cookie(store) => store.delete(session_key).await,
+            AppSessionStore::Postgres(store) => store.delete(session_key).await
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::time::Duration;
+use derive_more::{Display, From};
+use lettre::{Address, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use lettre::address::AddressError;
+use lettre::message::{Mailbox, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::transport::smtp::client::{Tls, TlsParameters};
+use lettre::transport::smtp::extension::ClientId;
+use crate::config::{NotifyConfig, SmtpTls};
+
+#[derive(Debug, Display, From)]
+pub(crate) enum SmtpError {
+    /// Адрес отправителя или получателя не разобрать
+    Address(AddressError),
+    /// Письмо не собрать
+    Message(lettre::error::Error),
+    Smtp(lettre::transport::smtp::Error)
+}
+
+impl SmtpError {
+    /// Повторять бесполезно: плохой адрес или релей ответил 5xx
+    pub(crate) fn is_permanent(&self) -> bool {
+        match self {
+            SmtpError::Address(_) | SmtpError::Message(_) => true,
+            SmtpError::Smtp(e) => e.is_permanent()
+        }
+    }
+}
+
+/// Письмо от `NOTIFY.SMTP_FROM`: только текст или текст вместе с html
+fn build_message(config: &NotifyConfig,
+                 to: &str,
+                 subject: &str,
+                 text: &str,
+                 html: Option<&str>)
+    -> Result<Message, SmtpError>
+{
+    let builder = Message::builder()
+        .from(config.smtp_from.parse::<Mailbox>()?)
+        .to(Mailbox::new(None, to.parse::<Address>()?))
+        .subject(subject);
+    let message = match html {
+        Some(html) => builder.multipart(
+            MultiPart::alternative_plain_html(text.to_string(), html.to_string()))?,
+        None => builder.singlepart(SinglePart::plain(text.to_string()))?
+    };
+    Ok(message)
+}
+
+/// Соединение с релеем по `NOTIFY.SMTP_*`
+fn transport(config: &NotifyConfig) -> Result<AsyncSmtpTransport<Tokio1Executor>, SmtpError> {
+    let tls = match config.smtp_tls {
+        SmtpTls::None => Tls::None,
+        SmtpTls::Starttls => Tls::Required(TlsParameters::new(config.smtp_host.clone())?),
+        SmtpTls::Tls => Tls::Wrapper(TlsParameters::new(config.smtp_host.clone())?)
+    };
+    let mut builder = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.smtp_host)
+        .port(config.smtp_port)
+        .tls(tls)
+        .hello_name(ClientId::Domain(config.smtp_helo.clone()))
+        .timeout(Some(Duration::from_secs(config.smtp_timeout)));
+    if let Some(user) = &config.smtp_user {
+        let password = config.smtp_password.clone().unwrap_or_default();
+        builder = builder.credentials(Credentials::new(user.clone(), password));
+    }
+    Ok(builder.build())
+}
+
+/// Отправляет письмо через релей из `NOTIFY.SMTP_HOST`
+pub(crate) async fn send_mail(config: &NotifyConfig,
+                              to: &str,
+                              subject: &str,
+                              text: &str,
+                              html: Option<&str>)
+    -> Result<(), SmtpError>
+{
+    let message = build_message(config, to, subject, text, html)?;
+    transport(config)?.send(message).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formatted(message: Message) -> String {
+        String::from_utf8(message.formatted()).unwrap()
+    }
+
+    #[test]
+    fn plain_message_has_one_part() {
+        let config = NotifyConfig::default();
+        let message = build_message(&config, "user@example.com", "Hello", "text", None).unwrap();
+        let message = formatted(message);
+        assert!(message.contains("From: noreply@localhost"));
+        assert!(message.contains("To: user@example.com"));
+        assert!(message.contains("Content-Type: text/plain; charset=utf-8"));
+        assert!(!message.contains("multipart"));
+    }
+
+    #[test]
+    fn html_message_is_alternative() {
+        let config = NotifyConfig::default();
+        let message = build_message(&config, "user@example.com", "Hello", "text",
+                                    Some("<p>html</p>")).unwrap();
+        let message = formatted(message);
+        assert!(message.contains("multipart/alternative"));
+        assert!(message.contains("text/plain"));
+        assert!(message.contains("text/html"));
+    }
+
+    #[test]
+    fn non_ascii_subject_is_encoded() {
+        let config = NotifyConfig::default();
+        let message = build_message(&config, "user@example.com", "Сброс пароля", "текст", None).unwrap();
+        let message = formatted(message);
+        let subject = message.lines().find(|line| line.starts_with("Subject:")).unwrap();
+        assert!(subject.is_ascii());
+        assert!(subject.contains("=?utf-8?"));
+    }
+
+    #[test]
+    fn bad_recipient_is_permanent() {
+        let config = NotifyConfig::default();
+        for to in ["not an address", "user@example.com\r\nBcc: other@example.com"] {
+            let error = build_message(&config, to, "Hello", "text", None).unwrap_err();
+            assert!(matches!(error, SmtpError::Address(_)));
+            assert!(error.is_permanent());
+        }
+    }
+}
@@ -1,44 +1,169 @@
 use std::collections::HashMap;
 use actix_session::{Session, SessionGetError};
-use actix_web::HttpRequest;
+use actix_web::{HttpRequest, HttpResponse};
+use actix_web::http::header::{self, ContentType, Header};
+use actix_web::http::StatusCode;
+use chrono::Utc;
 use deadpool_postgres::Client;
 use handlebars::Handlebars;
-use serde_json::json;
+use serde::Serialize;
+use serde_json::{json, Value};
 use crate::{AppState, models};
 use crate::core::ServiceData;
-use crate::models::user::get_user_by_token;
+use crate::core::csrf::with_csrf_token;
+use crate::models::user::{get_user_by_token, PublicUser};
 
-pub(crate) async fn wrap_page(service_data: &ServiceData<'_>,
-                        content: &str,
-                        title: Option<&str>)
-    -> String
-{
-    let requested_with = match service_data.req.headers().get("X-Requested-With") {
+/// Ключ сессии, в котором копятся сообщения до следующей отрисовки страницы
+const FLASH_KEY: &str = "flash";
+
+/// Заголовки запроса, от которых зависит вид страницы
+const PAGE_VARY: &str = "Accept, X-Requested-With";
+
+/// Состояние страницы для навигации без перезагрузки: всё, что при полной
+/// загрузке попало бы в `wrap`
+#[derive(Default, Serialize)]
+pub(crate) struct Fragment {
+    pub(crate) content: Option<String>,
+    pub(crate) page: FragmentPage,
+    pub(crate) user: Option<PublicUser>,
+    pub(crate) header: String,
+    pub(crate) redirect: Option<String>,
+    pub(crate) flash: Vec<String>
+}
+
+#[derive(Default, Serialize)]
+pub(crate) struct FragmentPage {
+    pub(crate) name: String
+}
+
+/// Запрос пришёл от скрипта навигации, а не от браузера
+pub(crate) fn is_fragment_request(req: &HttpRequest) -> bool {
+    let requested_with = match req.headers().get("X-Requested-With") {
         Some(T) => { T.to_str().unwrap_or("") },
         None => { "" }
     };
-    if(requested_with == "XMLHttpRequest") {
-        return content.to_string();
-    }
+    requested_with == "XMLHttpRequest"
+}
 
-    /*let mut handlebars = Handlebars::new();
-    handlebars
-        .register_template_string("wrap", include_str!("../views/wrap.hbs"))
-        .unwrap();*/
-
-    let mut data = json!({ "content": content, "page": { "name": title.unwrap_or_default() } });
+/// Запоминает сообщение, которое покажется на следующей отрисованной странице
+pub(crate) fn push_flash(session: &Session, message: &str) {
+    let mut flash: Vec<String> = session.get(FLASH_KEY).ok().flatten().unwrap_or_default();
+    flash.push(message.to_string());
+    if let Err(e) = session.insert(FLASH_KEY, flash) {
+        log::warn!("failed to store flash message: {e}");
+    }
+}
 
+fn take_flash(session: &Session) -> Vec<String> {
+    session.remove_as::<Vec<String>>(FLASH_KEY)
+        .and_then(|flash| flash.ok())
+        .unwrap_or_default()
+}
 
+async fn current_user(service_data: &ServiceData<'_>) -> Option<PublicUser> {
     if let Ok(option) = service_data.session.get("token") {
         let option : Option<String> = option;
         if let Some(token) = option {
-            if let Ok(user) = get_user_by_token(&service_data.client, token.as_str()).await {
-                data["user"] = json!(user);
+            if let Ok(user) = get_user_by_token(&service_data.client, token.as_str(),
+                                                Utc::now().timestamp()).await {
+                return Some(PublicUser::from(user));
             }
         }
     };
+    None
+}
+
+/// Собирает состояние страницы вокруг отрисованного `content`
+pub(crate) async fn fragment(service_data: &ServiceData<'_>,
+                             content: &str,
+                             title: Option<&str>)
+    -> Fragment
+{
+    let user = current_user(service_data).await;
+    let header = service_data.app_state.handlebars
+        .render("partials/user_nav", &json!({ "user": user }))
+        .unwrap_or_default();
+    Fragment {
+        content: Some(content.to_string()),
+        page: FragmentPage { name: title.unwrap_or_default().to_string() },
+        user,
+        header,
+        redirect: None,
+        flash: take_flash(&service_data.session)
+    }
+}
+
+pub(crate) async fn wrap_page(service_data: &ServiceData<'_>,
+                        content: &str,
+                        title: Option<&str>)
+    -> String
+{
+    /*let mut handlebars = Handlebars::new();
+    handlebars
+        .register_template_string("wrap", include_str!("../views/wrap.hbs"))
+        .unwrap();*/
+
+    let data = json!(fragment(service_data, content, title).await);
 
     let wrap = service_data.app_state.handlebars.render("wrap", &data).unwrap();
 
     return wrap;
-}
\ No newline at end of file
+}
+
+/// Перенаправляет на `location`. Скрипту навигации вместо `302` отдаётся
+/// фрагмент с адресом: `fetch` молча проходит редиректы, и скрипт не узнал бы,
+/// куда попал.
+pub(crate) fn redirect(req: &HttpRequest, location: &str) -> HttpResponse {
+    if is_fragment_request(req) {
+        return HttpResponse::Ok().json(Fragment {
+            redirect: Some(location.to_string()),
+            ..Fragment::default()
+        });
+    }
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, location))
+        .finish()
+}
+
+/// Просит ли клиент JSON вместо html: `application/json` в `Accept`
+/// стоит выше любого html-типа
+pub(crate) fn wants_json(req: &HttpRequest) -> bool {
+    let Ok(accept) = header::Accept::parse(req) else {
+        return false;
+    };
+    accept.ranked().into_iter()
+        .find(|mime| mime.subtype() == "json" || mime.subtype() == "html")
+        .is_some_and(|mime| mime.subtype() == "json")
+}
+
+/// Отдаёт страницу по данным `data`: клиенту, просящему JSON, -- сами данные,
+/// скрипту навигации -- фрагмент, остальным -- шаблон `template` в обёртке `wrap_page`.
+/// Ответ зависит от `Accept` и `X-Requested-With`, о чём сказано кэшам в `Vary`
+pub(crate) async fn render_page(service_data: &ServiceData<'_>,
+                                status: StatusCode,
+                                template: &str,
+                                data: &Value,
+                                title: Option<&str>)
+    -> HttpResponse
+{
+    if wants_json(&service_data.req) {
+        return HttpResponse::build(status)
+            .insert_header((header::VARY, PAGE_VARY))
+            .json(data);
+    }
+
+    let data = with_csrf_token(&service_data.session, data);
+    let content = service_data.app_state.handlebars
+        .render(template, &data)
+        .unwrap_or_default();
+    if is_fragment_request(&service_data.req) {
+        return HttpResponse::build(status)
+            .insert_header((header::VARY, PAGE_VARY))
+            .json(fragment(service_data, &content, title).await);
+    }
+    let wrap = wrap_page(service_data, &content, title).await;
+    HttpResponse::build(status)
+        .content_type(ContentType::html())
+        .insert_header((header::VARY, PAGE_VARY))
+        .body(wrap)
+}
@@ -0,0 +1,166 @@
+use deadpool_postgres::Client;
+use crate::config::LoginConfig;
+use crate::core::errors::DbError;
+use crate::models::login_attempt::{release_login_attempt, remove_login_attempts,
+                                   reserve_login_attempt, set_login_lock};
+
+/// Чьи попытки считаем
+#[derive(Clone, Copy)]
+pub(crate) enum AttemptKind {
+    Ip,
+    Login,
+    /// Запросы ссылки для сброса пароля с одного адреса
+    Reset
+}
+
+impl AttemptKind {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            AttemptKind::Ip => "ip",
+            AttemptKind::Login => "login",
+            AttemptKind::Reset => "reset"
+        }
+    }
+}
+
+/// Логины сравниваются без учёта регистра и пробелов по краям
+pub(crate) fn login_subject(login: &str) -> String {
+    login.trim().to_lowercase()
+}
+
+/// На сколько секунд закрыть вход после `failures` неудач подряд: первые
+/// `free` даром, дальше задержка удваивается до `max_delay`, а после `lockout_after`
+/// вход закрывается на `lockout_duration`
+fn lock_duration(config: &LoginConfig, failures: i32, free: i32, lockout_after: i32) -> i64 {
+    if failures >= lockout_after {
+        return config.lockout_duration;
+    }
+    if failures <= free {
+        return 0;
+    }
+    let doublings = (failures - free - 1).min(32) as u32;
+    config.base_delay.saturating_mul(1i64 << doublings).min(config.max_delay)
+}
+
+/// Засчитывает попытку входа адресу и логину, существует он или нет, ещё до
+/// проверки пароля и возвращает, сколько секунд ждать; 0 -- попытка засчитана
+/// и пароль можно проверять. Счёт и новая блокировка пишутся в одной транзакции:
+/// параллельные попытки ждут её на строках счётчиков и видят уже закрытый вход.
+pub(crate) async fn reserve_login(client: &mut Client,
+                                  config: &LoginConfig,
+                                  ip: &str,
+                                  login: &str,
+                                  now: i64)
+    -> Result<i64, DbError>
+{
+    let login = login_subject(login);
+    let subjects = [
+        (AttemptKind::Ip, ip, config.ip_free_attempts, config.ip_lockout_after),
+        (AttemptKind::Login, login.as_str(), config.free_attempts, config.lockout_after)
+    ];
+    let transaction = client.transaction().await?;
+    let mut reserved = Vec::new();
+    let mut wait = 0;
+    for (kind, subject, free, lockout_after) in subjects {
+        let attempts = reserve_login_attempt(&transaction, kind.as_str(), subject, now, config.window).await?;
+        if attempts.locked_until > now {
+            wait = wait.max(attempts.locked_until - now);
+        } else {
+            reserved.push((kind, subject, attempts.failures, free, lockout_after));
+        }
+    }
+    if wait > 0 {
+        // попытки не будет: транзакция откатится и засчитанное вернёт
+        return Ok(wait);
+    }
+
+    for (kind, subject, failures, free, lockout_after) in reserved {
+        let duration = lock_duration(config, failures, free, lockout_after);
+        if duration > 0 {
+            if failures >= lockout_after {
+                log::warn!("login locked for {} {subject} after {failures} failures", kind.as_str());
+            }
+            set_login_lock(&transaction, kind.as_str(), subject, now + duration).await?;
+        }
+    }
+    transaction.commit().await?;
+    Ok(0)
+}
+
+/// Вход удался: адресу возвращается засчитанная `reserve_login` попытка,
+/// а с логина снимаются ограничения
+pub(crate) async fn accept_login(client: &Client, ip: &str, login: &str) -> Result<(), DbError> {
+    release_login_attempt(client, AttemptKind::Ip.as_str(), ip).await?;
+    clear_login_failures(client, login).await
+}
+
+/// Снимает ограничения с логина: после удачного входа или по просьбе администратора.
+/// Счётчик адреса не трогаем, иначе вход в свой аккаунт обнулял бы перебор чужих.
+pub(crate) async fn clear_login_failures(client: &Client, login: &str) -> Result<(), DbError> {
+    remove_login_attempts(client, AttemptKind::Login.as_str(), &login_subject(login)).await
+}
+
+/// Засчитывает запрос ссылки для сброса пароля с адреса `ip`.
+/// `false`, если за `window` их было больше `reset_ip_limit`.
+pub(crate) async fn allow_reset_request(client: &Client, config: &LoginConfig, ip: &str, now: i64)
+    -> Result<bool, DbError>
+{
+    let attempts = reserve_login_attempt(client, AttemptKind::Reset.as_str(), ip, now, config.window).await?;
+    Ok(attempts.failures <= config.reset_ip_limit)
+}
+
+/// Ожидание по-человечески: «40 с», «3 мин»
+pub(crate) fn format_wait(seconds: i64) -> String {
+    if seconds < 60 {
+        format!("{} с", seconds.max(1))
+    } else {
+        format!("{} мин", (seconds + 59) / 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> LoginConfig {
+        LoginConfig {
+            free_attempts: 3,
+            lockout_after: 10,
+            base_delay: 2,
+            max_delay: 60,
+            lockout_duration: 900,
+            ..LoginConfig::default()
+        }
+    }
+
+    #[test]
+    fn free_attempts_cost_nothing() {
+        let config = config();
+        for failures in 0..=3 {
+            assert_eq!(lock_duration(&config, failures, 3, 10), 0);
+        }
+    }
+
+    #[test]
+    fn delay_doubles_after_free_attempts() {
+        let config = config();
+        let delays: Vec<i64> = (4..=8).map(|failures| lock_duration(&config, failures, 3, 10)).collect();
+        assert_eq!(delays, [2, 4, 8, 16, 32]);
+    }
+
+    #[test]
+    fn delay_stops_at_max_delay() {
+        let config = config();
+        assert_eq!(lock_duration(&config, 9, 3, 10), 60);
+        // и не переполняется, сколько бы неудач ни набралось
+        assert_eq!(lock_duration(&config, 1000, 3, i32::MAX), 60);
+    }
+
+    #[test]
+    fn lockout_starts_at_lockout_after() {
+        let config = config();
+        assert_eq!(lock_duration(&config, 9, 3, 10), 60);
+        assert_eq!(lock_duration(&config, 10, 3, 10), 900);
+        assert_eq!(lock_duration(&config, 11, 3, 10), 900);
+    }
+}
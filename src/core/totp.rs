@@ -0,0 +1,173 @@
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha1::Sha1;
+
+/// Длина шага, в секундах, и число цифр кода: так умеют все приложения-аутентификаторы
+const STEP: i64 = 30;
+const DIGITS: u32 = 6;
+
+/// Сколько шагов в каждую сторону прощаем расхождению часов
+const SKEW: i64 = 1;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Base32 без `=` в конце, как его ждут в `otpauth://`
+pub(crate) fn base32_encode(bytes: &[u8]) -> String {
+    let mut result = String::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            result.push(BASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        result.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    result
+}
+
+/// Разбирает base32 без учёта регистра, пробелов и `=`
+pub(crate) fn base32_decode(text: &str) -> Option<Vec<u8>> {
+    let mut result = Vec::new();
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in text.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET.iter().position(|&a| a as char == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((buffer >> bits) as u8);
+        }
+    }
+    Some(result)
+}
+
+/// Новый секрет в base32: 160 бит, как советует RFC 4226
+pub(crate) fn generate_secret() -> String {
+    base32_encode(&rand::thread_rng().gen::<[u8; 20]>())
+}
+
+/// Номер шага на момент `now`
+fn step_at(now: i64) -> i64 {
+    now.div_euclid(STEP)
+}
+
+/// Код для шага `step` по RFC 6238 (HMAC-SHA1)
+fn code_at(key: &[u8], step: i64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("hmac accepts any key length");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]]);
+    (value & 0x7fff_ffff) % 10u32.pow(DIGITS)
+}
+
+/// Проверяет код `code` для секрета `secret`. Возвращает шаг, которым он подошёл;
+/// шаги не позже `last_step` уже использованы, и повторно тот же код не примется.
+pub(crate) fn verify(secret: &str, code: &str, now: i64, last_step: Option<i64>) -> Option<i64> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let key = base32_decode(secret)?;
+    let current = step_at(now);
+    (current - SKEW..=current + SKEW)
+        .filter(|step| last_step.is_none_or(|last| *step > last))
+        .find(|step| code_at(&key, *step) == code)
+}
+
+/// Ссылка для приложения-аутентификатора; её и кодирует QR-код
+pub(crate) fn otpauth_uri(issuer: &str, login: &str, secret: &str) -> String {
+    let escape = |value: &str| -> String {
+        value.bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+                _ => format!("%{b:02X}")
+            })
+            .collect()
+    };
+    format!("otpauth://totp/{issuer}:{login}?secret={secret}&issuer={issuer}&digits={DIGITS}&period={STEP}",
+            issuer = escape(issuer), login = escape(login))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ключ из тестовых векторов RFC 6238 для SHA-1
+    const RFC_KEY: &[u8] = b"12345678901234567890";
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn base32_matches_rfc_4648() {
+        let vectors = [("", ""), ("f", "MY"), ("fo", "MZXQ"), ("foo", "MZXW6"), ("foob", "MZXW6YQ"),
+                       ("fooba", "MZXW6YTB"), ("foobar", "MZXW6YTBOI")];
+        for (plain, encoded) in vectors {
+            assert_eq!(base32_encode(plain.as_bytes()), encoded);
+            assert_eq!(base32_decode(encoded).as_deref(), Some(plain.as_bytes()));
+        }
+        assert_eq!(base32_encode(RFC_KEY), RFC_SECRET);
+    }
+
+    #[test]
+    fn base32_decode_is_lenient_but_checks_alphabet() {
+        assert_eq!(base32_decode("mzxw 6ytb oi======").as_deref(), Some(&b"foobar"[..]));
+        assert_eq!(base32_decode("MZXW1"), None);
+        assert_eq!(base32_decode("MZXW8"), None);
+    }
+
+    #[test]
+    fn base32_round_trip() {
+        for _ in 0..100 {
+            let bytes: Vec<u8> = (0..rand::thread_rng().gen_range(0..40)).map(|_| rand::random()).collect();
+            assert_eq!(base32_decode(&base32_encode(&bytes)), Some(bytes));
+        }
+        let secret = generate_secret();
+        assert_eq!(secret.len(), 32);
+        assert_eq!(base32_decode(&secret).map(|key| key.len()), Some(20));
+    }
+
+    #[test]
+    fn codes_match_rfc_6238() {
+        // в RFC коды из 8 цифр, у нас последние 6 из них
+        let vectors = [(59, 94287082), (1111111109, 7081804), (1111111111, 14050471),
+                       (1234567890, 89005924), (2000000000, 69279037), (20000000000, 65353130)];
+        for (time, code) in vectors {
+            assert_eq!(code_at(RFC_KEY, step_at(time)), code % 1_000_000, "time {time}");
+        }
+    }
+
+    #[test]
+    fn verify_accepts_neighbouring_steps_once() {
+        let step = step_at(1111111111);
+        let code = "050471";
+        assert_eq!(verify(RFC_SECRET, code, 1111111111, None), Some(step));
+        assert_eq!(verify(RFC_SECRET, "050 471", 1111111111, None), Some(step));
+        // часы разошлись на шаг в любую сторону
+        assert_eq!(verify(RFC_SECRET, code, 1111111111 + STEP, None), Some(step));
+        assert_eq!(verify(RFC_SECRET, code, 1111111111 - STEP, None), Some(step));
+        assert_eq!(verify(RFC_SECRET, code, 1111111111 + 2 * STEP, None), None);
+        // повторно тот же код не проходит
+        assert_eq!(verify(RFC_SECRET, code, 1111111111, Some(step)), None);
+        assert_eq!(verify(RFC_SECRET, code, 1111111111, Some(step - 1)), Some(step));
+    }
+
+    #[test]
+    fn verify_rejects_malformed_codes() {
+        for code in ["", "50471", "0504710", "05047a", "-50471"] {
+            assert_eq!(verify(RFC_SECRET, code, 1111111111, None), None, "{code:?}");
+        }
+        assert_eq!(verify("not base32!", "050471", 1111111111, None), None);
+    }
+
+    #[test]
+    fn otpauth_uri_escapes_labels() {
+        assert_eq!(otpauth_uri("Клуб", "a b", "ABC"),
+                   "otpauth://totp/%D0%9A%D0%BB%D1%83%D0%B1:a%20b?secret=ABC&issuer=%D0%9A%D0%BB%D1%83%D0%B1\
+                    &digits=6&period=30");
+    }
+}
@@ -1,24 +1,127 @@
 use std::hash::{BuildHasher, Hasher};
 use actix_session::{Session, SessionGetError};
-use deadpool_postgres::Client;
+use actix_web::{rt, web};
+use chrono::Utc;
+use deadpool_postgres::{Client, Pool};
+use argon2::{Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::SaltString;
 use rs_sha512::{HasherContext, Sha512State};
-use crate::core::errors::DbError;
-use crate::models::user::{get_user_by_token, User};
+use crate::core::errors::{AuthError, DbError};
+use crate::models::session::{remove_expired_sessions, touch_session};
+use crate::models::session_store::remove_expired_session_states;
+use crate::models::login_attempt::remove_stale_login_attempts;
+use crate::core::totp;
+use crate::models::recovery_code::{generate_recovery_code, get_unused_recovery_codes, normalize_recovery_code,
+                                   use_recovery_code, RECOVERY_CODES_COUNT};
+use crate::models::roles::get_totp_required_roles;
+use crate::models::user::{get_user_by_token, set_totp_last_step, User};
 
-enum GetCurrentUserError {
+pub(crate) enum GetCurrentUserError {
     SessionGet(SessionGetError), Db(DbError), SessionIsNotString
 }
 
-pub(crate) fn hash_password(password:&str, login: &str) -> String {
+/// Результат проверки пароля
+#[derive(PartialEq)]
+pub(crate) enum PasswordCheck {
+    Invalid,
+    Valid,
+    /// Пароль верный, но хеш устарел и его стоит пересчитать
+    ValidNeedsRehash
+}
+
+/// Хеш Argon2id со случайной солью в формате PHC (`$argon2id$v=19$...`).
+/// Argon2 нарочно медленный, поэтому считается в пуле блокирующих потоков,
+/// а не на воркере, который обслуживает запросы.
+pub(crate) async fn hash_password(password: &str) -> Result<String, AuthError> {
+    let password = password.to_string();
+    match web::block(move || argon2_hash(&password)).await {
+        Ok(Ok(hash)) => Ok(hash),
+        Ok(Err(e)) => {
+            log::error!("failed to hash password: {e}");
+            Err(AuthError::PasswordNotHashed)
+        },
+        Err(e) => {
+            log::error!("failed to hash password: {e}");
+            Err(AuthError::PasswordNotHashed)
+        }
+    }
+}
+
+fn argon2_hash(password: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Ok(Argon2::default().hash_password(password.as_bytes(), &salt)?.to_string())
+}
+
+/// Старый хеш: SHA-512 с логином и постоянной строкой вместо соли.
+/// Нужен только для проверки ещё не пересчитанных паролей.
+fn legacy_hash_password(password: &str, login: &str) -> String {
     let mut sha512hasher = Sha512State::default().build_hasher();
     sha512hasher.write(password.as_bytes());
     sha512hasher.write(format!("СВО{}aboba_AntiHohol",
-                               login.clone()).as_bytes());
+                               login).as_bytes());
     let bytes_result = HasherContext::finish(&mut sha512hasher);
-    return format!("{bytes_result:02x}");
+    format!("{bytes_result:02x}")
+}
+
+/// Сравнение за время, не зависящее от того, где строки расходятся
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Хеш, с которым сверяется пароль, когда логина нет: без него по времени
+/// ответа было бы видно, существует ли пользователь
+const DUMMY_PASSWORD_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$6+LGXLGQZYlUSz/r+UMh6Q$J5Ij5icG2pPcsBvNA6OpytRo6OI1hnbaN1bgu2xf9eg";
+
+/// Тратит на проверку пароля столько же времени, сколько настоящая проверка
+pub(crate) async fn verify_dummy_password(password: &str) {
+    let password = password.to_string();
+    let _ = web::block(move || check_password(DUMMY_PASSWORD_HASH, "", &password)).await;
 }
 
+/// Проверяет пароль пользователя по хешу любого поколения.
+/// Как и `hash_password`, считается в пуле блокирующих потоков.
+pub(crate) async fn verify_password(user: &User, password: &str) -> PasswordCheck {
+    let Some(stored) = user.password_hash.clone() else {
+        return PasswordCheck::Invalid;
+    };
+    let login = user.login.clone().unwrap_or_default();
+    let password = password.to_string();
+    web::block(move || check_password(&stored, &login, &password))
+        .await
+        .unwrap_or_else(|e| {
+            log::error!("failed to verify password: {e}");
+            PasswordCheck::Invalid
+        })
+}
 
+fn check_password(stored: &str, login: &str, password: &str) -> PasswordCheck {
+    match PasswordHash::new(stored) {
+        Ok(hash) => {
+            if Argon2::default().verify_password(password.as_bytes(), &hash).is_err() {
+                return PasswordCheck::Invalid;
+            }
+            // хеш с прежними параметрами тоже пересчитываем; длину вывода не сравниваем:
+            // в разобранном хеше она есть всегда, а в параметрах по умолчанию -- нет
+            let default = Params::default();
+            let current = hash.algorithm == argon2::ARGON2ID_IDENT
+                && Params::try_from(&hash).is_ok_and(|params| {
+                    (params.m_cost(), params.t_cost(), params.p_cost())
+                        == (default.m_cost(), default.t_cost(), default.p_cost())
+                });
+            if current { PasswordCheck::Valid } else { PasswordCheck::ValidNeedsRehash }
+        },
+        Err(_) => {
+            let legacy = legacy_hash_password(password, login);
+            if constant_time_eq(legacy.as_bytes(), stored.as_bytes()) {
+                PasswordCheck::ValidNeedsRehash
+            } else {
+                PasswordCheck::Invalid
+            }
+        }
+    }
+}
 
 pub async fn get_current_user(client: &Client,
                               session: Session)
@@ -32,12 +135,158 @@ pub async fn get_current_user(client: &Client,
         Err(error) => return Err(GetCurrentUserError::SessionGet(error))
     };
 
-    match get_user_by_token(&client, token.as_str()).await {
-        Ok(user) => Ok(user),
+    let now = Utc::now().timestamp();
+    match get_user_by_token(client, token.as_str(), now).await {
+        Ok(user) => {
+            if let Err(e) = touch_session(client, token.as_str(), now).await {
+                log::warn!("failed to touch session: {e}");
+            }
+            Ok(user)
+        },
         Err(error) => Err(GetCurrentUserError::Db(error))
     }
 }
 
+/// Чем пользователь подтвердил вход
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum SecondFactor {
+    Totp,
+    RecoveryCode
+}
+
+impl SecondFactor {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            SecondFactor::Totp => "totp",
+            SecondFactor::RecoveryCode => "recovery_code"
+        }
+    }
+}
+
+/// Проверяет код из приложения или, если он не подошёл, код восстановления.
+/// Подошедший код тратится: второй раз его не примут.
+pub(crate) async fn check_second_factor(client: &Client, user: &User, code: &str, now: i64)
+    -> Result<Option<SecondFactor>, DbError>
+{
+    let user_id = user.id.unwrap_or_default();
+    let secret = user.totp_secret.as_deref().unwrap_or_default();
+    if let Some(step) = totp::verify(secret, code, now, user.totp_last_step) {
+        // шаг запоминаем условно: из двух одновременных входов одним кодом пройдёт один
+        return match set_totp_last_step(client, user_id, step).await {
+            Ok(_) => Ok(Some(SecondFactor::Totp)),
+            Err(DbError::NotFound) => Ok(None),
+            Err(e) => Err(e)
+        };
+    }
+    let code = normalize_recovery_code(code);
+    if code.is_empty() {
+        return Ok(None);
+    }
+    let codes = get_unused_recovery_codes(client, user_id).await?;
+    // соль у каждого хеша своя, так что код ищем, сверяя его со всеми
+    let found = web::block(move || codes.into_iter()
+        .find(|recovery| check_password(&recovery.code_hash, "", &code) != PasswordCheck::Invalid)
+        .map(|recovery| recovery.id))
+        .await
+        .unwrap_or_else(|e| {
+            log::error!("failed to verify recovery code: {e}");
+            None
+        });
+    let Some(id) = found else {
+        return Ok(None);
+    };
+    match use_recovery_code(client, id, now).await {
+        Ok(()) => Ok(Some(SecondFactor::RecoveryCode)),
+        Err(DbError::NotFound) => Ok(None),
+        Err(e) => Err(e)
+    }
+}
+
+/// Новые коды восстановления: сами коды, чтобы один раз показать их
+/// пользователю, и их хеши для базы
+pub(crate) async fn generate_recovery_codes() -> Result<(Vec<String>, Vec<String>), AuthError> {
+    let codes: Vec<String> = (0..RECOVERY_CODES_COUNT).map(|_| generate_recovery_code()).collect();
+    let mut hashes = Vec::with_capacity(codes.len());
+    for code in &codes {
+        hashes.push(hash_password(code).await?);
+    }
+    Ok((codes, hashes))
+}
+
+/// Нужно ли пользователю сначала включить второй фактор: его роли
+/// администратор это потребовал, а пользователь ещё не включил
+pub(crate) async fn needs_totp(client: &Client, user: &User) -> Result<bool, DbError> {
+    if user.has_totp() {
+        return Ok(false);
+    }
+    Ok(get_totp_required_roles(client).await?.contains(&user.role()))
+}
+
 pub async fn is_authored(client: &Client, session: Session) -> bool {
     return get_current_user(&client, session).await.is_ok();
-}
\ No newline at end of file
+}
+
+/// Время от времени вычищает закончившиеся сессии и устаревшие счётчики попыток входа
+pub(crate) async fn sweep_sessions(pool: Pool, every: std::time::Duration, attempt_window: i64) {
+    loop {
+        rt::time::sleep(every).await;
+        let client = match pool.get().await {
+            Ok(client) => client,
+            Err(e) => {
+                log::warn!("session sweep: no database connection: {e}");
+                continue;
+            }
+        };
+        match remove_expired_sessions(&client, Utc::now().timestamp()).await {
+            Ok(0) => {},
+            Ok(removed) => log::info!("session sweep: removed {removed} expired sessions"),
+            Err(e) => log::warn!("session sweep failed: {e}")
+        }
+        match remove_expired_session_states(&client, Utc::now().timestamp()).await {
+            Ok(0) => {},
+            Ok(removed) => log::info!("session sweep: removed {removed} expired session states"),
+            Err(e) => log::warn!("session state sweep failed: {e}")
+        }
+        if let Err(e) = remove_stale_login_attempts(&client, Utc::now().timestamp(), attempt_window).await {
+            log::warn!("login attempts sweep failed: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn argon2_hash_is_current() {
+        let hash = argon2_hash("пароль").unwrap();
+        assert!(hash.starts_with("$argon2id$v=19$"));
+        assert!(check_password(&hash, "tester", "пароль") == PasswordCheck::Valid);
+    }
+
+    #[test]
+    fn wrong_password_is_invalid() {
+        let hash = argon2_hash("пароль").unwrap();
+        assert!(check_password(&hash, "tester", "Пароль") == PasswordCheck::Invalid);
+        let legacy = legacy_hash_password("пароль", "tester");
+        assert!(check_password(&legacy, "tester", "Пароль") == PasswordCheck::Invalid);
+        // соль старого хеша -- логин, так что с чужим логином пароль не подходит
+        assert!(check_password(&legacy, "other", "пароль") == PasswordCheck::Invalid);
+    }
+
+    #[test]
+    fn legacy_hash_needs_rehash() {
+        let legacy = legacy_hash_password("пароль", "tester");
+        assert!(check_password(&legacy, "tester", "пароль") == PasswordCheck::ValidNeedsRehash);
+    }
+
+    #[test]
+    fn weaker_argon2_needs_rehash() {
+        let params = Params::new(8 * 1024, 1, 1, None).unwrap();
+        let argon2 = Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = argon2.hash_password("пароль".as_bytes(), &salt).unwrap().to_string();
+        assert!(check_password(&hash, "tester", "пароль") == PasswordCheck::ValidNeedsRehash);
+        assert!(check_password(&hash, "tester", "Пароль") == PasswordCheck::Invalid);
+    }
+}
@@ -7,7 +7,7 @@ mod core;
 use std::{io, sync::Arc};
 
 use actix_files::{Files, NamedFile};
-use actix_session::{SessionMiddleware, storage::CookieSessionStore};
+use actix_session::SessionMiddleware;
 use actix_web::{
     App, Either,
     error,
@@ -17,21 +17,28 @@ use actix_web::{
 };
 use ::config::Config;
 use actix_session::config::CookieContentSecurity;
-use actix_session::config::SessionLifecycle::BrowserSession;
+use actix_session::config::BrowserSession;
 use actix_web::cookie::{Key, SameSite};
+use actix_web_lab::middleware::from_fn;
 use deadpool_postgres::Pool;
 use dotenv::dotenv;
 use handlebars::{DirectorySourceOptions, Handlebars};
 use tokio_postgres::NoTls;
-use crate::config::ServerConfig;
-
-// NOTE: Not a suitable session key for production.
-static SESSION_SIGNING_KEY: &[u8] = &[0; 64];
+use crate::config::{LoginConfig, NotifyConfig, RegistrationConfig, ServerConfig, SessionConfig};
+use crate::core::events::SeatEvents;
+use crate::core::keys::{SessionKeys, SESSION_COOKIE};
+use crate::core::session_store::AppSessionStore;
 
 #[derive(Clone)]
 struct AppState<'a> {
     handlebars: Arc<Handlebars<'a>>,
-    db_pool: Pool
+    db_pool: Pool,
+    seat_events: SeatEvents,
+    sessions: SessionConfig,
+    session_keys: SessionKeys,
+    login: LoginConfig,
+    notify: NotifyConfig,
+    registration: RegistrationConfig
 }
 
 /// favicon handler
@@ -40,15 +47,20 @@ async fn favicon() -> Result<impl Responder> {
     Ok(NamedFile::open("static/favicon.ico")?)
 }
 
-fn session_middleware() -> SessionMiddleware<CookieSessionStore> {
+/// Печенька живёт до закрытия браузера; запомненным входам срок добавляет
+/// `remember_session_cookie`. `state_ttl` -- сколько хранилище держит данные сессии,
+/// а когда на самом деле закончится вход, решает срок сессии в базе.
+fn session_middleware(store: AppSessionStore, key: Key, state_ttl: i64) -> SessionMiddleware<AppSessionStore> {
     SessionMiddleware::builder(
-        CookieSessionStore::default(), Key::from(&[0; 64])
+        store, key
     )
+        .cookie_name(SESSION_COOKIE.into())
         .cookie_secure(false) // https и http
-        //.session_lifecycle(BrowserSession::default()) // expire at end of session
+        .session_lifecycle(BrowserSession::default() // expire at end of session
+            .state_ttl(actix_web::cookie::time::Duration::seconds(state_ttl)))
         .cookie_same_site(SameSite::Strict)
         .cookie_content_security(CookieContentSecurity::Private) // encrypt
-        .cookie_http_only(false) // не отключать чтение скриптами
+        .cookie_http_only(true) // скриптам печенька не нужна, токен csrf они берут из формы
         .build()
 }
 
@@ -69,9 +81,6 @@ async fn default_handler(req_method: Method) -> Result<impl Responder> {
 async fn main() -> io::Result<()> {
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
-    // random key means that restarting server will invalidate existing session cookies
-    let key = actix_web::cookie::Key::from(SESSION_SIGNING_KEY);
-
     dotenv().ok();
 
     let config_ = Config::builder()
@@ -80,8 +89,19 @@ async fn main() -> io::Result<()> {
         .unwrap();
 
     let config: ServerConfig = config_.try_deserialize().unwrap();
+    let session_keys = core::keys::load_session_keys(&config.sessions, config.dev)?;
 
     let pool = config.pg.create_pool(None, NoTls).unwrap();
+    let seat_events = SeatEvents::new();
+    // слушатель живёт на отдельном соединении, пул для этого не годится
+    actix_web::rt::spawn(core::events::listen(config.pg.get_pg_config().unwrap(),
+                                              seat_events.clone()));
+    actix_web::rt::spawn(core::events::watch_schedule(pool.clone(), seat_events.clone()));
+    actix_web::rt::spawn(core::users::sweep_sessions(
+        pool.clone(), std::time::Duration::from_secs(config.sessions.sweep_interval),
+        config.login.window));
+
+    actix_web::rt::spawn(core::mail::deliver_outbox(pool.clone(), config.notify.clone()));
 
     log::info!("starting HTTP server at http://localhost:8080");
 
@@ -89,11 +109,18 @@ async fn main() -> io::Result<()> {
     handlebars
         .register_templates_directory("views", DirectorySourceOptions::default())
         .unwrap();
+    handlebars.register_helper("csrf_field", Box::new(core::csrf::csrf_field));
 
 
     let state = AppState {
         handlebars: Arc::new(handlebars),
-        db_pool: pool
+        db_pool: pool,
+        seat_events,
+        sessions: config.sessions,
+        session_keys,
+        login: config.login,
+        notify: config.notify,
+        registration: config.registration
     };
 
     HttpServer::new(move || {
@@ -101,10 +128,21 @@ async fn main() -> io::Result<()> {
             .app_data(web::Data::new(state.clone()))
             // enable automatic response compression - usually register this first
             .wrap(middleware::Compress::default())
+            // токен csrf лежит в сессии, поэтому проверка внутри неё
+            .wrap(from_fn(core::csrf::verify_csrf))
+            // флаг «запомнить меня» читается из сессии, пока она ещё не сохранена
+            .wrap(from_fn(core::remember::mark_remembered))
             // cookie session middleware
-            .wrap(session_middleware())
+            .wrap(session_middleware(AppSessionStore::new(state.sessions.store, &state.db_pool),
+                                     state.session_keys.current.clone(),
+                                     state.sessions.remember_timeout))
+            // срок печеньки запомненного входа
+            .wrap(from_fn(core::remember::remember_session_cookie))
+            // печеньки со старым ключом перешифровываются до сессии
+            .wrap(from_fn(core::keys::rotate_session_cookie))
             // enable logger - always register Actix Web Logger middleware last
-            .wrap(middleware::Logger::default())
+            // токен сброса пароля в адресе не должен оседать в логах
+            .wrap(middleware::Logger::default().exclude_regex("^/password/reset/"))
             // register favicon
             .service(favicon)
             // with path parameters
@@ -137,13 +175,65 @@ async fn main() -> io::Result<()> {
             )*/
             .service(services::booking::index)
             .service(services::booking::seat_page)
+            .service(services::booking::seat_favourite)
+            .service(services::booking::seat_check_in)
+            .service(services::booking::search_page)
+            .service(services::booking::book_next_to_get)
+            .service(services::booking::book_next_to_post)
+            .service(services::booking::book_usual_get)
+            .service(services::booking::book_usual_post)
             .service(services::static_pages::about)
+            .service(services::live::seat_events)
+            .service(services::api::scope())
+            .service(services::api::docs())
+            .service(services::tokens::tokens_get)
+            .service(services::tokens::tokens_post)
+            .service(services::tokens::token_revoke)
+            .service(services::admin::admin_index)
+            .service(services::admin::admin_users)
+            .service(services::admin::admin_user_role)
+            .service(services::admin::admin_user_ban)
+            .service(services::admin::admin_user_unlock)
+            .service(services::admin::admin_user_password)
+            .service(services::admin::admin_user_two_factor_reset)
+            .service(services::admin::admin_role_two_factor)
+            .service(services::admin::admin_registrations)
+            .service(services::admin::admin_registration_approve)
+            .service(services::admin::admin_registration_reject)
+            .service(services::admin::admin_seats)
+            .service(services::admin::admin_seat_add)
+            .service(services::admin::admin_seat_edit)
+            .service(services::admin::admin_seat_delete)
+            .service(services::admin::admin_bookings)
+            .service(services::admin::admin_booking_cancel)
+            .service(services::admin::admin_booking_reassign)
+            .service(services::admin::admin_audit)
+            .service(services::admin::admin_audit_purge)
+            .service(services::admin::admin_invites)
+            .service(services::admin::admin_invite_add)
+            .service(services::admin::admin_invite_revoke)
             .service(services::users::users)
             .service(services::users::register_get)
             .service(services::users::register_post)
             .service(services::users::login_get)
             .service(services::users::login_post)
+            .service(services::users::login_two_factor_get)
+            .service(services::users::login_two_factor_post)
             .service(services::users::logout)
+            .service(services::users::sessions_get)
+            .service(services::users::session_revoke)
+            .service(services::users::sessions_revoke_all)
+            .service(services::account::account_get)
+            .service(services::account::account_email)
+            .service(services::account::account_password)
+            .service(services::account::two_factor_get)
+            .service(services::account::two_factor_enable)
+            .service(services::account::two_factor_disable)
+            .service(services::account::two_factor_recovery)
+            .service(services::account::password_forgot_get)
+            .service(services::account::password_forgot_post)
+            .service(services::account::password_reset_get)
+            .service(services::account::password_reset_post)
             // default
             .default_service(web::to(default_handler))
             .wrap(middleware::NormalizePath::trim())
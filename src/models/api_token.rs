@@ -0,0 +1,190 @@
+use std::hash::{BuildHasher, Hasher};
+use std::str::FromStr;
+use deadpool_postgres::Client;
+use rand::Rng;
+use rs_sha512::{HasherContext, Sha512State};
+use serde::{Deserialize, Serialize};
+use tokio_pg_mapper::FromTokioPostgresRow;
+use tokio_pg_mapper::tokio_pg_mapper_derive::PostgresMapper;
+use tokio_postgres::types::ToSql;
+use crate::core::errors::DbError;
+use crate::models::roles::Permission;
+
+/// Префикс, по которому токен легко узнать в логах и конфигах
+const TOKEN_PREFIX: &str = "dsc_";
+
+/// Что разрешено делать с токеном. Каждый следующий уровень включает предыдущие.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenScope {
+    Read,
+    Book,
+    Admin
+}
+
+impl TokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::Read => "read",
+            TokenScope::Book => "book",
+            TokenScope::Admin => "admin"
+        }
+    }
+
+    /// Область, которую токен должен разрешать, чтобы им пользовались с правом роли `permission`
+    pub(crate) fn for_permission(permission: Permission) -> TokenScope {
+        match permission {
+            Permission::Book => TokenScope::Book,
+            Permission::ManageBookings | Permission::ManageSeats | Permission::ManageUsers
+                | Permission::ViewAudit | Permission::PurgeAudit
+                | Permission::ManageInvites => TokenScope::Admin
+        }
+    }
+
+    /// Покрывает ли эта область доступа требуемую `required`
+    pub fn covers(&self, required: TokenScope) -> bool {
+        *self >= required
+    }
+}
+
+impl FromStr for TokenScope {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(TokenScope::Read),
+            "book" => Ok(TokenScope::Book),
+            "admin" => Ok(TokenScope::Admin),
+            _ => Err(())
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "api_tokens")]
+pub struct ApiToken {
+    pub id: Option<i32>,
+    pub user_id: Option<i32>,
+    pub name: Option<String>,
+    pub scopes: Option<Vec<String>>,
+    pub created: Option<i64>,   // utc timestamp
+    pub expires: Option<i64>,   // utc timestamp
+    pub last_used: Option<i64>  // utc timestamp
+}
+
+impl ApiToken {
+    pub fn scopes(&self) -> Vec<TokenScope> {
+        self.scopes.iter()
+            .flatten()
+            .filter_map(|scope| scope.parse().ok())
+            .collect()
+    }
+
+    /// Разрешает ли токен действие, требующее `required`
+    pub fn allows(&self, required: TokenScope) -> bool {
+        self.scopes().iter().any(|scope| scope.covers(required))
+    }
+}
+
+/// Форма создания токена
+#[derive(Clone, Deserialize, Serialize)]
+pub struct ApiTokenForm {
+    pub name: String,
+    pub scope: String,
+    /// Срок жизни в днях, пусто -- бессрочный
+    pub days: Option<String>
+}
+
+/// Хеш токена для хранения в базе. Токен -- случайные 256 бит,
+/// так что соль и медленный хеш ему не нужны.
+pub(crate) fn hash_token(token: &str) -> String {
+    let mut sha512hasher = Sha512State::default().build_hasher();
+    sha512hasher.write(token.as_bytes());
+    let bytes_result = HasherContext::finish(&mut sha512hasher);
+    format!("{bytes_result:02x}")
+}
+
+fn generate_token() -> String {
+    let bytes = rand::thread_rng().gen::<[u8; 32]>();
+    let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    format!("{TOKEN_PREFIX}{hex}")
+}
+
+async fn get_api_tokens_by(client: &Client,
+                           sql: &str,
+                           query_params: &[&(dyn ToSql + Sync)])
+    -> Result<Vec<ApiToken>, DbError>
+{
+    let stmt = sql.replace("$table_fields", &ApiToken::sql_table_fields());
+    let stmt = client.prepare(&stmt).await?;
+
+    let results = client
+        .query(&stmt, query_params)
+        .await?
+        .iter()
+        .map(ApiToken::from_row_ref)
+        .collect::<Result<Vec<ApiToken>, _>>()?;
+
+    Ok(results)
+}
+
+/// Создаёт токен. Сам токен возвращается только здесь, в базе остаётся лишь хеш.
+pub async fn add_api_token(client: &Client,
+                           user_id: i32,
+                           name: &str,
+                           scopes: &[TokenScope],
+                           created: i64,
+                           expires: Option<i64>)
+    -> Result<(ApiToken, String), DbError>
+{
+    let token = generate_token();
+    let scopes: Vec<&str> = scopes.iter().map(TokenScope::as_str).collect();
+    let api_token = get_api_tokens_by(client,
+                                      include_str!("sql/api_token/add_api_token.sql"),
+                                      &[&user_id, &name, &hash_token(&token),
+                                        &scopes, &created, &expires])
+        .await?
+        .pop()
+        .ok_or(DbError::NotFound)?;
+    Ok((api_token, token))
+}
+
+/// Токены пользователя, от новых к старым
+pub async fn get_user_api_tokens(client: &Client, user_id: i32) -> Result<Vec<ApiToken>, DbError> {
+    get_api_tokens_by(client,
+                      include_str!("sql/api_token/get_user_api_tokens.sql"),
+                      &[&user_id]).await
+}
+
+/// Находит действующий токен по его значению и отмечает, что им воспользовались
+pub async fn use_api_token(client: &Client, token: &str, now: i64) -> Result<ApiToken, DbError> {
+    let api_token = get_api_tokens_by(client,
+                                      include_str!("sql/api_token/get_api_token_by_hash.sql"),
+                                      &[&hash_token(token), &now])
+        .await?
+        .pop()
+        .ok_or(DbError::NotFound)?;
+
+    let stmt = include_str!("sql/api_token/touch_api_token.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&api_token.id, &now]).await?;
+    Ok(api_token)
+}
+
+/// Отзывает токен пользователя
+pub async fn remove_api_token(client: &Client, user_id: i32, id: i32) -> Result<ApiToken, DbError> {
+    get_api_tokens_by(client,
+                      include_str!("sql/api_token/remove_api_token.sql"),
+                      &[&id, &user_id])
+        .await?
+        .pop()
+        .ok_or(DbError::NotFound)
+}
+
+/// Отзывает все токены пользователя
+pub async fn remove_user_api_tokens(client: &Client, user_id: i32) -> Result<(), DbError> {
+    let stmt = include_str!("sql/api_token/remove_user_api_tokens.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&user_id]).await?;
+    Ok(())
+}
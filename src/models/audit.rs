@@ -0,0 +1,66 @@
+use deadpool_postgres::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio_pg_mapper::FromTokioPostgresRow;
+use tokio_pg_mapper::tokio_pg_mapper_derive::PostgresMapper;
+use crate::core::errors::DbError;
+
+/// Запись журнала действий
+#[derive(Clone, Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "audit_log")]
+pub struct AuditRecord {
+    pub id: Option<i64>,
+    pub actor_id: Option<i32>,
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<i32>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    pub ip: Option<String>,
+    pub created: Option<i64> // utc timestamp
+}
+
+/// Фильтр журнала; пустые поля не ограничивают выборку
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct AuditFilter {
+    pub actor_id: Option<i32>,
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<i32>
+}
+
+pub async fn add_audit_record(client: &Client, record: &AuditRecord) -> Result<(), DbError> {
+    let stmt = include_str!("sql/audit/add_audit_record.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&record.actor_id, &record.action, &record.target_type,
+                            &record.target_id, &record.before, &record.after,
+                            &record.ip, &record.created]).await?;
+    Ok(())
+}
+
+/// Последние `limit` записей под фильтр, от новых к старым
+pub async fn get_audit_records(client: &Client, filter: &AuditFilter, limit: i64)
+    -> Result<Vec<AuditRecord>, DbError>
+{
+    let stmt = include_str!("sql/audit/get_audit_records.sql");
+    let stmt = stmt.replace("$table_fields", &AuditRecord::sql_table_fields());
+    let stmt = client.prepare(&stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&filter.actor_id, &filter.action, &filter.target_type,
+                        &filter.target_id, &limit])
+        .await?
+        .iter()
+        .map(AuditRecord::from_row_ref)
+        .collect::<Result<Vec<AuditRecord>, _>>()?;
+
+    Ok(results)
+}
+
+/// Удаляет записи старше `keep_days` дней, возвращает число удалённых
+pub async fn purge_audit_records(client: &Client, keep_days: i32) -> Result<i64, DbError> {
+    let stmt = include_str!("sql/audit/purge_audit_records.sql");
+    let stmt = client.prepare(stmt).await?;
+    let row = client.query_one(&stmt, &[&keep_days]).await?;
+    Ok(row.try_get("removed")?)
+}
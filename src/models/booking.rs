@@ -0,0 +1,218 @@
+use deadpool_postgres::Client;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use tokio_pg_mapper::FromTokioPostgresRow;
+use tokio_pg_mapper::tokio_pg_mapper_derive::PostgresMapper;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::types::ToSql;
+use crate::core::errors::DbError;
+
+#[derive(Clone, Deserialize, PostgresMapper, Serialize, ToSchema)]
+#[pg_mapper(table = "bookings")]
+pub struct Booking {
+    pub id: Option<i32>,
+    pub seat_id: Option<i32>,
+    pub user_id: Option<i32>,
+    pub starts: Option<i64>, // utc timestamp
+    pub ends: Option<i64>    // utc timestamp
+}
+
+/// Форма «посадите меня рядом с @login»
+#[derive(Clone, Deserialize, Serialize)]
+pub struct BookNextToForm {
+    pub login: String,
+    pub date: Option<String>,
+    pub from: String,
+    pub hours: Option<f64>
+}
+
+/// Форма подтверждения брони конкретного места на конкретное время
+#[derive(Clone, Deserialize, Serialize, ToSchema)]
+pub struct BookSeatForm {
+    pub seat_id: i32,
+    pub starts: i64,
+    pub ends: i64
+}
+
+/// Продление брони
+#[derive(Clone, Deserialize, Serialize, ToSchema)]
+pub struct ExtendBookingForm {
+    pub ends: i64
+}
+
+/// Самая длинная бронь, в секундах
+pub const MAX_BOOKING_DURATION: i64 = 24 * 60 * 60;
+
+impl Booking {
+    /// Можно ли забронировать интервал `[starts, ends)`: он в будущем,
+    /// не пустой и не длиннее `MAX_BOOKING_DURATION`
+    pub fn is_valid_interval(starts: i64, ends: i64, now: i64) -> bool {
+        starts > now && ends > starts && ends - starts <= MAX_BOOKING_DURATION
+    }
+
+    /// Пересекается ли бронь с полуинтервалом `[starts, ends)`
+    pub fn overlaps(&self, starts: i64, ends: i64) -> bool {
+        match (self.starts, self.ends) {
+            (Some(s), Some(e)) => s < ends && e > starts,
+            _ => false
+        }
+    }
+}
+
+async fn get_bookings_by(client: &Client,
+                         sql: &str,
+                         query_params: &[&(dyn ToSql + Sync)])
+    -> Result<Vec<Booking>, DbError>
+{
+    let stmt = sql.replace("$table_fields", &Booking::sql_table_fields());
+    let stmt = client.prepare(&stmt).await?;
+
+    let results = client
+        .query(&stmt, query_params)
+        .await?
+        .iter()
+        .map(Booking::from_row_ref)
+        .collect::<Result<Vec<Booking>, _>>()?;
+
+    Ok(results)
+}
+
+/// Все брони, пересекающиеся с интервалом `[starts, ends)`
+pub async fn get_bookings_in_range(client: &Client, starts: i64, ends: i64)
+    -> Result<Vec<Booking>, DbError>
+{
+    get_bookings_by(client,
+                    include_str!("sql/booking/get_bookings_in_range.sql"),
+                    &[&starts, &ends]).await
+}
+
+pub async fn get_booking_by_id(client: &Client, id: i32) -> Result<Booking, DbError> {
+    get_bookings_by(client, include_str!("sql/booking/get_booking_by_id.sql"), &[&id])
+        .await?
+        .pop()
+        .ok_or(DbError::NotFound)
+}
+
+/// Брони, которые начались или закончились в полуинтервале `(from, to]`
+pub async fn get_bookings_started_or_ended(client: &Client, from: i64, to: i64)
+    -> Result<Vec<Booking>, DbError>
+{
+    get_bookings_by(client,
+                    include_str!("sql/booking/get_bookings_started_or_ended.sql"),
+                    &[&from, &to]).await
+}
+
+/// Бронирует место, если на это время оно ещё никем не занято
+pub async fn add_booking(client: &Client, booking: Booking) -> Result<Booking, DbError> {
+    let stmt = include_str!("sql/booking/add_booking.sql");
+    let stmt = stmt.replace("$table_fields", &Booking::sql_table_fields());
+    let stmt = client.prepare(&stmt).await?;
+
+    let query_params : [&(dyn ToSql + Sync); 4] = [
+        &booking.seat_id,
+        &booking.user_id,
+        &booking.starts,
+        &booking.ends
+    ];
+
+    let output = match client.query(&stmt, &query_params).await {
+        Ok(mut rows) => rows.pop(),
+        Err(e) if e.code() == Some(&SqlState::EXCLUSION_VIOLATION) => return Err(DbError::Conflict),
+        Err(e) => return Err(e.into())
+    };
+    match output {
+        Some(row) => Ok(Booking::from_row_ref(&row)?),
+        None => Err(DbError::Conflict)
+    }
+}
+
+/// Бронь пользователя на это место, идущая в момент `now`
+pub async fn get_user_seat_booking(client: &Client, user_id: i32, seat_id: i32, now: i64)
+    -> Result<Option<Booking>, DbError>
+{
+    let stmt = include_str!("sql/booking/get_user_seat_booking.sql");
+    let stmt = stmt.replace("$table_fields", &Booking::sql_table_fields());
+    let stmt = client.prepare(&stmt).await?;
+
+    match client.query_opt(&stmt, &[&user_id, &seat_id, &now]).await? {
+        Some(row) => Ok(Some(Booking::from_row_ref(&row)?)),
+        None => Ok(None)
+    }
+}
+
+/// Отмечает, что пользователь пришёл на место по своей текущей брони.
+/// Повторная отметка ничего не меняет; без текущей брони -- `NotFound`.
+pub async fn check_in_booking(client: &Client, user_id: i32, seat_id: i32, now: i64)
+    -> Result<i32, DbError>
+{
+    let stmt = include_str!("sql/booking/check_in_booking.sql");
+    let stmt = client.prepare(stmt).await?;
+    match client.query_opt(&stmt, &[&user_id, &seat_id, &now]).await? {
+        Some(row) => Ok(row.try_get("booking_id")?),
+        None => Err(DbError::NotFound)
+    }
+}
+
+/// Места, на которых в момент `now` кто-то отметился по брони
+pub async fn get_checked_in_seats(client: &Client, now: i64) -> Result<Vec<i32>, DbError> {
+    let stmt = include_str!("sql/booking/get_checked_in_seats.sql");
+    let stmt = client.prepare(stmt).await?;
+    let output = client.query(&stmt, &[&now])
+        .await?
+        .iter()
+        .map(|row| row.try_get("seat_id"))
+        .collect::<Result<Vec<i32>, _>>()?;
+    Ok(output)
+}
+
+/// Прошедшие брони пользователя, от новых к старым
+pub async fn get_past_user_bookings(client: &Client, user_id: i32, now: i64)
+    -> Result<Vec<Booking>, DbError>
+{
+    get_bookings_by(client,
+                    include_str!("sql/booking/get_past_user_bookings.sql"),
+                    &[&user_id, &now]).await
+}
+
+/// Текущие и будущие брони пользователя, по времени начала
+pub async fn get_upcoming_user_bookings(client: &Client, user_id: i32, now: i64)
+    -> Result<Vec<Booking>, DbError>
+{
+    get_bookings_by(client,
+                    include_str!("sql/booking/get_upcoming_user_bookings.sql"),
+                    &[&user_id, &now]).await
+}
+
+/// Все текущие и будущие брони, по времени начала
+pub async fn get_upcoming_bookings(client: &Client, now: i64) -> Result<Vec<Booking>, DbError> {
+    get_bookings_by(client,
+                    include_str!("sql/booking/get_upcoming_bookings.sql"),
+                    &[&now]).await
+}
+
+/// Передаёт бронь другому пользователю
+pub async fn set_booking_user(client: &Client, id: i32, user_id: i32) -> Result<Booking, DbError> {
+    get_bookings_by(client, include_str!("sql/booking/set_booking_user.sql"), &[&id, &user_id])
+        .await?
+        .pop()
+        .ok_or(DbError::NotFound)
+}
+
+/// Отменяет бронь, возвращая удалённую запись
+pub async fn remove_booking(client: &Client, id: i32) -> Result<Booking, DbError> {
+    get_bookings_by(client, include_str!("sql/booking/remove_booking.sql"), &[&id])
+        .await?
+        .pop()
+        .ok_or(DbError::NotFound)
+}
+
+/// Переносит конец брони, если место на новое время никем не занято
+pub async fn set_booking_ends(client: &Client, id: i32, ends: i64) -> Result<Booking, DbError> {
+    let stmt = include_str!("sql/booking/set_booking_ends.sql");
+    match get_bookings_by(client, stmt, &[&id, &ends]).await {
+        Ok(mut bookings) => bookings.pop().ok_or(DbError::Conflict),
+        Err(DbError::PGError(e)) if e.code() == Some(&SqlState::EXCLUSION_VIOLATION) =>
+            Err(DbError::Conflict),
+        Err(e) => Err(e)
+    }
+}
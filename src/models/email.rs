@@ -0,0 +1,75 @@
+use deadpool_postgres::Client;
+use serde::{Deserialize, Serialize};
+use tokio_pg_mapper::FromTokioPostgresRow;
+use tokio_pg_mapper::tokio_pg_mapper_derive::PostgresMapper;
+use crate::core::errors::DbError;
+
+/// Письмо в очереди на отправку
+#[derive(Clone, Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "email_outbox")]
+pub struct OutboxEmail {
+    pub id: i64,
+    pub recipient: String,
+    pub subject: String,
+    pub text_body: String,
+    pub html_body: Option<String>,
+    pub created: i64,      // utc timestamp
+    pub attempts: i32,
+    pub next_attempt: i64, // utc timestamp
+    pub sent: Option<i64>, // utc timestamp
+    pub last_error: Option<String>
+}
+
+pub async fn add_outbox_email(client: &Client,
+                              recipient: &str,
+                              subject: &str,
+                              text_body: &str,
+                              html_body: Option<&str>,
+                              now: i64)
+    -> Result<OutboxEmail, DbError>
+{
+    let stmt = include_str!("sql/email/add_outbox_email.sql");
+    let stmt = stmt.replace("$table_fields", &OutboxEmail::sql_table_fields());
+    let stmt = client.prepare(&stmt).await?;
+    let row = client.query_one(&stmt, &[&recipient, &subject, &text_body, &html_body, &now]).await?;
+    Ok(OutboxEmail::from_row_ref(&row)?)
+}
+
+/// До `limit` писем, которым пора уйти; на `lease` секунд они скрываются от других отправителей
+pub async fn claim_outbox_emails(client: &Client, now: i64, lease: i64, max_attempts: i32, limit: i64)
+    -> Result<Vec<OutboxEmail>, DbError>
+{
+    let stmt = include_str!("sql/email/claim_outbox_emails.sql");
+    let stmt = stmt.replace("$table_fields", &OutboxEmail::sql_table_fields());
+    let stmt = client.prepare(&stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&now, &lease, &max_attempts, &limit])
+        .await?
+        .iter()
+        .map(OutboxEmail::from_row_ref)
+        .collect::<Result<Vec<OutboxEmail>, _>>()?;
+
+    Ok(results)
+}
+
+pub async fn set_email_sent(client: &Client, id: i64, now: i64) -> Result<(), DbError> {
+    let stmt = include_str!("sql/email/set_email_sent.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&id, &now]).await?;
+    Ok(())
+}
+
+pub async fn set_email_failed(client: &Client, id: i64, next_attempt: i64, error: &str) -> Result<(), DbError> {
+    let stmt = include_str!("sql/email/set_email_failed.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&id, &next_attempt, &error]).await?;
+    Ok(())
+}
+
+pub async fn set_email_rejected(client: &Client, id: i64, max_attempts: i32, error: &str) -> Result<(), DbError> {
+    let stmt = include_str!("sql/email/set_email_rejected.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&id, &max_attempts, &error]).await?;
+    Ok(())
+}
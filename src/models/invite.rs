@@ -0,0 +1,150 @@
+use deadpool_postgres::Client;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio_pg_mapper::FromTokioPostgresRow;
+use tokio_pg_mapper::tokio_pg_mapper_derive::PostgresMapper;
+use crate::core::errors::DbError;
+
+/// Длина кода приглашения
+const INVITE_CODE_LENGTH: usize = 10;
+
+/// Код приглашения: по нему можно зарегистрироваться
+#[derive(Clone, Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "invites")]
+pub struct Invite {
+    pub id: Option<i32>,
+    pub code: Option<String>,
+    pub created_by: Option<i32>,
+    pub role: Option<i32>,     // роль нового пользователя, None -- по умолчанию
+    pub club: Option<String>,
+    pub max_uses: Option<i32>, // None -- без ограничения
+    pub uses: Option<i32>,
+    pub created: Option<i64>,  // utc timestamp
+    pub expires: Option<i64>,  // utc timestamp
+    pub revoked: Option<i64>   // utc timestamp
+}
+
+impl Invite {
+    /// Можно ли ещё зарегистрироваться по коду на момент `now`
+    pub(crate) fn is_active(&self, now: i64) -> bool {
+        self.revoked.is_none()
+            && self.expires.is_none_or(|expires| expires > now)
+            && self.max_uses.is_none_or(|max_uses| self.uses.unwrap_or_default() < max_uses)
+    }
+}
+
+/// Кто и когда зарегистрировался по коду
+#[derive(Clone, Serialize)]
+pub struct InviteUse {
+    pub invite_id: i32,
+    pub login: String,
+    pub used: i64 // utc timestamp
+}
+
+/// Код из заглавных букв и цифр без похожих друг на друга 0/O и 1/I:
+/// его будут диктовать и переписывать с доски
+fn generate_invite_code() -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+    let mut rng = rand::thread_rng();
+    (0..INVITE_CODE_LENGTH)
+        .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+        .collect()
+}
+
+/// Приводит введённый код к виду, в котором он хранится
+pub fn normalize_invite_code(code: &str) -> String {
+    code.trim().to_uppercase()
+}
+
+/// Заводит приглашение с новым кодом; `code` из `invite` не используется
+pub async fn add_invite(client: &Client, invite: &Invite, now: i64) -> Result<Invite, DbError> {
+    let stmt = include_str!("sql/invite/add_invite.sql");
+    let stmt = stmt.replace("$table_fields", &Invite::sql_table_fields());
+    let stmt = client.prepare(&stmt).await?;
+    let row = client.query_one(&stmt, &[&generate_invite_code(), &invite.created_by, &invite.role,
+                                        &invite.club, &invite.max_uses, &now, &invite.expires]).await?;
+    Ok(Invite::from_row_ref(&row)?)
+}
+
+/// Все приглашения или только созданные `created_by`, новые сначала
+pub async fn get_invites(client: &Client, created_by: Option<i32>) -> Result<Vec<Invite>, DbError> {
+    let stmt = include_str!("sql/invite/get_invites.sql");
+    let stmt = stmt.replace("$table_fields", &Invite::sql_table_fields());
+    let stmt = client.prepare(&stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&created_by])
+        .await?
+        .iter()
+        .map(Invite::from_row_ref)
+        .collect::<Result<Vec<Invite>, _>>()?;
+
+    Ok(results)
+}
+
+pub async fn get_invite_by_id(client: &Client, id: i32) -> Result<Invite, DbError> {
+    let stmt = include_str!("sql/invite/get_invite_by_id.sql");
+    let stmt = stmt.replace("$table_fields", &Invite::sql_table_fields());
+    let stmt = client.prepare(&stmt).await?;
+    match client.query_opt(&stmt, &[&id]).await? {
+        Some(row) => Ok(Invite::from_row_ref(&row)?),
+        None => Err(DbError::NotFound)
+    }
+}
+
+/// Занимает одно использование кода. Отозванный, истёкший или исчерпанный
+/// код даёт `NotFound`; два одновременных запроса последнее использование не поделят.
+pub async fn use_invite(client: &Client, code: &str, now: i64) -> Result<Invite, DbError> {
+    let stmt = include_str!("sql/invite/use_invite.sql");
+    let stmt = stmt.replace("$table_fields", &Invite::sql_table_fields());
+    let stmt = client.prepare(&stmt).await?;
+    match client.query_opt(&stmt, &[&normalize_invite_code(code), &now]).await? {
+        Some(row) => Ok(Invite::from_row_ref(&row)?),
+        None => Err(DbError::NotFound)
+    }
+}
+
+/// Возвращает использование, если регистрация по коду не удалась
+pub async fn release_invite(client: &Client, id: i32) -> Result<(), DbError> {
+    let stmt = include_str!("sql/invite/release_invite.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&id]).await?;
+    Ok(())
+}
+
+/// Отзывает приглашение; уже отозванное даёт `NotFound`
+pub async fn revoke_invite(client: &Client, id: i32, now: i64) -> Result<Invite, DbError> {
+    let stmt = include_str!("sql/invite/revoke_invite.sql");
+    let stmt = stmt.replace("$table_fields", &Invite::sql_table_fields());
+    let stmt = client.prepare(&stmt).await?;
+    match client.query_opt(&stmt, &[&id, &now]).await? {
+        Some(row) => Ok(Invite::from_row_ref(&row)?),
+        None => Err(DbError::NotFound)
+    }
+}
+
+pub async fn add_invite_use(client: &Client, invite_id: i32, user_id: i32, now: i64) -> Result<(), DbError> {
+    let stmt = include_str!("sql/invite/add_invite_use.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&invite_id, &user_id, &now]).await?;
+    Ok(())
+}
+
+/// Регистрации по приглашениям `invite_ids`, старые сначала
+pub async fn get_invite_uses(client: &Client, invite_ids: &[i32]) -> Result<Vec<InviteUse>, DbError> {
+    let stmt = include_str!("sql/invite/get_invite_uses.sql");
+    let stmt = client.prepare(stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&invite_ids])
+        .await?
+        .iter()
+        .map(|row| InviteUse {
+            invite_id: row.get(0),
+            login: row.get(1),
+            used: row.get(2)
+        })
+        .collect();
+
+    Ok(results)
+}
@@ -0,0 +1,74 @@
+use deadpool_postgres::{Client, GenericClient};
+use serde::{Deserialize, Serialize};
+use tokio_pg_mapper::FromTokioPostgresRow;
+use tokio_pg_mapper::tokio_pg_mapper_derive::PostgresMapper;
+use crate::core::errors::DbError;
+
+/// Счётчик неудачных попыток входа с одного адреса или под одним логином
+#[derive(Clone, Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "login_attempts")]
+pub struct LoginAttempts {
+    pub kind: String,
+    pub subject: String,
+    pub failures: i32,
+    pub last_failure: i64, // utc timestamp
+    pub locked_until: i64  // utc timestamp
+}
+
+/// Засчитывает попытку как неудачу, если вход сейчас не закрыт; закрытый счётчик
+/// возвращается как есть. Счётчик, к которому не прибавлялось дольше `window`
+/// секунд, начинается заново.
+pub async fn reserve_login_attempt(client: &impl GenericClient, kind: &str, subject: &str, now: i64, window: i64)
+    -> Result<LoginAttempts, DbError>
+{
+    let stmt = include_str!("sql/login_attempt/reserve_login_attempt.sql");
+    let stmt = stmt.replace("$table_fields", &LoginAttempts::sql_table_fields());
+    let stmt = client.prepare(&stmt).await?;
+    let row = client.query_one(&stmt, &[&kind, &subject, &now, &window]).await?;
+    Ok(LoginAttempts::from_row_ref(&row)?)
+}
+
+pub async fn set_login_lock(client: &impl GenericClient, kind: &str, subject: &str, until: i64) -> Result<(), DbError> {
+    let stmt = include_str!("sql/login_attempt/set_login_lock.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&kind, &subject, &until]).await?;
+    Ok(())
+}
+
+/// Возвращает попытку, засчитанную `reserve_login_attempt`
+pub async fn release_login_attempt(client: &Client, kind: &str, subject: &str) -> Result<(), DbError> {
+    let stmt = include_str!("sql/login_attempt/release_login_attempt.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&kind, &subject]).await?;
+    Ok(())
+}
+
+pub async fn remove_login_attempts(client: &Client, kind: &str, subject: &str) -> Result<(), DbError> {
+    let stmt = include_str!("sql/login_attempt/remove_login_attempts.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&kind, &subject]).await?;
+    Ok(())
+}
+
+/// Логины, под которыми вход сейчас закрыт
+pub async fn get_locked_logins(client: &Client, now: i64) -> Result<Vec<LoginAttempts>, DbError> {
+    let stmt = include_str!("sql/login_attempt/get_locked_logins.sql");
+    let stmt = stmt.replace("$table_fields", &LoginAttempts::sql_table_fields());
+    let stmt = client.prepare(&stmt).await?;
+
+    let results = client
+        .query(&stmt, &[&now])
+        .await?
+        .iter()
+        .map(LoginAttempts::from_row_ref)
+        .collect::<Result<Vec<LoginAttempts>, _>>()?;
+
+    Ok(results)
+}
+
+/// Удаляет счётчики, которые уже ни на что не влияют
+pub async fn remove_stale_login_attempts(client: &Client, now: i64, window: i64) -> Result<u64, DbError> {
+    let stmt = include_str!("sql/login_attempt/remove_stale_login_attempts.sql");
+    let stmt = client.prepare(stmt).await?;
+    Ok(client.execute(&stmt, &[&now, &window]).await?)
+}
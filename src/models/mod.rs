@@ -7,6 +7,15 @@ pub(crate) mod user;
 pub(crate) mod roles;
 pub(crate) mod seat;
 pub(crate) mod session;
+pub(crate) mod session_store;
+pub(crate) mod booking;
+pub(crate) mod api_token;
+pub(crate) mod audit;
+pub(crate) mod login_attempt;
+pub(crate) mod password_reset;
+pub(crate) mod email;
+pub(crate) mod invite;
+pub(crate) mod recovery_code;
 /*pub async fn select_sql<T>(client: &Client, sql: &str) {
     let mut stmt = String::from(sql);
     stmt = stmt.replace("$table_fields", &T::sql_table_fields());
@@ -0,0 +1,61 @@
+use deadpool_postgres::Client;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio_pg_mapper::FromTokioPostgresRow;
+use tokio_pg_mapper::tokio_pg_mapper_derive::PostgresMapper;
+use crate::core::errors::DbError;
+use crate::models::api_token::hash_token;
+
+/// Одноразовый токен сброса пароля; сам токен знает только получатель письма
+#[derive(Clone, Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "password_resets")]
+pub struct PasswordReset {
+    pub id: Option<i32>,
+    pub user_id: Option<i32>,
+    pub created: Option<i64>, // utc timestamp
+    pub expires: Option<i64>, // utc timestamp
+    pub used: Option<i64>     // utc timestamp
+}
+
+fn generate_reset_token() -> String {
+    let bytes = rand::thread_rng().gen::<[u8; 32]>();
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Заводит токен сброса, действующий `ttl` секунд, и возвращает его
+pub async fn add_password_reset(client: &Client, user_id: i32, now: i64, ttl: i64) -> Result<String, DbError> {
+    let token = generate_reset_token();
+    let stmt = include_str!("sql/password_reset/add_password_reset.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&user_id, &hash_token(&token), &now, &(now + ttl)]).await?;
+    Ok(token)
+}
+
+async fn get_password_reset_by(client: &Client, sql: &str, token: &str, now: i64)
+    -> Result<PasswordReset, DbError>
+{
+    let stmt = sql.replace("$table_fields", &PasswordReset::sql_table_fields());
+    let stmt = client.prepare(&stmt).await?;
+    match client.query_opt(&stmt, &[&hash_token(token), &now]).await? {
+        Some(row) => Ok(PasswordReset::from_row_ref(&row)?),
+        None => Err(DbError::NotFound)
+    }
+}
+
+/// Неиспользованный и не истёкший токен
+pub async fn get_password_reset(client: &Client, token: &str, now: i64) -> Result<PasswordReset, DbError> {
+    get_password_reset_by(client, include_str!("sql/password_reset/get_password_reset.sql"), token, now).await
+}
+
+/// Помечает токен использованным. Второй раз тот же токен даст `NotFound`.
+pub async fn use_password_reset(client: &Client, token: &str, now: i64) -> Result<PasswordReset, DbError> {
+    get_password_reset_by(client, include_str!("sql/password_reset/use_password_reset.sql"), token, now).await
+}
+
+/// Отзывает все неиспользованные токены пользователя
+pub async fn remove_user_password_resets(client: &Client, user_id: i32) -> Result<(), DbError> {
+    let stmt = include_str!("sql/password_reset/remove_user_password_resets.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&user_id]).await?;
+    Ok(())
+}
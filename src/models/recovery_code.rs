@@ -0,0 +1,92 @@
+use deadpool_postgres::{Client, GenericClient};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio_pg_mapper::FromTokioPostgresRow;
+use tokio_pg_mapper::tokio_pg_mapper_derive::PostgresMapper;
+use crate::core::errors::DbError;
+
+/// Сколько кодов восстановления выдаём за раз
+pub(crate) const RECOVERY_CODES_COUNT: usize = 10;
+
+/// Неиспользованный код восстановления. Сам код знает только пользователь,
+/// хранится его хеш argon2, как у паролей.
+#[derive(Clone, Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "recovery_codes")]
+pub struct RecoveryCode {
+    pub id: i32,
+    pub code_hash: String
+}
+
+/// Код вида `k7m2-x9qp`: строчные буквы и цифры без похожих 0/o и 1/l
+pub(crate) fn generate_recovery_code() -> String {
+    const ALPHABET: &[u8] = b"abcdefghijkmnpqrstuvwxyz23456789";
+    let mut rng = rand::thread_rng();
+    let mut half = || -> String {
+        (0..4).map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char).collect()
+    };
+    format!("{}-{}", half(), half())
+}
+
+/// Приводит введённый код к виду, в котором считался хеш
+pub(crate) fn normalize_recovery_code(code: &str) -> String {
+    let code: String = code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    match code.len() {
+        8 => format!("{}-{}", &code[..4], &code[4..]),
+        _ => code
+    }
+}
+
+/// Заменяет коды пользователя новыми по их хешам
+pub async fn replace_recovery_codes(client: &impl GenericClient, user_id: i32, hashes: &[String])
+    -> Result<(), DbError>
+{
+    remove_user_recovery_codes(client, user_id).await?;
+    let stmt = include_str!("sql/recovery_code/add_recovery_code.sql");
+    let stmt = client.prepare(stmt).await?;
+    for hash in hashes {
+        client.execute(&stmt, &[&user_id, hash]).await?;
+    }
+    Ok(())
+}
+
+pub async fn get_unused_recovery_codes(client: &Client, user_id: i32) -> Result<Vec<RecoveryCode>, DbError> {
+    let stmt = include_str!("sql/recovery_code/get_unused_recovery_codes.sql");
+    let stmt = stmt.replace("$table_fields", &RecoveryCode::sql_table_fields());
+    let stmt = client.prepare(&stmt).await?;
+    let results = client
+        .query(&stmt, &[&user_id])
+        .await?
+        .iter()
+        .map(RecoveryCode::from_row_ref)
+        .collect::<Result<Vec<RecoveryCode>, _>>()?;
+
+    Ok(results)
+}
+
+/// Тратит код восстановления. Уже использованный код даёт `NotFound`:
+/// из двух одновременных входов одним кодом пройдёт один.
+pub async fn use_recovery_code(client: &Client, id: i32, now: i64) -> Result<(), DbError> {
+    let stmt = include_str!("sql/recovery_code/use_recovery_code.sql");
+    let stmt = client.prepare(stmt).await?;
+    match client.query_opt(&stmt, &[&id, &now]).await? {
+        Some(_) => Ok(()),
+        None => Err(DbError::NotFound)
+    }
+}
+
+/// Сколько кодов у пользователя ещё не потрачено
+pub async fn count_unused_recovery_codes(client: &Client, user_id: i32) -> Result<i64, DbError> {
+    let stmt = include_str!("sql/recovery_code/count_unused_recovery_codes.sql");
+    let stmt = client.prepare(stmt).await?;
+    Ok(client.query_one(&stmt, &[&user_id]).await?.get(0))
+}
+
+pub async fn remove_user_recovery_codes(client: &impl GenericClient, user_id: i32) -> Result<(), DbError> {
+    let stmt = include_str!("sql/recovery_code/remove_user_recovery_codes.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&user_id]).await?;
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use std::fmt::{Display, Formatter};
+use deadpool_postgres::Client;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::core::errors::DbError;
+
+/// Что может делать пользователь
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Permission {
+    /// Бронировать места для себя
+    Book,
+    /// Смотреть, отменять и передавать чужие брони
+    ManageBookings,
+    /// Заводить, менять и удалять места
+    ManageSeats,
+    /// Менять роли, блокировать и сбрасывать пароли
+    ManageUsers,
+    /// Читать журнал действий
+    ViewAudit,
+    /// Удалять старые записи журнала
+    PurgeAudit,
+    /// Выдавать коды приглашений
+    ManageInvites
+}
+
+/// Роль пользователя, хранится в `users.role` по `id` из таблицы `roles`
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Role {
+    Guest,
+    Member,
+    ClubLead,
+    Admin
+}
+
+/// Роль новых пользователей
+pub(crate) const DEFAULT_ROLE: Role = Role::Member;
+
+impl Role {
+    pub(crate) const ALL: [Role; 4] = [Role::Guest, Role::Member, Role::ClubLead, Role::Admin];
+
+    pub(crate) fn from_id(id: i32) -> Option<Role> {
+        match id {
+            1 => Some(Role::Guest),
+            2 => Some(Role::Member),
+            3 => Some(Role::ClubLead),
+            4 => Some(Role::Admin),
+            _ => None
+        }
+    }
+
+    pub(crate) fn id(&self) -> i32 {
+        match self {
+            Role::Guest => 1,
+            Role::Member => 2,
+            Role::ClubLead => 3,
+            Role::Admin => 4
+        }
+    }
+
+    /// Название для людей
+    pub(crate) fn title(&self) -> &'static str {
+        match self {
+            Role::Guest => "Гость",
+            Role::Member => "Участник клуба",
+            Role::ClubLead => "Руководитель клуба",
+            Role::Admin => "Администратор"
+        }
+    }
+
+    pub(crate) fn permissions(&self) -> &'static [Permission] {
+        match self {
+            Role::Guest => &[],
+            Role::Member => &[Permission::Book],
+            Role::ClubLead => &[Permission::Book, Permission::ManageBookings,
+                                Permission::ManageInvites],
+            Role::Admin => &[Permission::Book, Permission::ManageBookings,
+                             Permission::ManageSeats, Permission::ManageUsers,
+                             Permission::ViewAudit, Permission::PurgeAudit,
+                             Permission::ManageInvites]
+        }
+    }
+
+    pub(crate) fn can(&self, permission: Permission) -> bool {
+        self.permissions().contains(&permission)
+    }
+
+    /// Может ли роль больше, чем бронировать себе: таким можно потребовать второй фактор
+    pub(crate) fn is_privileged(&self) -> bool {
+        self.permissions().iter().any(|permission| *permission != Permission::Book)
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            Role::Guest => write!(f, "guest"),
+            Role::Member => write!(f, "member"),
+            Role::ClubLead => write!(f, "club_lead"),
+            Role::Admin => write!(f, "admin")
+        }
+    }
+}
+
+/// Роли, которым администратор потребовал второй фактор
+pub async fn get_totp_required_roles(client: &Client) -> Result<Vec<Role>, DbError> {
+    let stmt = include_str!("sql/role/get_totp_required_roles.sql");
+    let stmt = client.prepare(stmt).await?;
+    let results = client
+        .query(&stmt, &[])
+        .await?
+        .iter()
+        .filter_map(|row| Role::from_id(row.get(0)))
+        .collect();
+    Ok(results)
+}
+
+pub async fn set_role_totp_required(client: &Client, role: Role, required: bool) -> Result<(), DbError> {
+    let stmt = include_str!("sql/role/set_role_totp_required.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&role.id(), &required]).await?;
+    Ok(())
+}
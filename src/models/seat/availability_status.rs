@@ -1,10 +1,13 @@
 use std::fmt::{Display, Formatter, write};
+use std::str::FromStr;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use tokio_postgres::Row;
 use tokio_postgres::types::{FromSql, Type};
 use crate::models::seat::seat_type::SeatType;
 
-#[derive(Clone, Deserialize, Serialize)]
+#[derive(Clone, Copy, Deserialize, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
 pub(crate) enum AvailabilityStatus {
     Unavailable,
     Taken,
@@ -38,4 +41,17 @@ impl Display for AvailabilityStatus {
             AvailabilityStatus::Free        => write!(f, "free")
         }
     }
-}
\ No newline at end of file
+}
+
+impl FromStr for AvailabilityStatus {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unavailable" => Ok(AvailabilityStatus::Unavailable),
+            "taken"       => Ok(AvailabilityStatus::Taken),
+            "free"        => Ok(AvailabilityStatus::Free),
+            _             => Err(())
+        }
+    }
+}
@@ -3,6 +3,7 @@ pub(crate) mod availability_status;
 
 use std::fmt::{Display, Formatter};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use tokio_pg_mapper::PostgresMapper;
 use tokio_postgres::{Client, Row};
 use tokio_postgres::types::{FromSql, Type};
@@ -11,7 +12,7 @@ use crate::models::seat::availability_status::AvailabilityStatus;
 use crate::models::seat::seat_type::SeatType;
 
 
-#[derive(Clone, Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize, ToSchema)]
 pub struct Seat {
     pub(crate) id: Option<i32>,
     pub(crate) name: Option<String>,
@@ -22,22 +23,68 @@ pub struct Seat {
     pub(crate) default_rot: Option<f64>,
     pub(crate) x: Option<f64>,
     pub(crate) y: Option<f64>,
-    pub(crate) rot: Option<f64>
+    pub(crate) rot: Option<f64>,
+    pub(crate) amenities: Option<Vec<String>>
+}
+
+/// Параметры формы «найди мне место»
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct SeatSearchForm {
+    pub date: Option<String>,      // YYYY-MM-DD, по умолчанию сегодня
+    pub from: Option<String>,      // HH:MM
+    pub hours: Option<f64>,
+    pub seat_type: Option<String>,
+    pub amenities: Option<String>, // через запятую
+    pub people: Option<usize>
+}
+
+/// Форма места в админке
+#[derive(Clone, Deserialize, Serialize)]
+pub struct SeatForm {
+    pub name: String,
+    pub seat_type: String,
+    pub availability: String,
+    pub x: f64,
+    pub y: f64,
+    pub rot: Option<f64>,
+    pub amenities: Option<String> // через запятую
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct FavouriteSeatForm {
+    pub favourite: bool
+}
+
+impl Seat {
+    /// Текущее положение места на карте, либо положение по умолчанию
+    pub(crate) fn position(&self) -> Option<(f64, f64)> {
+        match (self.x.or(self.default_x), self.y.or(self.default_y)) {
+            (Some(x), Some(y)) => Some((x, y)),
+            _ => None
+        }
+    }
+
+    /// Есть ли у места все перечисленные удобства
+    pub(crate) fn has_amenities(&self, wanted: &[String]) -> bool {
+        let amenities = self.amenities.as_deref().unwrap_or_default();
+        wanted.iter().all(|w| amenities.iter().any(|a| a.eq_ignore_ascii_case(w)))
+    }
 }
 
 impl std::convert::From<&tokio_postgres::Row> for Seat {
     fn from(row: &Row) -> Self {
         Self {
-            id:  row.try_get("id").unwrap_or_else(|e| None),
-            name: row.try_get("name").unwrap_or_else(|e| None),
-            seat_type: row.try_get("type").unwrap_or_else(|e| None),
-            availability: row.try_get("availability").unwrap_or_else(|e| None),
-            default_x: row.try_get("default_x").unwrap_or_else(|e| None),
-            default_y: row.try_get("default_y").unwrap_or_else(|e| None),
-            default_rot: row.try_get("default_rot").unwrap_or_else(|e| None),
-            x: row.try_get("x").unwrap_or_else(|e| None),
-            y: row.try_get("y").unwrap_or_else(|e| None),
-            rot: row.try_get("rot").unwrap_or_else(|e| None)
+            id:  row.try_get("id").unwrap_or_default(),
+            name: row.try_get("name").unwrap_or_default(),
+            seat_type: row.try_get("type").unwrap_or_default(),
+            availability: row.try_get("availability").unwrap_or_default(),
+            default_x: row.try_get("default_x").unwrap_or_default(),
+            default_y: row.try_get("default_y").unwrap_or_default(),
+            default_rot: row.try_get("default_rot").unwrap_or_default(),
+            x: row.try_get("x").unwrap_or_default(),
+            y: row.try_get("y").unwrap_or_default(),
+            rot: row.try_get("rot").unwrap_or_default(),
+            amenities: row.try_get("amenities").unwrap_or_default()
         }
     }
 }
@@ -54,3 +101,88 @@ pub(crate) async fn get_all_seats(client: &Client) -> Result<Vec<Seat>, DbError>
     Ok(output)
 }
 
+
+/// Пары соседних мест, заданные вручную
+pub(crate) async fn get_seat_neighbours(client: &Client) -> Result<Vec<(i32, i32)>, DbError> {
+    let stmt = include_str!("../sql/seat/get_seat_neighbours.sql");
+    let stmt = client.prepare(stmt).await?;
+    let output = client.query(&stmt, &[])
+        .await?
+        .iter()
+        .map(|row| Ok((row.try_get("seat_id")?, row.try_get("neighbour_id")?)))
+        .collect::<Result<Vec<(i32, i32)>, DbError>>()?;
+    Ok(output)
+}
+
+pub(crate) async fn get_seat_by_id(client: &Client, id: i32) -> Result<Seat, DbError> {
+    let stmt = include_str!("../sql/seat/get_seat_by_id.sql");
+    let stmt = client.prepare(stmt).await?;
+    match client.query(&stmt, &[&id]).await?.pop() {
+        Some(row) => Ok(Seat::from(&row)),
+        None => Err(DbError::NotFound)
+    }
+}
+
+/// Идентификаторы избранных мест пользователя
+pub(crate) async fn get_favourite_seats(client: &Client, user_id: i32) -> Result<Vec<i32>, DbError> {
+    let stmt = include_str!("../sql/seat/get_favourite_seats.sql");
+    let stmt = client.prepare(stmt).await?;
+    let output = client.query(&stmt, &[&user_id])
+        .await?
+        .iter()
+        .map(|row| row.try_get("seat_id"))
+        .collect::<Result<Vec<i32>, _>>()?;
+    Ok(output)
+}
+
+pub(crate) async fn set_favourite_seat(client: &Client, user_id: i32, seat_id: i32, favourite: bool)
+    -> Result<(), DbError>
+{
+    let stmt = if favourite {
+        include_str!("../sql/seat/add_favourite_seat.sql")
+    } else {
+        include_str!("../sql/seat/remove_favourite_seat.sql")
+    };
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&user_id, &seat_id]).await?;
+    Ok(())
+}
+
+/// Параметры места для `add_seat.sql` и `update_seat.sql`; перечисления
+/// передаются текстом и приводятся к типам базы в самом запросе
+fn seat_params(seat: &Seat) -> (Option<String>, Option<String>) {
+    (seat.seat_type.map(|t| t.to_string()), seat.availability.map(|a| a.to_string()))
+}
+
+pub(crate) async fn add_seat(client: &Client, seat: &Seat) -> Result<Seat, DbError> {
+    let stmt = include_str!("../sql/seat/add_seat.sql");
+    let stmt = client.prepare(stmt).await?;
+    let (seat_type, availability) = seat_params(seat);
+    match client.query(&stmt, &[&seat.name, &seat_type, &availability, &seat.default_x,
+                                &seat.default_y, &seat.default_rot, &seat.amenities]).await?.pop() {
+        Some(row) => Ok(Seat::from(&row)),
+        None => Err(DbError::NotFound)
+    }
+}
+
+pub(crate) async fn update_seat(client: &Client, id: i32, seat: &Seat) -> Result<Seat, DbError> {
+    let stmt = include_str!("../sql/seat/update_seat.sql");
+    let stmt = client.prepare(stmt).await?;
+    let (seat_type, availability) = seat_params(seat);
+    match client.query(&stmt, &[&id, &seat.name, &seat_type, &availability, &seat.default_x,
+                                &seat.default_y, &seat.default_rot, &seat.amenities]).await?.pop() {
+        Some(row) => Ok(Seat::from(&row)),
+        None => Err(DbError::NotFound)
+    }
+}
+
+/// Удаляет место вместе с прошедшими бронями. Если на момент `now` у места
+/// есть идущие или будущие брони, не удаляет ничего и возвращает `Conflict`.
+pub(crate) async fn remove_seat(client: &Client, id: i32, now: i64) -> Result<Seat, DbError> {
+    let stmt = include_str!("../sql/seat/remove_seat.sql");
+    let stmt = client.prepare(stmt).await?;
+    match client.query(&stmt, &[&id, &now]).await?.pop() {
+        Some(row) => Ok(Seat::from(&row)),
+        None => Err(DbError::Conflict)
+    }
+}
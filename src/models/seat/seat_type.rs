@@ -1,8 +1,10 @@
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use tokio_postgres::types::{FromSql, Type};
 
-#[derive(Clone, Deserialize, Serialize)]
+#[derive(Clone, Copy, Deserialize, PartialEq, Serialize, ToSchema)]
 pub(crate) enum SeatType {
     Desk,
     Chair,
@@ -38,4 +40,18 @@ impl Display for SeatType {
             SeatType::Pouf => write!(f, "pouf"),
         }
     }
-}
\ No newline at end of file
+}
+
+impl FromStr for SeatType {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "desk" => Ok(SeatType::Desk),
+            "chair" => Ok(SeatType::Chair),
+            "computer_chair" => Ok(SeatType::ComputerChair),
+            "pouf" => Ok(SeatType::Pouf),
+            _ => Err(()),
+        }
+    }
+}
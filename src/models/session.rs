@@ -6,33 +6,103 @@ use rs_sha512::{HasherContext, Sha512State};
 use serde::{Deserialize, Serialize};
 use tokio_pg_mapper::{FromTokioPostgresRow, PostgresMapper};
 use tokio_postgres::types::ToSql;
+use crate::config::SessionConfig;
 use crate::core::errors::DbError;
 use crate::models::user::{get_user_by_login, User};
 
 #[derive(Deserialize, PostgresMapper, Serialize)]
 #[pg_mapper(table = "sessions")] // singular 'user' is a keyword..
 pub struct Session {
+    pub id: Option<i32>,
     pub key: Option<String>,
     pub user_id: Option<i32>,
-    pub expires: Option<i32> // utc timestamp
+    pub expires: Option<i64>,      // utc timestamp
+    pub created: Option<i64>,      // utc timestamp
+    pub last_seen: Option<i64>,    // utc timestamp
+    pub idle_timeout: Option<i64>, // секунды, None -- без ограничения по бездействию
+    pub user_agent: Option<String>,
+    pub ip: Option<String>
 }
 
-pub async fn remove_session_by_token(client: &Client, token: &str) {
+/// Откуда открыта сессия
+#[derive(Clone, Default)]
+pub struct SessionOrigin {
+    pub user_agent: Option<String>,
+    pub ip: Option<String>
+}
+
+/// Как часто обновлять `last_seen`, чтобы не писать в базу на каждый запрос, в секундах
+const TOUCH_INTERVAL: i64 = 60;
+
+pub async fn remove_session_by_token(client: &Client, token: &str) -> Result<(), DbError> {
     let stmt = include_str!("sql/user/remove_session_by_token.sql");
-    let stmt = client.prepare(stmt).await.unwrap();
-    client.query(&stmt, &[&token]);
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&token]).await?;
+    Ok(())
+}
+
+/// Продлевает сессию по бездействию: отмечает, что её владелец активен
+pub async fn touch_session(client: &Client, token: &str, now: i64) -> Result<(), DbError> {
+    let stmt = include_str!("sql/user/touch_session.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&token, &now, &TOUCH_INTERVAL]).await?;
+    Ok(())
+}
+
+/// Действующие на момент `now` сессии пользователя, от недавних к давним
+pub async fn get_user_sessions(client: &Client, user_id: i32, now: i64) -> Result<Vec<Session>, DbError> {
+    let stmt = include_str!("sql/user/get_user_sessions.sql");
+    let stmt = stmt.replace("$table_fields", &Session::sql_table_fields());
+    let stmt = client.prepare(&stmt).await?;
+    let results = client
+        .query(&stmt, &[&user_id, &now])
+        .await?
+        .iter()
+        .map(Session::from_row_ref)
+        .collect::<Result<Vec<Session>, _>>()?;
+    Ok(results)
+}
+
+/// Закрывает одну сессию пользователя
+pub async fn remove_user_session(client: &Client, user_id: i32, id: i32) -> Result<(), DbError> {
+    let stmt = include_str!("sql/user/remove_user_session.sql");
+    let stmt = client.prepare(stmt).await?;
+    match client.execute(&stmt, &[&id, &user_id]).await? {
+        0 => Err(DbError::NotFound),
+        _ => Ok(())
+    }
 }
 
-pub async fn remove_user_sessions(client: &Client, user: User) {
+/// Удаляет закончившиеся сессии, возвращает их количество
+pub async fn remove_expired_sessions(client: &Client, now: i64) -> Result<u64, DbError> {
+    let stmt = include_str!("sql/user/remove_expired_sessions.sql");
+    let stmt = client.prepare(stmt).await?;
+    Ok(client.execute(&stmt, &[&now]).await?)
+}
+
+/// Закрывает все сессии пользователя, кроме сессии с ключом `keep`
+pub async fn remove_other_user_sessions(client: &Client, user_id: i32, keep: &str) -> Result<u64, DbError> {
+    let stmt = include_str!("sql/user/remove_other_user_sessions.sql");
+    let stmt = client.prepare(stmt).await?;
+    Ok(client.execute(&stmt, &[&user_id, &keep]).await?)
+}
+
+pub async fn remove_user_sessions(client: &Client, user: User) -> Result<(), DbError> {
     let stmt = include_str!("sql/user/remove_sessions_by_user.sql");
-    let stmt = client.prepare(stmt).await.unwrap();
-    client.query(&stmt, &[&user.id]);
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&user.id]).await?;
+    Ok(())
 }
 
 
+/// Заводит сессию пользователю. Без `lifetime` это сессия браузера: живёт
+/// не дольше `config.absolute_timeout` и заканчивается после `config.idle_timeout`
+/// бездействия. С `lifetime` живёт ровно столько, без ограничения по бездействию.
 pub async fn generate_session_token(client: &Client,
                                     mut user: User,
-                                    lifetime:Option<Duration>)
+                                    lifetime:Option<Duration>,
+                                    config: &SessionConfig,
+                                    origin: SessionOrigin)
                                     -> Result<Session, DbError>
 {
     if(user.id.is_none()) {
@@ -51,44 +121,32 @@ pub async fn generate_session_token(client: &Client,
     let bytes_result = HasherContext::finish(&mut sha512hasher);
     let token = format!("{bytes_result:02x}");
 
-    let q;
-
-    if(lifetime.is_none()) {
-        let _stmt = include_str!("sql/user/add_session.sql");
-        let _stmt = _stmt.replace("$table_fields", &User::sql_table_fields());
-        let stmt = client.prepare(&_stmt).await.unwrap();
-        let query_params : [&(dyn ToSql + Sync); 2] = [
-            &token,
-            &user.id
-        ];
-        q = client
-            .query(
-                &stmt,
-                &query_params,
-            ).await?;
-    } else {
-        let _stmt = include_str!("sql/user/add_expirable_session.sql");
-        let _stmt = _stmt.replace("$table_fields", &User::sql_table_fields());
-        let stmt = client.prepare(&_stmt).await.unwrap();
-        let query_params : [&(dyn ToSql + Sync); 3] = [
-            &token,
-            &user.id,
-            &((Utc::now() + lifetime.unwrap()).timestamp())
-        ];
-        q = client
-            .query(
-                &stmt,
-                &query_params,
-            ).await?;
-    }
-    let output = q
-        .iter()
-        .map(|row| Session::from_row_ref(row).unwrap())
-        .collect::<Vec<Session>>()
-        .pop();
+    let now = Utc::now().timestamp();
+    let (expires, idle_timeout) = match lifetime {
+        Some(lifetime) => (now + lifetime.num_seconds(), None),
+        None => (now + config.absolute_timeout, Some(config.idle_timeout))
+    };
+    let stmt = include_str!("sql/user/add_session.sql");
+    let stmt = client.prepare(stmt).await?;
+    let query_params : [&(dyn ToSql + Sync); 7] = [
+        &token,
+        &user.id,
+        &expires,
+        &now,
+        &idle_timeout,
+        &origin.user_agent,
+        &origin.ip
+    ];
+    client.execute(&stmt, &query_params).await?;
     Ok(Session {
+        id: None,
         key: Option::from(token),
         user_id: user.id,
-        expires: None,
+        expires: Some(expires),
+        created: Some(now),
+        last_seen: Some(now),
+        idle_timeout,
+        user_agent: origin.user_agent,
+        ip: origin.ip
     })
 }
\ No newline at end of file
@@ -0,0 +1,48 @@
+use deadpool_postgres::Client;
+use serde_json::Value;
+use crate::core::errors::DbError;
+
+/// Состояние сессии по ключу, если она ещё не истекла
+pub async fn get_session_state(client: &Client, key: &str, now: i64) -> Result<Option<Value>, DbError> {
+    let stmt = include_str!("sql/session_store/get_session_state.sql");
+    let stmt = client.prepare(stmt).await?;
+    let row = client.query_opt(&stmt, &[&key, &now]).await?;
+    Ok(row.map(|row| row.get(0)))
+}
+
+pub async fn add_session_state(client: &Client, key: &str, state: &Value, expires: i64) -> Result<(), DbError> {
+    let stmt = include_str!("sql/session_store/add_session_state.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&key, state, &expires]).await?;
+    Ok(())
+}
+
+/// `NotFound`, если сессии с таким ключом уже нет
+pub async fn update_session_state(client: &Client, key: &str, state: &Value, expires: i64) -> Result<(), DbError> {
+    let stmt = include_str!("sql/session_store/update_session_state.sql");
+    let stmt = client.prepare(stmt).await?;
+    match client.execute(&stmt, &[&key, state, &expires]).await? {
+        0 => Err(DbError::NotFound),
+        _ => Ok(())
+    }
+}
+
+pub async fn update_session_expires(client: &Client, key: &str, expires: i64) -> Result<(), DbError> {
+    let stmt = include_str!("sql/session_store/update_session_expires.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&key, &expires]).await?;
+    Ok(())
+}
+
+pub async fn remove_session_state(client: &Client, key: &str) -> Result<(), DbError> {
+    let stmt = include_str!("sql/session_store/remove_session_state.sql");
+    let stmt = client.prepare(stmt).await?;
+    client.execute(&stmt, &[&key]).await?;
+    Ok(())
+}
+
+pub async fn remove_expired_session_states(client: &Client, now: i64) -> Result<u64, DbError> {
+    let stmt = include_str!("sql/session_store/remove_expired_session_states.sql");
+    let stmt = client.prepare(stmt).await?;
+    Ok(client.execute(&stmt, &[&now]).await?)
+}
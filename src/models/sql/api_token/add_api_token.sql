@@ -0,0 +1,5 @@
+INSERT INTO
+    public.api_tokens (user_id, name, token_hash, scopes, created, expires)
+VALUES
+    ($1, $2, $3, $4, $5, $6)
+RETURNING $table_fields;
@@ -0,0 +1,6 @@
+SELECT
+    $table_fields
+FROM
+    public.api_tokens
+WHERE
+    token_hash = $1 AND (expires IS NULL OR expires > $2);
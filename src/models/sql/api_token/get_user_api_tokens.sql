@@ -0,0 +1,8 @@
+SELECT
+    $table_fields
+FROM
+    public.api_tokens
+WHERE
+    user_id = $1
+ORDER BY
+    created DESC;
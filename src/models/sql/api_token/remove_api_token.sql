@@ -0,0 +1,5 @@
+DELETE FROM
+    public.api_tokens
+WHERE
+    id = $1 AND user_id = $2
+RETURNING $table_fields;
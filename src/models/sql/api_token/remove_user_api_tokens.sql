@@ -0,0 +1,4 @@
+DELETE FROM
+    public.api_tokens
+WHERE
+    user_id = $1;
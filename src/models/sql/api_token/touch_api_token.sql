@@ -0,0 +1,6 @@
+UPDATE
+    public.api_tokens
+SET
+    last_used = $2
+WHERE
+    id = $1;
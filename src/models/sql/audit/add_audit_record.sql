@@ -0,0 +1,4 @@
+INSERT INTO
+    public.audit_log (actor_id, action, target_type, target_id, before, after, ip, created)
+VALUES
+    ($1, $2, $3, $4, $5, $6, $7, $8);
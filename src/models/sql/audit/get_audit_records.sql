@@ -0,0 +1,12 @@
+SELECT
+    $table_fields
+FROM
+    public.audit_log
+WHERE
+    ($1::INTEGER IS NULL OR actor_id = $1)
+    AND ($2::TEXT IS NULL OR action = $2)
+    AND ($3::TEXT IS NULL OR target_type = $3)
+    AND ($4::INTEGER IS NULL OR target_id = $4)
+ORDER BY
+    id DESC
+LIMIT $5;
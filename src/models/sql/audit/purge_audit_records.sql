@@ -0,0 +1 @@
+SELECT public.audit_log_purge($1) AS removed;
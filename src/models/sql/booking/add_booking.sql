@@ -0,0 +1,9 @@
+INSERT INTO
+    public.bookings (seat_id, user_id, starts, ends)
+SELECT
+    $1, $2, $3, $4
+WHERE NOT EXISTS (
+    SELECT 1 FROM public.bookings
+    WHERE seat_id = $1 AND starts < $4 AND ends > $3
+)
+RETURNING $table_fields;
@@ -0,0 +1,11 @@
+INSERT INTO
+    public.booking_check_ins (booking_id, checked_in)
+SELECT
+    id, $3
+FROM
+    public.bookings
+WHERE
+    user_id = $1 AND seat_id = $2 AND starts <= $3 AND ends > $3
+ON CONFLICT (booking_id) DO UPDATE
+    SET checked_in = public.booking_check_ins.checked_in
+RETURNING booking_id;
@@ -0,0 +1,6 @@
+SELECT
+    $table_fields
+FROM
+    public.bookings
+WHERE
+    id = $1;
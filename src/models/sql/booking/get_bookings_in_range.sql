@@ -0,0 +1,6 @@
+SELECT
+    $table_fields
+FROM
+    public.bookings
+WHERE
+    starts < $2 AND ends > $1;
@@ -0,0 +1,6 @@
+SELECT
+    $table_fields
+FROM
+    public.bookings
+WHERE
+    (starts > $1 AND starts <= $2) OR (ends > $1 AND ends <= $2);
@@ -0,0 +1,7 @@
+SELECT
+    bookings.seat_id
+FROM
+    public.bookings
+    JOIN public.booking_check_ins ON booking_check_ins.booking_id = bookings.id
+WHERE
+    bookings.starts <= $1 AND bookings.ends > $1;
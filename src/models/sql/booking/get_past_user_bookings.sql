@@ -0,0 +1,9 @@
+SELECT
+    $table_fields
+FROM
+    public.bookings
+WHERE
+    user_id = $1 AND ends <= $2
+ORDER BY
+    starts DESC
+LIMIT 200;
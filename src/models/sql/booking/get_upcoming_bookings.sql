@@ -0,0 +1,8 @@
+SELECT
+    $table_fields
+FROM
+    public.bookings
+WHERE
+    ends > $1
+ORDER BY
+    starts;
@@ -0,0 +1,8 @@
+SELECT
+    $table_fields
+FROM
+    public.bookings
+WHERE
+    user_id = $1 AND ends > $2
+ORDER BY
+    starts;
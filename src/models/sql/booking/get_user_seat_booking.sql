@@ -0,0 +1,6 @@
+SELECT
+    $table_fields
+FROM
+    public.bookings
+WHERE
+    user_id = $1 AND seat_id = $2 AND starts <= $3 AND ends > $3;
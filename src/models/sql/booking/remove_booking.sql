@@ -0,0 +1,5 @@
+DELETE FROM
+    public.bookings
+WHERE
+    id = $1
+RETURNING $table_fields;
@@ -0,0 +1,12 @@
+UPDATE
+    public.bookings
+SET
+    ends = $2
+WHERE
+    id = $1 AND NOT EXISTS (
+        SELECT 1 FROM public.bookings AS other
+        WHERE other.id <> $1
+            AND other.seat_id = bookings.seat_id
+            AND other.starts < $2 AND other.ends > bookings.starts
+    )
+RETURNING $table_fields;
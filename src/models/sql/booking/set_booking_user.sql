@@ -0,0 +1,7 @@
+UPDATE
+    public.bookings
+SET
+    user_id = $2
+WHERE
+    id = $1
+RETURNING $table_fields;
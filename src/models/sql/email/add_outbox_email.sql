@@ -0,0 +1,5 @@
+INSERT INTO
+    public.email_outbox (recipient, subject, text_body, html_body, created, next_attempt)
+VALUES
+    ($1, $2, $3, $4, $5, $5)
+RETURNING $table_fields;
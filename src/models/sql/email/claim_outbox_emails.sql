@@ -0,0 +1,15 @@
+-- Забираем письма, которым пора уйти, и откладываем их на $2 секунд:
+-- другой экземпляр их не тронет, а если отправитель упадёт, письма вернутся в очередь
+UPDATE
+    public.email_outbox
+SET
+    next_attempt = $1 + $2
+WHERE
+    id IN (
+        SELECT id FROM public.email_outbox
+        WHERE sent IS NULL AND attempts < $3 AND next_attempt <= $1
+        ORDER BY next_attempt
+        LIMIT $4
+        FOR UPDATE SKIP LOCKED
+    )
+RETURNING $table_fields;
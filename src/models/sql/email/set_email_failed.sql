@@ -0,0 +1,8 @@
+UPDATE
+    public.email_outbox
+SET
+    attempts = attempts + 1,
+    next_attempt = $2,
+    last_error = $3
+WHERE
+    id = $1;
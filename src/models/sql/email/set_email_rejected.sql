@@ -0,0 +1,8 @@
+-- Релей отказал насовсем: считаем попытки исчерпанными, и письмо больше не выбирается
+UPDATE
+    public.email_outbox
+SET
+    attempts = GREATEST(attempts + 1, $2),
+    last_error = $3
+WHERE
+    id = $1;
@@ -0,0 +1,8 @@
+UPDATE
+    public.email_outbox
+SET
+    sent = $2,
+    attempts = attempts + 1,
+    last_error = NULL
+WHERE
+    id = $1;
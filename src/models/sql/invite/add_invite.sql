@@ -0,0 +1,5 @@
+INSERT INTO
+    public.invites (code, created_by, role, club, max_uses, created, expires)
+VALUES
+    ($1, $2, $3, $4, $5, $6, $7)
+RETURNING $table_fields;
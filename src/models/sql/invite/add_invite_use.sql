@@ -0,0 +1,4 @@
+INSERT INTO
+    public.invite_uses (invite_id, user_id, used)
+VALUES
+    ($1, $2, $3);
@@ -0,0 +1,6 @@
+SELECT
+    $table_fields
+FROM
+    public.invites
+WHERE
+    id = $1;
@@ -0,0 +1,9 @@
+SELECT
+    invite_uses.invite_id, users.login, invite_uses.used
+FROM
+    public.invite_uses
+    JOIN public.users ON users.id = invite_uses.user_id
+WHERE
+    invite_uses.invite_id = ANY($1)
+ORDER BY
+    invite_uses.used;
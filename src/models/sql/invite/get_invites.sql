@@ -0,0 +1,8 @@
+SELECT
+    $table_fields
+FROM
+    public.invites
+WHERE
+    $1::INTEGER IS NULL OR created_by = $1
+ORDER BY
+    id DESC;
@@ -0,0 +1,6 @@
+UPDATE
+    public.invites
+SET
+    uses = uses - 1
+WHERE
+    id = $1 AND uses > 0;
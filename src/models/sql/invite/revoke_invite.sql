@@ -0,0 +1,7 @@
+UPDATE
+    public.invites
+SET
+    revoked = $2
+WHERE
+    id = $1 AND revoked IS NULL
+RETURNING $table_fields;
@@ -0,0 +1,10 @@
+UPDATE
+    public.invites
+SET
+    uses = uses + 1
+WHERE
+    code = $1
+    AND revoked IS NULL
+    AND (expires IS NULL OR expires > $2)
+    AND (max_uses IS NULL OR uses < max_uses)
+RETURNING $table_fields;
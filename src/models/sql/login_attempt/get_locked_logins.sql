@@ -0,0 +1,6 @@
+SELECT
+    $table_fields
+FROM
+    public.login_attempts
+WHERE
+    kind = 'login' AND locked_until > $1;
@@ -0,0 +1,6 @@
+UPDATE
+    public.login_attempts
+SET
+    failures = GREATEST(failures - 1, 0)
+WHERE
+    kind = $1 AND subject = $2;
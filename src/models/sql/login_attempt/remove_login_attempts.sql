@@ -0,0 +1,4 @@
+DELETE FROM
+    public.login_attempts
+WHERE
+    kind = $1 AND subject = $2;
@@ -0,0 +1,4 @@
+DELETE FROM
+    public.login_attempts
+WHERE
+    locked_until <= $1 AND last_failure <= $1 - $2;
@@ -0,0 +1,15 @@
+INSERT INTO
+    public.login_attempts (kind, subject, failures, last_failure)
+VALUES
+    ($1, $2, 1, $3)
+ON CONFLICT (kind, subject) DO UPDATE SET
+    failures = CASE
+        WHEN login_attempts.locked_until > $3 THEN login_attempts.failures
+        WHEN login_attempts.last_failure <= $3 - $4 THEN 1
+        ELSE login_attempts.failures + 1
+    END,
+    last_failure = CASE
+        WHEN login_attempts.locked_until > $3 THEN login_attempts.last_failure
+        ELSE $3
+    END
+RETURNING $table_fields;
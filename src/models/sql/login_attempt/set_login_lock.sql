@@ -0,0 +1,6 @@
+UPDATE
+    public.login_attempts
+SET
+    locked_until = $3
+WHERE
+    kind = $1 AND subject = $2;
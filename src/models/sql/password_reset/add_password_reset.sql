@@ -0,0 +1,4 @@
+INSERT INTO
+    public.password_resets (user_id, token_hash, created, expires)
+VALUES
+    ($1, $2, $3, $4);
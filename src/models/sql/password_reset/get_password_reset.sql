@@ -0,0 +1,6 @@
+SELECT
+    $table_fields
+FROM
+    public.password_resets
+WHERE
+    token_hash = $1 AND used IS NULL AND expires > $2;
@@ -0,0 +1,4 @@
+DELETE FROM
+    public.password_resets
+WHERE
+    user_id = $1 AND used IS NULL;
@@ -0,0 +1,7 @@
+UPDATE
+    public.password_resets
+SET
+    used = $2
+WHERE
+    token_hash = $1 AND used IS NULL AND expires > $2
+RETURNING $table_fields;
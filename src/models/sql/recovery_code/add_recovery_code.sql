@@ -0,0 +1,4 @@
+INSERT INTO
+    public.recovery_codes (user_id, code_hash)
+VALUES
+    ($1, $2);
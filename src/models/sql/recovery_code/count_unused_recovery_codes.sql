@@ -0,0 +1,6 @@
+SELECT
+    count(*)
+FROM
+    public.recovery_codes
+WHERE
+    user_id = $1 AND used IS NULL;
@@ -0,0 +1,6 @@
+SELECT
+    $table_fields
+FROM
+    public.recovery_codes
+WHERE
+    user_id = $1 AND used IS NULL;
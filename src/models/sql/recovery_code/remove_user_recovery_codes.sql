@@ -0,0 +1,4 @@
+DELETE FROM
+    public.recovery_codes
+WHERE
+    user_id = $1;
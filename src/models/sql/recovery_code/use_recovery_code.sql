@@ -0,0 +1,7 @@
+UPDATE
+    public.recovery_codes
+SET
+    used = $2
+WHERE
+    id = $1 AND used IS NULL
+RETURNING id;
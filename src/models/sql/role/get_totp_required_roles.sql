@@ -0,0 +1,8 @@
+SELECT
+    id
+FROM
+    public.roles
+WHERE
+    require_totp
+ORDER BY
+    id;
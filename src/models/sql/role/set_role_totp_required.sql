@@ -0,0 +1,6 @@
+UPDATE
+    public.roles
+SET
+    require_totp = $2
+WHERE
+    id = $1;
@@ -0,0 +1,5 @@
+INSERT INTO
+    public.favourite_seats (user_id, seat_id)
+VALUES
+    ($1, $2)
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,5 @@
+INSERT INTO
+    public.seats (name, type, availability, default_x, default_y, default_rot, amenities)
+VALUES
+    ($1, $2::text::seat_type, $3::text::availability_status, $4, $5, $6, $7)
+RETURNING *;
@@ -0,0 +1,6 @@
+SELECT
+    seat_id
+FROM
+    public.favourite_seats
+WHERE
+    user_id = $1;
@@ -0,0 +1,6 @@
+SELECT
+    *
+FROM
+    public.seats
+WHERE
+    id = $1;
@@ -0,0 +1,4 @@
+SELECT
+    seat_id, neighbour_id
+FROM
+    public.seat_neighbours;
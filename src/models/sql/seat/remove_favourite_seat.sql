@@ -0,0 +1,4 @@
+DELETE FROM
+    public.favourite_seats
+WHERE
+    user_id = $1 AND seat_id = $2;
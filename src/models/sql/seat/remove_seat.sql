@@ -0,0 +1,8 @@
+DELETE FROM
+    public.seats
+WHERE
+    id = $1 AND NOT EXISTS (
+        SELECT 1 FROM public.bookings
+        WHERE seat_id = $1 AND ends > $2
+    )
+RETURNING *;
\ No newline at end of file
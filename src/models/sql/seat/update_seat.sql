@@ -0,0 +1,13 @@
+UPDATE
+    public.seats
+SET
+    name = $2,
+    type = $3::text::seat_type,
+    availability = $4::text::availability_status,
+    default_x = $5,
+    default_y = $6,
+    default_rot = $7,
+    amenities = $8
+WHERE
+    id = $1
+RETURNING *;
@@ -0,0 +1,4 @@
+INSERT INTO
+    public.session_store ("key", state, expires)
+VALUES
+    ($1, $2, $3);
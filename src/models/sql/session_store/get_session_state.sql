@@ -0,0 +1,6 @@
+SELECT
+    state
+FROM
+    public.session_store
+WHERE
+    "key" = $1 AND expires > $2;
@@ -0,0 +1,4 @@
+DELETE FROM
+    public.session_store
+WHERE
+    expires <= $1;
@@ -0,0 +1,6 @@
+UPDATE
+    public.session_store
+SET
+    expires = $2
+WHERE
+    "key" = $1;
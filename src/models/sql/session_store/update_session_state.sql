@@ -0,0 +1,7 @@
+UPDATE
+    public.session_store
+SET
+    state = $2,
+    expires = $3
+WHERE
+    "key" = $1;
@@ -1,4 +0,0 @@
-INSERT INTO
-    public.sessions ("key", user_id, expires)
-VALUES
-    ($1, $2, $3);
\ No newline at end of file
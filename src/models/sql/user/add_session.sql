@@ -1,4 +1,4 @@
 INSERT INTO
-    public.sessions ("key", user_id)
+    public.sessions ("key", user_id, expires, created, last_seen, idle_timeout, user_agent, ip)
 VALUES
-    ($1, $2);
\ No newline at end of file
+    ($1, $2, $3, $4, $4, $5, $6, $7);
@@ -1,5 +1,5 @@
 INSERT INTO
-    public.users (login, name, password_hash, role)
+    public.users (login, name, password_hash, role, email, club, approval, registered)
 VALUES
-    ($1, $2, $3, $4)
+    ($1, $2, $3, $4, $5, $6, $7, $8)
 RETURNING $table_fields;
\ No newline at end of file
@@ -0,0 +1,8 @@
+SELECT
+    $table_fields
+FROM
+    public.users
+WHERE
+    approval = 'pending'
+ORDER BY
+    registered;
@@ -7,4 +7,6 @@ LEFT JOIN
 ON
     sess.user_id = users.id
 WHERE
-    sess.user_id = users.id  AND sess."key" = $1;
\ No newline at end of file
+    sess.user_id = users.id  AND sess."key" = $1 AND NOT users.banned AND users.approval <> 'rejected'
+    AND sess.expires > $2
+    AND (sess.idle_timeout IS NULL OR sess.last_seen + sess.idle_timeout > $2);
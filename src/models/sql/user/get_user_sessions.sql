@@ -0,0 +1,9 @@
+SELECT
+    $table_fields
+FROM
+    public.sessions
+WHERE
+    user_id = $1 AND expires > $2
+    AND (idle_timeout IS NULL OR last_seen + idle_timeout > $2)
+ORDER BY
+    last_seen DESC;
@@ -0,0 +1,5 @@
+DELETE FROM
+    public.sessions
+WHERE
+    expires <= $1
+    OR (idle_timeout IS NOT NULL AND last_seen + idle_timeout <= $1);
@@ -0,0 +1,4 @@
+DELETE FROM
+    public.sessions
+WHERE
+    user_id = $1 AND "key" <> $2;
@@ -0,0 +1,4 @@
+DELETE FROM
+    public.sessions
+WHERE
+    id = $1 AND user_id = $2;
@@ -0,0 +1,7 @@
+UPDATE
+    public.users
+SET
+    totp_last_step = $2
+WHERE
+    id = $1 AND (totp_last_step IS NULL OR totp_last_step < $2)
+RETURNING $table_fields;
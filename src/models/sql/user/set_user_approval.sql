@@ -0,0 +1,7 @@
+UPDATE
+    public.users
+SET
+    approval = $2, approval_reason = $3
+WHERE
+    id = $1 AND approval = 'pending'
+RETURNING $table_fields;
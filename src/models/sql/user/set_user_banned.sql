@@ -0,0 +1,7 @@
+UPDATE
+    public.users
+SET
+    banned = $2
+WHERE
+    id = $1
+RETURNING $table_fields;
@@ -0,0 +1,7 @@
+UPDATE
+    public.users
+SET
+    totp_secret = $2, totp_last_step = $3
+WHERE
+    id = $1
+RETURNING $table_fields;
@@ -0,0 +1,6 @@
+UPDATE
+    public.sessions
+SET
+    last_seen = $2
+WHERE
+    "key" = $1 AND last_seen < $2::BIGINT - $3::BIGINT;
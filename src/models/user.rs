@@ -4,12 +4,14 @@ use deadpool_postgres::{Client, GenericClient};
 use rand::Rng;
 use rs_sha512::{HasherContext, Sha512State};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use tokio_pg_mapper::FromTokioPostgresRow;
 use tokio_pg_mapper::tokio_pg_mapper_derive::PostgresMapper;
 use tokio_postgres::{Row, Statement};
 use tokio_postgres::types::ToSql;
 use crate::core::errors::DbError;
 use crate::models;
+use crate::models::roles::{Permission, Role, DEFAULT_ROLE};
 
 #[derive(Clone, Deserialize, PostgresMapper, Serialize)]
 #[pg_mapper(table = "users")] // singular 'user' is a keyword..
@@ -19,20 +21,113 @@ pub struct User {
     pub name: Option<String>,
     pub password_hash: Option<String>,
     pub role: Option<i32>,
+    pub score: Option<i32>,
+    pub banned: Option<bool>,
+    pub email: Option<String>, // для восстановления пароля
+    pub club: Option<String>,
+    pub approval: Option<String>,
+    pub approval_reason: Option<String>,
+    pub registered: Option<i64>, // utc timestamp
+    pub totp_secret: Option<String>, // base32, None -- без второго фактора
+    pub totp_last_step: Option<i64>
+}
+
+/// Решение по заявке на регистрацию, хранится в `users.approval`
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Approval {
+    /// Ждёт решения: войти можно, бронировать нельзя
+    Pending,
+    Approved,
+    /// Войти нельзя
+    Rejected
+}
+
+impl Approval {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Approval::Pending => "pending",
+            Approval::Approved => "approved",
+            Approval::Rejected => "rejected"
+        }
+    }
+
+    fn parse(value: &str) -> Option<Approval> {
+        match value {
+            "pending" => Some(Approval::Pending),
+            "approved" => Some(Approval::Approved),
+            "rejected" => Some(Approval::Rejected),
+            _ => None
+        }
+    }
+}
+
+impl User {
+    /// Роль пользователя; без роли или с неизвестной -- гость
+    pub(crate) fn role(&self) -> Role {
+        self.role.and_then(Role::from_id).unwrap_or(Role::Guest)
+    }
+
+    /// Состояние заявки; у пользователей до модерации её не было, они одобрены
+    pub(crate) fn approval(&self) -> Approval {
+        self.approval.as_deref().and_then(Approval::parse).unwrap_or(Approval::Approved)
+    }
+
+    /// Есть ли право у роли пользователя. Пока заявка не одобрена, прав нет никаких.
+    pub(crate) fn can(&self, permission: Permission) -> bool {
+        self.approval() == Approval::Approved && self.role().can(permission)
+    }
+
+    /// Включён ли вход с кодом из приложения
+    pub(crate) fn has_totp(&self) -> bool {
+        self.totp_secret.is_some()
+    }
+}
+
+/// Пользователь без секретов, для отдачи наружу
+#[derive(Clone, Deserialize, Serialize, ToSchema)]
+pub struct PublicUser {
+    pub id: Option<i32>,
+    pub login: Option<String>,
+    pub name: Option<String>,
+    pub role: Role,
+    pub role_title: String,
     pub score: Option<i32>
 }
 
+impl From<User> for PublicUser {
+    fn from(user: User) -> Self {
+        let role = user.role();
+        PublicUser {
+            id: user.id,
+            login: user.login,
+            name: user.name,
+            role,
+            role_title: role.title().to_string(),
+            score: user.score
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct UserRegisterForm {
     pub login: String,
     pub name: String,
-    pub password: String
+    pub password: String,
+    /// Необязательна, но без неё не восстановить пароль
+    #[serde(default)]
+    pub email: Option<String>,
+    /// Код приглашения
+    #[serde(default)]
+    pub invite: Option<String>
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct UserLoginForm {
     pub login: String,
-    pub password: String
+    pub password: String,
+    /// Галочка «запомнить меня»: долгая сессия вместо сессии браузера
+    pub remember: Option<String>
 }
 
 pub async fn get_users(client: &Client) -> Result<Vec<User>, DbError> {
@@ -55,11 +150,15 @@ pub async fn add_user(client: &Client, user_info: User) -> Result<User, DbError>
     let _stmt = _stmt.replace("$table_fields", &User::sql_table_fields());
     let stmt = client.prepare(&_stmt).await?;
 
-    let query_params : [&(dyn ToSql + Sync); 4] = [
+    let query_params : [&(dyn ToSql + Sync); 8] = [
         &user_info.login,
         &user_info.name,
         &user_info.password_hash,
-        &user_info.role.unwrap_or(2)
+        &user_info.role.unwrap_or(DEFAULT_ROLE.id()),
+        &user_info.email,
+        &user_info.club,
+        &user_info.approval.as_deref().unwrap_or(Approval::Approved.as_str()),
+        &user_info.registered
     ];
 
     let q = client
@@ -78,12 +177,12 @@ pub async fn add_user(client: &Client, user_info: User) -> Result<User, DbError>
 
 async fn get_user_by(client: &Client,
                      sql:&str,
-                     query_params: [&(dyn ToSql + Sync); 1])
+                     query_params: &[&(dyn ToSql + Sync)])
     -> Result<User, DbError>
 {
     let stmt = sql.replace("$table_fields", &User::sql_table_fields());
     let stmt = client.prepare(&stmt).await?;
-    let query = client.query(&stmt, &query_params);
+    let query = client.query(&stmt, query_params);
     let output = query.await?.pop();
     match output {
         Some(T) => Ok(User::from_row_ref(&T)?),
@@ -93,17 +192,85 @@ async fn get_user_by(client: &Client,
 
 pub async fn get_user_by_id(client: &Client, id:i32) -> Result<User, DbError>
 {
-    return get_user_by(client, include_str!("sql/user/get_user_by_id.sql"), [&id]).await;
+    return get_user_by(client, include_str!("sql/user/get_user_by_id.sql"), &[&id]).await;
 }
 
 pub async fn get_user_by_login(client: &Client, login:&str) -> Result<User, DbError> {
     return get_user_by(client,
                        include_str!("sql/user/get_user_by_login.sql"),
-                       [&login]).await;
+                       &[&login]).await;
 }
 
-pub async fn get_user_by_token(client: &Client, token: &str) -> Result<User, DbError> {
+/// Владелец действующей на момент `now` сессии
+pub async fn get_user_by_token(client: &Client, token: &str, now: i64) -> Result<User, DbError> {
     return get_user_by(client,
                        include_str!("sql/user/get_user_by_token.sql"),
-                       [&token]).await;
-}
\ No newline at end of file
+                       &[&token, &now]).await;
+}
+
+/// Меняет пользователя запросом `sql` с параметрами `(id, значения...)`
+async fn update_user(client: &impl GenericClient, sql: &str, query_params: &[&(dyn ToSql + Sync)])
+    -> Result<User, DbError>
+{
+    let stmt = sql.replace("$table_fields", &User::sql_table_fields());
+    let stmt = client.prepare(&stmt).await?;
+    match client.query(&stmt, query_params).await?.pop() {
+        Some(row) => Ok(User::from_row_ref(&row)?),
+        None => Err(DbError::NotFound)
+    }
+}
+
+pub async fn set_user_role(client: &Client, id: i32, role: Role) -> Result<User, DbError> {
+    update_user(client, include_str!("sql/user/set_user_role.sql"), &[&id, &role.id()]).await
+}
+
+pub async fn set_user_banned(client: &Client, id: i32, banned: bool) -> Result<User, DbError> {
+    update_user(client, include_str!("sql/user/set_user_banned.sql"), &[&id, &banned]).await
+}
+
+pub async fn set_user_password_hash(client: &Client, id: i32, password_hash: &str)
+    -> Result<User, DbError>
+{
+    update_user(client, include_str!("sql/user/set_user_password_hash.sql"), &[&id, &password_hash]).await
+}
+
+pub async fn set_user_email(client: &Client, id: i32, email: Option<&str>) -> Result<User, DbError> {
+    update_user(client, include_str!("sql/user/set_user_email.sql"), &[&id, &email]).await
+}
+
+/// Заявки на регистрацию, ждущие решения, старые сначала
+pub async fn get_pending_users(client: &Client) -> Result<Vec<User>, DbError> {
+    let stmt = include_str!("sql/user/get_pending_users.sql");
+    let stmt = stmt.replace("$table_fields", &User::sql_table_fields());
+    let stmt = client.prepare(&stmt).await?;
+
+    let results = client
+        .query(&stmt, &[])
+        .await?
+        .iter()
+        .map(User::from_row_ref)
+        .collect::<Result<Vec<User>, _>>()?;
+
+    Ok(results)
+}
+
+/// Решение по заявке. Заявка, по которой уже решили, даёт `NotFound`.
+pub async fn set_user_approval(client: &Client, id: i32, approval: Approval, reason: Option<&str>)
+    -> Result<User, DbError>
+{
+    update_user(client, include_str!("sql/user/set_user_approval.sql"),
+                &[&id, &approval.as_str(), &reason]).await
+}
+
+/// Включает второй фактор с секретом `secret` или, с `None`, выключает его
+pub async fn set_user_totp(client: &impl GenericClient, id: i32, secret: Option<&str>, last_step: Option<i64>)
+    -> Result<User, DbError>
+{
+    update_user(client, include_str!("sql/user/set_user_totp.sql"), &[&id, &secret, &last_step]).await
+}
+
+/// Запоминает шаг принятого кода. Шаг не новее запомненного даёт `NotFound`:
+/// код уже кто-то использовал.
+pub async fn set_totp_last_step(client: &Client, id: i32, step: i64) -> Result<User, DbError> {
+    update_user(client, include_str!("sql/user/set_totp_last_step.sql"), &[&id, &step]).await
+}
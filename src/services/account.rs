@@ -0,0 +1,488 @@
+use actix_session::Session;
+use actix_web::{get, post, HttpRequest, HttpResponse, web};
+use actix_web::http::{header, StatusCode};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::AppState;
+use crate::core::{audit, qr, templator, totp, ServiceData};
+use crate::core::audit::{AuditAction, AuditTarget};
+use crate::core::errors::DbError;
+use crate::core::mail::queue_email;
+use crate::core::throttle::{accept_login, allow_reset_request, clear_login_failures, format_wait,
+                            reserve_login};
+use crate::core::users::{check_second_factor, generate_recovery_codes, get_current_user, hash_password,
+                         verify_password, PasswordCheck};
+use crate::models::password_reset::{add_password_reset, get_password_reset,
+                                    remove_user_password_resets, use_password_reset};
+use crate::models::recovery_code::{count_unused_recovery_codes, remove_user_recovery_codes,
+                                   replace_recovery_codes};
+use crate::models::roles::get_totp_required_roles;
+use crate::models::session::{remove_other_user_sessions, remove_user_sessions};
+use crate::models::user::{get_user_by_login, set_user_email, set_user_password_hash, set_user_totp, User};
+use crate::services::users::{is_valid_email, is_valid_password, normalize_email};
+
+#[derive(Deserialize)]
+struct EmailForm {
+    email: String
+}
+
+#[derive(Deserialize)]
+struct PasswordChangeForm {
+    old_password: String,
+    password: String,
+    password_confirm: String
+}
+
+#[derive(Deserialize)]
+struct ForgotForm {
+    login: String
+}
+
+#[derive(Deserialize)]
+struct ResetForm {
+    password: String,
+    password_confirm: String
+}
+
+#[derive(Deserialize)]
+struct ResetPath {
+    token: String
+}
+
+#[derive(Deserialize)]
+struct TotpEnableForm {
+    code: String
+}
+
+#[derive(Deserialize)]
+struct TotpConfirmForm {
+    password: String,
+    /// Для выключения: код из приложения или код восстановления
+    #[serde(default)]
+    code: Option<String>
+}
+
+/// Кем представляемся в приложении-аутентификаторе
+const TOTP_ISSUER: &str = "Клубный дом";
+
+/// Ключ сессии с секретом, который пользователь ещё только добавляет в приложение
+const TOTP_SETUP: &str = "totp_setup";
+
+async fn generate_account_page(service_data: &ServiceData<'_>,
+                               status: StatusCode,
+                               user: &User,
+                               mut data: Value)
+    -> HttpResponse
+{
+    data["email"] = json!(user.email);
+    data["two_factor"] = json!(user.has_totp());
+    templator::render_page(service_data, status, "pages/account", &data, "Аккаунт".into()).await
+}
+
+/// Ошибки нового пароля: `None`, если всё в порядке
+fn new_password_errors(password: &str, confirm: &str) -> Option<Value> {
+    let bad = !is_valid_password(password);
+    let mismatch = password != confirm;
+    (bad || mismatch).then(|| json!({ "password": bad, "confirm": mismatch }))
+}
+
+/// Чем кончилась проверка пароля в настройках аккаунта
+enum PasswordAttempt {
+    Valid,
+    Invalid,
+    /// Пароль верный, а код второго фактора нет
+    BadCode,
+    /// Неудач было слишком много, ждать ещё столько секунд
+    Throttled(i64)
+}
+
+/// Сверяет пароль вошедшего пользователя и, если задан `code`, код второго фактора.
+/// Проверка считается попыткой входа, иначе через формы аккаунта пароль и код
+/// можно было бы подбирать без задержек. Попытка возвращается, только если подошло всё.
+async fn check_account_password(service_data: &mut ServiceData<'_>,
+                                user: &User,
+                                password: &str,
+                                code: Option<&str>)
+    -> Result<PasswordAttempt, DbError>
+{
+    let now = Utc::now().timestamp();
+    let ip = service_data.req.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_default();
+    let login = user.login.as_deref().unwrap_or_default();
+    let wait = reserve_login(&mut service_data.client, &service_data.app_state.login,
+                             &ip, login, now).await?;
+    if wait > 0 {
+        return Ok(PasswordAttempt::Throttled(wait));
+    }
+    if verify_password(user, password).await == PasswordCheck::Invalid {
+        return Ok(PasswordAttempt::Invalid);
+    }
+    if let Some(code) = code {
+        if check_second_factor(&service_data.client, user, code, now).await?.is_none() {
+            return Ok(PasswordAttempt::BadCode);
+        }
+    }
+    if let Err(e) = accept_login(&service_data.client, &ip, login).await {
+        log::warn!("failed to clear login failures for {login}: {e}");
+    }
+    Ok(PasswordAttempt::Valid)
+}
+
+/// Ответ на слишком частые попытки: `Retry-After` подсказывает, сколько ждать
+fn retry_after(mut response: HttpResponse, wait: i64) -> HttpResponse {
+    response.headers_mut().insert(header::RETRY_AFTER, header::HeaderValue::from(wait));
+    response
+}
+
+#[get("/account")]
+async fn account_get(req: HttpRequest, app_state: web::Data<AppState<'_>>, session: Session)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let Ok(user) = get_current_user(&service_data.client, service_data.session.clone()).await else {
+        return Ok(templator::redirect(&service_data.req, "/login"));
+    };
+    Ok(generate_account_page(&service_data, StatusCode::OK, &user, json!({})).await)
+}
+
+#[post("/account/email")]
+async fn account_email(req: HttpRequest,
+                       app_state: web::Data<AppState<'_>>,
+                       session: Session,
+                       params: web::Form<EmailForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let Ok(user @ User { id: Some(user_id), .. }) =
+        get_current_user(&service_data.client, service_data.session.clone()).await else {
+        return Ok(templator::redirect(&service_data.req, "/login"));
+    };
+    let email = normalize_email(Some(&params.email));
+    if email.as_deref().is_some_and(|email| !is_valid_email(email)) {
+        return Ok(generate_account_page(&service_data, StatusCode::BAD_REQUEST, &user,
+                                        json!({ "errors": { "email": true } })).await);
+    }
+    let updated = set_user_email(&service_data.client, user_id, email.as_deref()).await?;
+    audit::record(&service_data, Some(&user), AuditAction::EmailChanged, AuditTarget::User(user_id),
+                  Some(json!({ "email": user.email })), Some(json!({ "email": updated.email }))).await;
+    templator::push_flash(&service_data.session, "Почта сохранена");
+    Ok(templator::redirect(&service_data.req, "/account"))
+}
+
+/// Смена пароля со знанием старого. Все сессии, кроме текущей, закрываются.
+#[post("/account/password")]
+async fn account_password(req: HttpRequest,
+                          app_state: web::Data<AppState<'_>>,
+                          session: Session,
+                          params: web::Form<PasswordChangeForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let mut service_data = ServiceData::new(req, app_state, session).await?;
+    let Ok(user @ User { id: Some(user_id), .. }) =
+        get_current_user(&service_data.client, service_data.session.clone()).await else {
+        return Ok(templator::redirect(&service_data.req, "/login"));
+    };
+    match check_account_password(&mut service_data, &user, &params.old_password, None).await? {
+        PasswordAttempt::Valid => {},
+        PasswordAttempt::Invalid | PasswordAttempt::BadCode =>
+            return Ok(generate_account_page(&service_data, StatusCode::BAD_REQUEST, &user,
+                                            json!({ "errors": { "old_password": true } })).await),
+        PasswordAttempt::Throttled(wait) => {
+            let data = json!({ "errors": { "throttled": true }, "wait": format_wait(wait) });
+            let response = generate_account_page(&service_data, StatusCode::TOO_MANY_REQUESTS,
+                                                 &user, data).await;
+            return Ok(retry_after(response, wait));
+        }
+    }
+    if let Some(errors) = new_password_errors(&params.password, &params.password_confirm) {
+        return Ok(generate_account_page(&service_data, StatusCode::BAD_REQUEST, &user,
+                                        json!({ "errors": errors })).await);
+    }
+
+    let hash = hash_password(&params.password).await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    set_user_password_hash(&service_data.client, user_id, &hash).await?;
+    let current: String = service_data.session.get("token").ok().flatten().unwrap_or_default();
+    let closed = remove_other_user_sessions(&service_data.client, user_id, &current).await?;
+    remove_user_password_resets(&service_data.client, user_id).await?;
+    audit::record(&service_data, Some(&user), AuditAction::PasswordChanged, AuditTarget::User(user_id),
+                  None, Some(json!({ "sessions_closed": closed }))).await;
+    templator::push_flash(&service_data.session, "Пароль изменён, остальные сессии закрыты");
+    Ok(templator::redirect(&service_data.req, "/account"))
+}
+
+#[get("/password/forgot")]
+async fn password_forgot_get(req: HttpRequest, app_state: web::Data<AppState<'_>>, session: Session)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    Ok(templator::render_page(&service_data, StatusCode::OK, "pages/password_forgot",
+                              &json!({}), "Восстановление пароля".into()).await)
+}
+
+/// Отправляет ссылку для сброса на почту пользователя. Ответ одинаковый,
+/// есть такой логин, почта у него или нет. С одного адреса ссылок много не запросить,
+/// чтобы через форму нельзя было заваливать письмами.
+#[post("/password/forgot")]
+async fn password_forgot_post(req: HttpRequest,
+                              app_state: web::Data<AppState<'_>>,
+                              session: Session,
+                              params: web::Form<ForgotForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let ip = service_data.req.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_default();
+    let now = Utc::now().timestamp();
+    if !allow_reset_request(&service_data.client, &service_data.app_state.login, &ip, now).await? {
+        let response = templator::render_page(&service_data, StatusCode::TOO_MANY_REQUESTS,
+                                              "pages/password_forgot", &json!({ "throttled": true }),
+                                              "Восстановление пароля".into()).await;
+        return Ok(retry_after(response, service_data.app_state.login.window));
+    }
+    let user = match get_user_by_login(&service_data.client, params.login.trim()).await {
+        Ok(user) => Some(user),
+        Err(DbError::NotFound) => None,
+        Err(e) => return Err(e.into())
+    };
+    if let Some(User { id: Some(user_id), email: Some(email), name, .. }) = user
+        .filter(|user| user.banned != Some(true))
+    {
+        let config = &service_data.app_state;
+        let ttl = config.login.reset_token_ttl;
+        let token = add_password_reset(&service_data.client, user_id, now, ttl).await?;
+        let data = json!({
+            "name": name,
+            "ttl": format_wait(ttl),
+            "link": format!("{}/password/reset/{token}", config.notify.public_url.trim_end_matches('/'))
+        });
+        // ошибку не показываем: ответ должен быть одинаковым для всех
+        if let Err(e) = queue_email(&service_data, &email, "Сброс пароля", "password_reset", &data).await {
+            log::warn!("failed to queue password reset email for user {user_id}: {e}");
+        }
+        audit::record(&service_data, None, AuditAction::PasswordResetRequested,
+                      AuditTarget::User(user_id), None, None).await;
+    }
+    Ok(templator::render_page(&service_data, StatusCode::OK, "pages/password_forgot",
+                              &json!({ "sent": true }), "Восстановление пароля".into()).await)
+}
+
+#[get("/password/reset/{token}")]
+async fn password_reset_get(req: HttpRequest,
+                            app_state: web::Data<AppState<'_>>,
+                            session: Session,
+                            path: web::Path<ResetPath>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let (status, data) = match get_password_reset(&service_data.client, &path.token,
+                                                  Utc::now().timestamp()).await {
+        Ok(_) => (StatusCode::OK, json!({ "token": path.token })),
+        Err(DbError::NotFound) => (StatusCode::NOT_FOUND, json!({ "invalid": true })),
+        Err(e) => return Err(e.into())
+    };
+    Ok(templator::render_page(&service_data, status, "pages/password_reset", &data,
+                              "Новый пароль".into()).await)
+}
+
+/// Задаёт новый пароль по одноразовому токену и закрывает все сессии пользователя
+#[post("/password/reset/{token}")]
+async fn password_reset_post(req: HttpRequest,
+                             app_state: web::Data<AppState<'_>>,
+                             session: Session,
+                             path: web::Path<ResetPath>,
+                             params: web::Form<ResetForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    // проверяем и хешируем пароль до того, как потратить токен
+    if let Some(errors) = new_password_errors(&params.password, &params.password_confirm) {
+        return Ok(templator::render_page(&service_data, StatusCode::BAD_REQUEST, "pages/password_reset",
+                                         &json!({ "token": path.token, "errors": errors }),
+                                         "Новый пароль".into()).await);
+    }
+    let hash = hash_password(&params.password).await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let reset = match use_password_reset(&service_data.client, &path.token, Utc::now().timestamp()).await {
+        Ok(reset) => reset,
+        Err(DbError::NotFound) => return Ok(templator::render_page(
+            &service_data, StatusCode::NOT_FOUND, "pages/password_reset",
+            &json!({ "invalid": true }), "Новый пароль".into()).await),
+        Err(e) => return Err(e.into())
+    };
+    let user_id = reset.user_id.unwrap_or_default();
+    let user = set_user_password_hash(&service_data.client, user_id, &hash).await?;
+    remove_user_sessions(&service_data.client, user.clone()).await?;
+    remove_user_password_resets(&service_data.client, user_id).await?;
+    clear_login_failures(&service_data.client, user.login.as_deref().unwrap_or_default()).await?;
+    audit::record(&service_data, Some(&user), AuditAction::UserPasswordReset, AuditTarget::User(user_id),
+                  None, Some(json!({ "via": "email" }))).await;
+    service_data.session.remove("token");
+    templator::push_flash(&service_data.session, "Пароль изменён, входи с новым");
+    Ok(templator::redirect(&service_data.req, "/login"))
+}
+
+/// Страница второго фактора. Пока он не включён, показывает QR-код нового
+/// секрета; секрет живёт в сессии, пока его не подтвердят кодом.
+async fn generate_two_factor_page(service_data: &ServiceData<'_>,
+                                  status: StatusCode,
+                                  user: &User,
+                                  mut data: Value)
+    -> actix_web::Result<HttpResponse>
+{
+    let user_id = user.id.unwrap_or_default();
+    data["required"] = json!(get_totp_required_roles(&service_data.client).await?.contains(&user.role()));
+    if user.has_totp() {
+        data["enabled"] = json!(true);
+        data["recovery_left"] = json!(count_unused_recovery_codes(&service_data.client, user_id).await?);
+    } else {
+        let secret = match service_data.session.get::<String>(TOTP_SETUP).ok().flatten() {
+            Some(secret) => secret,
+            None => {
+                let secret = totp::generate_secret();
+                service_data.session.insert(TOTP_SETUP, &secret)?;
+                secret
+            }
+        };
+        let uri = totp::otpauth_uri(TOTP_ISSUER, user.login.as_deref().unwrap_or_default(), &secret);
+        data["qr"] = json!(qr::qr_svg(&uri));
+        data["secret"] = json!(secret);
+    }
+    Ok(templator::render_page(service_data, status, "pages/two_factor", &data,
+                              "Вход с кодом".into()).await)
+}
+
+#[get("/account/two-factor")]
+async fn two_factor_get(req: HttpRequest, app_state: web::Data<AppState<'_>>, session: Session)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let Ok(user) = get_current_user(&service_data.client, service_data.session.clone()).await else {
+        return Ok(templator::redirect(&service_data.req, "/login"));
+    };
+    generate_two_factor_page(&service_data, StatusCode::OK, &user, json!({})).await
+}
+
+/// Включает второй фактор, если код из приложения подходит к секрету из сессии.
+/// Коды восстановления показываются один раз, в ответе.
+#[post("/account/two-factor/enable")]
+async fn two_factor_enable(req: HttpRequest,
+                           app_state: web::Data<AppState<'_>>,
+                           session: Session,
+                           params: web::Form<TotpEnableForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let mut service_data = ServiceData::new(req, app_state, session).await?;
+    let Ok(user @ User { id: Some(user_id), .. }) =
+        get_current_user(&service_data.client, service_data.session.clone()).await else {
+        return Ok(templator::redirect(&service_data.req, "/login"));
+    };
+    if user.has_totp() {
+        return Ok(templator::redirect(&service_data.req, "/account/two-factor"));
+    }
+    let secret: Option<String> = service_data.session.get(TOTP_SETUP).ok().flatten();
+    let now = Utc::now().timestamp();
+    let Some((secret, step)) = secret
+        .and_then(|secret| totp::verify(&secret, &params.code, now, None).map(|step| (secret, step))) else {
+        return generate_two_factor_page(&service_data, StatusCode::BAD_REQUEST, &user,
+                                        json!({ "errors": { "code": true } })).await;
+    };
+
+    let (codes, hashes) = generate_recovery_codes().await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    // без кодов восстановления второй фактор не включаем
+    let transaction = service_data.client.transaction().await.map_err(DbError::from)?;
+    let user = set_user_totp(&transaction, user_id, Some(&secret), Some(step)).await?;
+    replace_recovery_codes(&transaction, user_id, &hashes).await?;
+    transaction.commit().await.map_err(DbError::from)?;
+    service_data.session.remove(TOTP_SETUP);
+    audit::record(&service_data, Some(&user), AuditAction::TwoFactorEnabled, AuditTarget::User(user_id),
+                  None, None).await;
+    generate_two_factor_page(&service_data, StatusCode::OK, &user,
+                             json!({ "recovery_codes": codes })).await
+}
+
+/// Выключает второй фактор: нужны пароль и код. Если роли второй фактор
+/// обязателен, выключить его нельзя.
+#[post("/account/two-factor/disable")]
+async fn two_factor_disable(req: HttpRequest,
+                            app_state: web::Data<AppState<'_>>,
+                            session: Session,
+                            params: web::Form<TotpConfirmForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let mut service_data = ServiceData::new(req, app_state, session).await?;
+    let Ok(user @ User { id: Some(user_id), .. }) =
+        get_current_user(&service_data.client, service_data.session.clone()).await else {
+        return Ok(templator::redirect(&service_data.req, "/login"));
+    };
+    if !user.has_totp() {
+        return Ok(templator::redirect(&service_data.req, "/account/two-factor"));
+    }
+    if get_totp_required_roles(&service_data.client).await?.contains(&user.role()) {
+        return generate_two_factor_page(&service_data, StatusCode::FORBIDDEN, &user, json!({})).await;
+    }
+    let code = params.code.as_deref().unwrap_or_default();
+    match check_account_password(&mut service_data, &user, &params.password, Some(code)).await? {
+        PasswordAttempt::Valid => {},
+        PasswordAttempt::Invalid =>
+            return generate_two_factor_page(&service_data, StatusCode::BAD_REQUEST, &user,
+                                            json!({ "errors": { "password": true } })).await,
+        PasswordAttempt::BadCode =>
+            return generate_two_factor_page(&service_data, StatusCode::BAD_REQUEST, &user,
+                                            json!({ "errors": { "code": true } })).await,
+        PasswordAttempt::Throttled(wait) => {
+            let data = json!({ "errors": { "throttled": true }, "wait": format_wait(wait) });
+            let response = generate_two_factor_page(&service_data, StatusCode::TOO_MANY_REQUESTS,
+                                                    &user, data).await?;
+            return Ok(retry_after(response, wait));
+        }
+    }
+
+    let transaction = service_data.client.transaction().await.map_err(DbError::from)?;
+    set_user_totp(&transaction, user_id, None, None).await?;
+    remove_user_recovery_codes(&transaction, user_id).await?;
+    transaction.commit().await.map_err(DbError::from)?;
+    audit::record(&service_data, Some(&user), AuditAction::TwoFactorDisabled, AuditTarget::User(user_id),
+                  None, None).await;
+    templator::push_flash(&service_data.session, "Вход с кодом выключен");
+    Ok(templator::redirect(&service_data.req, "/account"))
+}
+
+/// Новые коды восстановления взамен всех прежних
+#[post("/account/two-factor/recovery")]
+async fn two_factor_recovery(req: HttpRequest,
+                             app_state: web::Data<AppState<'_>>,
+                             session: Session,
+                             params: web::Form<TotpConfirmForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let mut service_data = ServiceData::new(req, app_state, session).await?;
+    let Ok(user @ User { id: Some(user_id), .. }) =
+        get_current_user(&service_data.client, service_data.session.clone()).await else {
+        return Ok(templator::redirect(&service_data.req, "/login"));
+    };
+    if !user.has_totp() {
+        return Ok(templator::redirect(&service_data.req, "/account/two-factor"));
+    }
+    match check_account_password(&mut service_data, &user, &params.password, None).await? {
+        PasswordAttempt::Valid => {},
+        PasswordAttempt::Invalid | PasswordAttempt::BadCode =>
+            return generate_two_factor_page(&service_data, StatusCode::BAD_REQUEST, &user,
+                                            json!({ "errors": { "recovery_password": true } })).await,
+        PasswordAttempt::Throttled(wait) => {
+            let data = json!({ "errors": { "recovery_throttled": true }, "wait": format_wait(wait) });
+            let response = generate_two_factor_page(&service_data, StatusCode::TOO_MANY_REQUESTS,
+                                                    &user, data).await?;
+            return Ok(retry_after(response, wait));
+        }
+    }
+    let (codes, hashes) = generate_recovery_codes().await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let transaction = service_data.client.transaction().await.map_err(DbError::from)?;
+    replace_recovery_codes(&transaction, user_id, &hashes).await?;
+    transaction.commit().await.map_err(DbError::from)?;
+    audit::record(&service_data, Some(&user), AuditAction::RecoveryCodesRegenerated,
+                  AuditTarget::User(user_id), None, None).await;
+    generate_two_factor_page(&service_data, StatusCode::OK, &user,
+                             json!({ "recovery_codes": codes })).await
+}
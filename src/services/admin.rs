@@ -0,0 +1,853 @@
+use std::collections::HashMap;
+use actix_session::Session;
+use actix_web::{get, post, HttpRequest, HttpResponse, web};
+use actix_web::http::StatusCode;
+use chrono::Utc;
+use rand::distributions::{Alphanumeric, DistString};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::AppState;
+use crate::core::{audit, events, templator, ServiceData};
+use crate::core::access::{Authorized, CanManageBookings, CanManageInvites, CanManageSeats,
+                          CanManageUsers, CanPurgeAudit, CanViewAudit};
+use crate::core::audit::{snapshot, AuditAction, AuditTarget};
+use crate::core::errors::DbError;
+use crate::core::events::SeatEventReason;
+use crate::core::mail::queue_email;
+use crate::core::throttle::{clear_login_failures, login_subject};
+use crate::core::users::hash_password;
+use crate::models::api_token::remove_user_api_tokens;
+use crate::models::audit::{get_audit_records, purge_audit_records, AuditFilter};
+use crate::models::invite::{add_invite, get_invite_by_id, get_invite_uses, get_invites,
+                            revoke_invite, Invite};
+use crate::models::login_attempt::get_locked_logins;
+use crate::models::booking::{get_booking_by_id, get_upcoming_bookings, remove_booking,
+                             set_booking_user};
+use crate::models::recovery_code::remove_user_recovery_codes;
+use crate::models::roles::{get_totp_required_roles, set_role_totp_required, Permission, Role, DEFAULT_ROLE};
+use crate::models::seat::{add_seat, get_all_seats, get_seat_by_id, remove_seat, update_seat,
+                          Seat, SeatForm};
+use crate::models::session::remove_user_sessions;
+use crate::models::user::{get_pending_users, get_user_by_id, get_user_by_login, get_users,
+                          set_user_approval, set_user_banned, set_user_password_hash, set_user_role,
+                          set_user_totp, Approval, User};
+use crate::services::booking::format_timestamp;
+
+/// Длина пароля, который выдаётся при сбросе
+const RESET_PASSWORD_LENGTH: usize = 12;
+
+/// Сколько записей журнала показывать за раз
+const AUDIT_PAGE_SIZE: i64 = 200;
+
+/// Меньше скольких дней журнал хранить нельзя; то же ограничение проверяет `audit_log_purge`
+const AUDIT_MIN_KEEP_DAYS: i32 = 30;
+
+#[derive(Deserialize)]
+struct IdPath {
+    id: i32
+}
+
+#[derive(Deserialize)]
+struct RoleForm {
+    role: Role
+}
+
+#[derive(Deserialize)]
+struct RolePath {
+    role: Role
+}
+
+#[derive(Deserialize)]
+struct RequiredForm {
+    required: bool
+}
+
+#[derive(Deserialize)]
+struct BanForm {
+    banned: bool
+}
+
+#[derive(Deserialize)]
+struct ReassignForm {
+    login: String
+}
+
+#[derive(Deserialize)]
+struct PurgeForm {
+    keep_days: i32
+}
+
+/// Фильтр журнала в том виде, в каком его присылает форма: пустые поля -- пустые строки
+#[derive(Default, Deserialize, Serialize)]
+struct AuditQuery {
+    actor: Option<String>,
+    action: Option<String>,
+    target_type: Option<String>,
+    target_id: Option<String>
+}
+
+/// Логин пользователя для сообщений
+fn actor(user: &User) -> &str {
+    user.login.as_deref().unwrap_or_default()
+}
+
+#[get("/admin")]
+async fn admin_index(req: HttpRequest,
+                     session: Session,
+                     app_state: web::Data<AppState<'_>>,
+                     authorized: Authorized<CanManageBookings>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let role = authorized.user.role();
+    let pending = match role.can(Permission::ManageUsers) {
+        true => get_pending_users(&service_data.client).await?.len(),
+        false => 0
+    };
+    Ok(templator::render_page(&service_data, StatusCode::OK, "pages/admin/index", &json!({
+        "users": role.can(Permission::ManageUsers),
+        "pending": pending,
+        "seats": role.can(Permission::ManageSeats),
+        "bookings": role.can(Permission::ManageBookings),
+        "audit": role.can(Permission::ViewAudit),
+        "invites": role.can(Permission::ManageInvites)
+    }), "Админка".into()).await)
+}
+
+async fn generate_users_page(service_data: &ServiceData<'_>, mut data: Value)
+    -> actix_web::Result<HttpResponse>
+{
+    let locked: HashMap<String, i64> = get_locked_logins(&service_data.client, Utc::now().timestamp())
+        .await?
+        .into_iter()
+        .map(|attempts| (attempts.subject, attempts.locked_until))
+        .collect();
+    let users: Vec<Value> = get_users(&service_data.client).await?
+        .into_iter()
+        .map(|user| json!({
+            "id": user.id,
+            "role": user.role(),
+            "banned": user.banned.unwrap_or_default(),
+            "locked_until": user.login.as_deref()
+                .and_then(|login| locked.get(&login_subject(login)))
+                .map(|until| format_timestamp(*until)),
+            "pending": user.approval() == Approval::Pending,
+            "totp": user.has_totp(),
+            "login": user.login,
+            "name": user.name,
+            "club": user.club
+        }))
+        .collect();
+    let totp_required = get_totp_required_roles(&service_data.client).await?;
+    data["users"] = json!(users);
+    data["roles"] = Role::ALL.iter()
+        .map(|role| json!({
+            "name": role,
+            "title": role.title(),
+            "privileged": role.is_privileged(),
+            "totp_required": totp_required.contains(role)
+        }))
+        .collect();
+    Ok(templator::render_page(service_data, StatusCode::OK, "pages/admin/users", &data,
+                              "Пользователи".into()).await)
+}
+
+#[get("/admin/users")]
+async fn admin_users(req: HttpRequest,
+                     session: Session,
+                     app_state: web::Data<AppState<'_>>,
+                     _authorized: Authorized<CanManageUsers>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    generate_users_page(&service_data, json!({})).await
+}
+
+#[post("/admin/users/{id}/role")]
+async fn admin_user_role(req: HttpRequest,
+                         session: Session,
+                         app_state: web::Data<AppState<'_>>,
+                         authorized: Authorized<CanManageUsers>,
+                         path: web::Path<IdPath>,
+                         params: web::Form<RoleForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    if authorized.user.id == Some(path.id) {
+        templator::push_flash(&service_data.session, "Свою роль поменять нельзя");
+        return Ok(templator::redirect(&service_data.req, "/admin/users"));
+    }
+    let before = get_user_by_id(&service_data.client, path.id).await?;
+    let user = set_user_role(&service_data.client, path.id, params.role).await?;
+    audit::record(&service_data, Some(&authorized.user), AuditAction::UserRoleChanged,
+                  AuditTarget::User(path.id),
+                  Some(json!({ "role": before.role() })), Some(json!({ "role": user.role() }))).await;
+    templator::push_flash(&service_data.session,
+                          &format!("{} теперь {}", actor(&user), params.role.title()));
+    Ok(templator::redirect(&service_data.req, "/admin/users"))
+}
+
+#[post("/admin/users/{id}/ban")]
+async fn admin_user_ban(req: HttpRequest,
+                        session: Session,
+                        app_state: web::Data<AppState<'_>>,
+                        authorized: Authorized<CanManageUsers>,
+                        path: web::Path<IdPath>,
+                        params: web::Form<BanForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    if authorized.user.id == Some(path.id) {
+        templator::push_flash(&service_data.session, "Себя заблокировать нельзя");
+        return Ok(templator::redirect(&service_data.req, "/admin/users"));
+    }
+    let before = get_user_by_id(&service_data.client, path.id).await?;
+    let user = set_user_banned(&service_data.client, path.id, params.banned).await?;
+    if params.banned {
+        remove_user_sessions(&service_data.client, user.clone()).await?;
+        remove_user_api_tokens(&service_data.client, path.id).await?;
+    }
+    audit::record(&service_data, Some(&authorized.user),
+                  if params.banned { AuditAction::UserBanned } else { AuditAction::UserUnbanned },
+                  AuditTarget::User(path.id),
+                  Some(json!({ "banned": before.banned })), Some(json!({ "banned": user.banned }))).await;
+    templator::push_flash(&service_data.session, &if params.banned {
+        format!("{} заблокирован", actor(&user))
+    } else {
+        format!("{} разблокирован", actor(&user))
+    });
+    Ok(templator::redirect(&service_data.req, "/admin/users"))
+}
+
+/// Снимает задержку и блокировку входа под логином пользователя
+#[post("/admin/users/{id}/unlock")]
+async fn admin_user_unlock(req: HttpRequest,
+                           session: Session,
+                           app_state: web::Data<AppState<'_>>,
+                           authorized: Authorized<CanManageUsers>,
+                           path: web::Path<IdPath>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let user = get_user_by_id(&service_data.client, path.id).await?;
+    clear_login_failures(&service_data.client, user.login.as_deref().unwrap_or_default()).await?;
+    audit::record(&service_data, Some(&authorized.user), AuditAction::UserUnlocked,
+                  AuditTarget::User(path.id), None, None).await;
+    templator::push_flash(&service_data.session, &format!("{} снова может войти", actor(&user)));
+    Ok(templator::redirect(&service_data.req, "/admin/users"))
+}
+
+/// Выдаёт пользователю новый случайный пароль и выкидывает его из всех сессий.
+/// Пароль показывается администратору один раз.
+#[post("/admin/users/{id}/password")]
+async fn admin_user_password(req: HttpRequest,
+                             session: Session,
+                             app_state: web::Data<AppState<'_>>,
+                             authorized: Authorized<CanManageUsers>,
+                             path: web::Path<IdPath>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let password = Alphanumeric.sample_string(&mut rand::thread_rng(), RESET_PASSWORD_LENGTH);
+    let hash = hash_password(&password).await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let user = set_user_password_hash(&service_data.client, path.id, &hash).await?;
+    remove_user_sessions(&service_data.client, user.clone()).await?;
+    audit::record(&service_data, Some(&authorized.user), AuditAction::UserPasswordReset,
+                  AuditTarget::User(path.id), None, None).await;
+    generate_users_page(&service_data, json!({
+        "reset": { "login": user.login, "password": password }
+    })).await
+}
+
+/// Выключает пользователю второй фактор, например, если он потерял телефон.
+/// Коды восстановления пропадают вместе с ним.
+#[post("/admin/users/{id}/two-factor/reset")]
+async fn admin_user_two_factor_reset(req: HttpRequest,
+                                     session: Session,
+                                     app_state: web::Data<AppState<'_>>,
+                                     authorized: Authorized<CanManageUsers>,
+                                     path: web::Path<IdPath>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let user = set_user_totp(&service_data.client, path.id, None, None).await?;
+    remove_user_recovery_codes(&service_data.client, path.id).await?;
+    audit::record(&service_data, Some(&authorized.user), AuditAction::TwoFactorDisabled,
+                  AuditTarget::User(path.id), None, None).await;
+    templator::push_flash(&service_data.session,
+                          &format!("{} снова входит только по паролю", actor(&user)));
+    Ok(templator::redirect(&service_data.req, "/admin/users"))
+}
+
+/// Требует второй фактор от всех с ролью или снимает требование.
+/// Потребовать можно только от ролей, которые могут больше, чем бронировать себе.
+#[post("/admin/roles/{role}/two-factor")]
+async fn admin_role_two_factor(req: HttpRequest,
+                               session: Session,
+                               app_state: web::Data<AppState<'_>>,
+                               authorized: Authorized<CanManageUsers>,
+                               path: web::Path<RolePath>,
+                               params: web::Form<RequiredForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    if params.required && !path.role.is_privileged() {
+        templator::push_flash(&service_data.session, "Этой роли код не нужен: она может только бронировать");
+        return Ok(templator::redirect(&service_data.req, "/admin/users"));
+    }
+    let before = get_totp_required_roles(&service_data.client).await?.contains(&path.role);
+    set_role_totp_required(&service_data.client, path.role, params.required).await?;
+    audit::record(&service_data, Some(&authorized.user), AuditAction::RoleTwoFactorChanged,
+                  AuditTarget::Nothing,
+                  Some(json!({ "role": path.role, "required": before })),
+                  Some(json!({ "role": path.role, "required": params.required }))).await;
+    let message = if params.required { "теперь входит только с кодом" } else { "может входить без кода" };
+    templator::push_flash(&service_data.session, &format!("Роль «{}» {message}", path.role.title()));
+    Ok(templator::redirect(&service_data.req, "/admin/users"))
+}
+
+/// Разбирает форму места. Положение задаётся сразу и как положение по умолчанию.
+fn parse_seat_form(form: &SeatForm) -> Option<Seat> {
+    let name = form.name.trim();
+    if name.is_empty() {
+        return None;
+    }
+    Some(Seat {
+        id: None,
+        name: Some(name.to_string()),
+        seat_type: Some(form.seat_type.parse().ok()?),
+        availability: Some(form.availability.parse().ok()?),
+        default_x: Some(form.x),
+        default_y: Some(form.y),
+        default_rot: Some(form.rot.unwrap_or_default()),
+        x: None,
+        y: None,
+        rot: None,
+        amenities: Some(form.amenities.as_deref().unwrap_or_default()
+            .split(',')
+            .map(|a| a.trim().to_string())
+            .filter(|a| !a.is_empty())
+            .collect())
+    })
+}
+
+#[get("/admin/seats")]
+async fn admin_seats(req: HttpRequest,
+                     session: Session,
+                     app_state: web::Data<AppState<'_>>,
+                     _authorized: Authorized<CanManageSeats>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let seats: Vec<Value> = get_all_seats(&service_data.client).await?
+        .into_iter()
+        .map(|seat| json!({
+            "id": seat.id,
+            "name": seat.name,
+            "seat_type": seat.seat_type.map(|t| t.to_string()),
+            "availability": seat.availability.map(|a| a.to_string()),
+            "x": seat.default_x,
+            "y": seat.default_y,
+            "rot": seat.default_rot,
+            "amenities": seat.amenities.unwrap_or_default().join(", ")
+        }))
+        .collect();
+    Ok(templator::render_page(&service_data, StatusCode::OK, "pages/admin/seats",
+                              &json!({ "seats": seats }), "Места".into()).await)
+}
+
+#[post("/admin/seats")]
+async fn admin_seat_add(req: HttpRequest,
+                        session: Session,
+                        app_state: web::Data<AppState<'_>>,
+                        authorized: Authorized<CanManageSeats>,
+                        params: web::Form<SeatForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let Some(seat) = parse_seat_form(&params) else {
+        templator::push_flash(&service_data.session, "Не понял, что за место");
+        return Ok(templator::redirect(&service_data.req, "/admin/seats"));
+    };
+    let seat = add_seat(&service_data.client, &seat).await?;
+    audit::record(&service_data, Some(&authorized.user), AuditAction::SeatCreated,
+                  AuditTarget::Seat(seat.id.unwrap_or_default()), None, snapshot(&seat)).await;
+    templator::push_flash(&service_data.session,
+                          &format!("Место «{}» добавлено", seat.name.unwrap_or_default()));
+    Ok(templator::redirect(&service_data.req, "/admin/seats"))
+}
+
+#[post("/admin/seats/{id}")]
+async fn admin_seat_edit(req: HttpRequest,
+                         session: Session,
+                         app_state: web::Data<AppState<'_>>,
+                         authorized: Authorized<CanManageSeats>,
+                         path: web::Path<IdPath>,
+                         params: web::Form<SeatForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let Some(seat) = parse_seat_form(&params) else {
+        templator::push_flash(&service_data.session, "Не понял, что за место");
+        return Ok(templator::redirect(&service_data.req, "/admin/seats"));
+    };
+    let before = get_seat_by_id(&service_data.client, path.id).await?;
+    let seat = update_seat(&service_data.client, path.id, &seat).await?;
+    audit::record(&service_data, Some(&authorized.user), AuditAction::SeatUpdated,
+                  AuditTarget::Seat(path.id), snapshot(&before), snapshot(&seat)).await;
+    if let Err(e) = events::seat_changed(&service_data.client, &service_data.app_state.seat_events,
+                                         path.id, SeatEventReason::Updated).await {
+        log::warn!("failed to publish seat {} change: {e}", path.id);
+    }
+    templator::push_flash(&service_data.session,
+                          &format!("Место «{}» сохранено", seat.name.unwrap_or_default()));
+    Ok(templator::redirect(&service_data.req, "/admin/seats"))
+}
+
+#[post("/admin/seats/{id}/delete")]
+async fn admin_seat_delete(req: HttpRequest,
+                           session: Session,
+                           app_state: web::Data<AppState<'_>>,
+                           authorized: Authorized<CanManageSeats>,
+                           path: web::Path<IdPath>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let seat = get_seat_by_id(&service_data.client, path.id).await?;
+    // брони не должны пропадать молча вместе с местом
+    let seat = match remove_seat(&service_data.client, path.id, Utc::now().timestamp()).await {
+        Ok(seat) => seat,
+        Err(DbError::Conflict) => {
+            templator::push_flash(&service_data.session, &format!(
+                "У места «{}» есть брони, которые ещё не закончились: сначала отмените их",
+                seat.name.unwrap_or_default()));
+            return Ok(templator::redirect(&service_data.req, "/admin/seats"));
+        },
+        Err(e) => return Err(e.into())
+    };
+    audit::record(&service_data, Some(&authorized.user), AuditAction::SeatDeleted,
+                  AuditTarget::Seat(path.id), snapshot(&seat), None).await;
+    templator::push_flash(&service_data.session,
+                          &format!("Место «{}» удалено", seat.name.unwrap_or_default()));
+    Ok(templator::redirect(&service_data.req, "/admin/seats"))
+}
+
+#[get("/admin/bookings")]
+async fn admin_bookings(req: HttpRequest,
+                        session: Session,
+                        app_state: web::Data<AppState<'_>>,
+                        _authorized: Authorized<CanManageBookings>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let seats: HashMap<i32, String> = get_all_seats(&service_data.client).await?
+        .into_iter()
+        .filter_map(|seat| Some((seat.id?, seat.name.unwrap_or_default())))
+        .collect();
+    let users: HashMap<i32, String> = get_users(&service_data.client).await?
+        .into_iter()
+        .filter_map(|user| Some((user.id?, user.login.unwrap_or_default())))
+        .collect();
+    let bookings: Vec<Value> = get_upcoming_bookings(&service_data.client, Utc::now().timestamp())
+        .await?
+        .into_iter()
+        .map(|booking| json!({
+            "id": booking.id,
+            "seat": booking.seat_id.and_then(|id| seats.get(&id)),
+            "user": booking.user_id.and_then(|id| users.get(&id)),
+            "starts": booking.starts.map(format_timestamp),
+            "ends": booking.ends.map(format_timestamp)
+        }))
+        .collect();
+    Ok(templator::render_page(&service_data, StatusCode::OK, "pages/admin/bookings",
+                              &json!({ "bookings": bookings }), "Брони".into()).await)
+}
+
+#[post("/admin/bookings/{id}/cancel")]
+async fn admin_booking_cancel(req: HttpRequest,
+                              session: Session,
+                              app_state: web::Data<AppState<'_>>,
+                              authorized: Authorized<CanManageBookings>,
+                              path: web::Path<IdPath>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let booking = remove_booking(&service_data.client, path.id).await?;
+    audit::record(&service_data, Some(&authorized.user), AuditAction::BookingCancelled,
+                  AuditTarget::Booking(path.id), snapshot(&booking), None).await;
+    events::booking_changed(&service_data.client, &service_data.app_state.seat_events,
+                            &booking, SeatEventReason::Cancelled).await;
+    templator::push_flash(&service_data.session, "Бронь отменена");
+    Ok(templator::redirect(&service_data.req, "/admin/bookings"))
+}
+
+#[post("/admin/bookings/{id}/reassign")]
+async fn admin_booking_reassign(req: HttpRequest,
+                                session: Session,
+                                app_state: web::Data<AppState<'_>>,
+                                authorized: Authorized<CanManageBookings>,
+                                path: web::Path<IdPath>,
+                                params: web::Form<ReassignForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let login = params.login.trim().trim_start_matches('@');
+    let Ok(user @ User { id: Some(user_id), .. }) = get_user_by_login(&service_data.client, login).await else {
+        templator::push_flash(&service_data.session, &format!("Не нашёл пользователя {login}"));
+        return Ok(templator::redirect(&service_data.req, "/admin/bookings"));
+    };
+    if user.banned == Some(true) || !user.can(Permission::Book) {
+        templator::push_flash(&service_data.session, &format!("Пользователю {login} нельзя бронировать"));
+        return Ok(templator::redirect(&service_data.req, "/admin/bookings"));
+    }
+    let previous = get_booking_by_id(&service_data.client, path.id).await?;
+    let booking = set_booking_user(&service_data.client, path.id, user_id).await?;
+    audit::record(&service_data, Some(&authorized.user), AuditAction::BookingReassigned,
+                  AuditTarget::Booking(path.id), snapshot(&previous), snapshot(&booking)).await;
+    templator::push_flash(&service_data.session, &format!("Бронь передана {login}"));
+    Ok(templator::redirect(&service_data.req, "/admin/bookings"))
+}
+
+fn non_empty(value: &Option<String>) -> Option<String> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(str::to_string)
+}
+
+#[get("/admin/audit")]
+async fn admin_audit(req: HttpRequest,
+                     session: Session,
+                     app_state: web::Data<AppState<'_>>,
+                     authorized: Authorized<CanViewAudit>,
+                     query: web::Query<AuditQuery>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let users: HashMap<i32, String> = get_users(&service_data.client).await?
+        .into_iter()
+        .filter_map(|user| Some((user.id?, user.login.unwrap_or_default())))
+        .collect();
+
+    let mut filter = AuditFilter {
+        actor_id: None,
+        action: non_empty(&query.action),
+        target_type: non_empty(&query.target_type),
+        target_id: non_empty(&query.target_id).and_then(|id| id.parse().ok())
+    };
+    let mut records = Vec::new();
+    let actor = non_empty(&query.actor).map(|login| login.trim_start_matches('@').to_string());
+    let actor_known = match &actor {
+        Some(login) => match users.iter().find(|(_, l)| *l == login) {
+            Some((id, _)) => {
+                filter.actor_id = Some(*id);
+                true
+            },
+            None => false
+        },
+        None => true
+    };
+    if actor_known {
+        records = get_audit_records(&service_data.client, &filter, AUDIT_PAGE_SIZE).await?
+            .into_iter()
+            .map(|record| json!({
+                "id": record.id,
+                "created": record.created.map(format_timestamp),
+                "actor": record.actor_id.and_then(|id| users.get(&id)),
+                "action": record.action,
+                "target_type": record.target_type,
+                "target_id": record.target_id,
+                "before": record.before.map(|v| v.to_string()),
+                "after": record.after.map(|v| v.to_string()),
+                "ip": record.ip
+            }))
+            .collect();
+    }
+
+    let actions: Vec<String> = AuditAction::ALL.iter().map(|a| a.to_string()).collect();
+    Ok(templator::render_page(&service_data, StatusCode::OK, "pages/admin/audit", &json!({
+        "records": records,
+        "form": query.0,
+        "actions": actions,
+        "target_types": AuditTarget::TYPES,
+        "purge": authorized.user.can(Permission::PurgeAudit),
+        "min_keep_days": AUDIT_MIN_KEEP_DAYS
+    }), "Журнал".into()).await)
+}
+
+#[post("/admin/audit/purge")]
+async fn admin_audit_purge(req: HttpRequest,
+                           session: Session,
+                           app_state: web::Data<AppState<'_>>,
+                           authorized: Authorized<CanPurgeAudit>,
+                           form: web::Form<PurgeForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    if form.keep_days < AUDIT_MIN_KEEP_DAYS {
+        templator::push_flash(&service_data.session, &format!(
+            "Журнал нужно хранить хотя бы {AUDIT_MIN_KEEP_DAYS} дней"));
+        return Ok(templator::redirect(&service_data.req, "/admin/audit"));
+    }
+    let removed = purge_audit_records(&service_data.client, form.keep_days).await?;
+    audit::record(&service_data, Some(&authorized.user), AuditAction::AuditPurged,
+                  AuditTarget::Nothing, None,
+                  Some(json!({ "keep_days": form.keep_days, "removed": removed }))).await;
+    templator::push_flash(&service_data.session,
+                          &format!("Удалено записей старше {} дней: {removed}", form.keep_days));
+    Ok(templator::redirect(&service_data.req, "/admin/audit"))
+}
+
+/// Новое приглашение. Пустые поля -- без ограничений.
+#[derive(Deserialize)]
+struct InviteForm {
+    role: Role,
+    club: Option<String>,
+    max_uses: Option<String>,
+    /// Срок действия в днях
+    expires_in: Option<String>
+}
+
+/// Какие роли может выдать приглашением пользователь с ролью `role`:
+/// руководитель клуба зовёт только участников, администратор -- кого угодно
+fn invite_roles(role: Role) -> Vec<Role> {
+    if role.can(Permission::ManageUsers) {
+        Role::ALL.to_vec()
+    } else {
+        vec![Role::Guest, Role::Member]
+    }
+}
+
+/// Приглашения, которые видит `user`: администратор все, остальные свои
+fn invites_owner(user: &User) -> Option<i32> {
+    if user.can(Permission::ManageUsers) { None } else { user.id }
+}
+
+#[get("/admin/invites")]
+async fn admin_invites(req: HttpRequest,
+                       session: Session,
+                       app_state: web::Data<AppState<'_>>,
+                       authorized: Authorized<CanManageInvites>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let now = Utc::now().timestamp();
+    let invites = get_invites(&service_data.client, invites_owner(&authorized.user)).await?;
+    let ids: Vec<i32> = invites.iter().filter_map(|invite| invite.id).collect();
+    let mut uses: HashMap<i32, Vec<Value>> = HashMap::new();
+    for invite_use in get_invite_uses(&service_data.client, &ids).await? {
+        uses.entry(invite_use.invite_id).or_default().push(json!({
+            "login": invite_use.login,
+            "used": format_timestamp(invite_use.used)
+        }));
+    }
+    let users: HashMap<i32, String> = get_users(&service_data.client).await?
+        .into_iter()
+        .filter_map(|user| Some((user.id?, user.login.unwrap_or_default())))
+        .collect();
+    let public_url = service_data.app_state.notify.public_url.trim_end_matches('/').to_string();
+
+    let invites: Vec<Value> = invites.into_iter()
+        .map(|invite| json!({
+            "id": invite.id,
+            "active": invite.is_active(now),
+            "link": format!("{public_url}/register?invite={}", invite.code.as_deref().unwrap_or_default()),
+            "created_by": invite.created_by.and_then(|id| users.get(&id)),
+            "role": invite.role.and_then(Role::from_id).map(|role| role.title()),
+            "uses": invite.uses,
+            "max_uses": invite.max_uses,
+            "created": invite.created.map(format_timestamp),
+            "expires": invite.expires.map(format_timestamp),
+            "revoked": invite.revoked.map(format_timestamp),
+            "used_by": invite.id.and_then(|id| uses.remove(&id)),
+            "code": invite.code,
+            "club": invite.club
+        }))
+        .collect();
+    let roles: Vec<Value> = invite_roles(authorized.user.role()).iter()
+        .map(|role| json!({ "name": role, "title": role.title(), "default": *role == DEFAULT_ROLE }))
+        .collect();
+    Ok(templator::render_page(&service_data, StatusCode::OK, "pages/admin/invites", &json!({
+        "invites": invites,
+        "roles": roles,
+        "club": authorized.user.club
+    }), "Приглашения".into()).await)
+}
+
+/// Заводит приглашение. Руководитель клуба с указанным клубом зовёт только в свой клуб.
+#[post("/admin/invites")]
+async fn admin_invite_add(req: HttpRequest,
+                          session: Session,
+                          app_state: web::Data<AppState<'_>>,
+                          authorized: Authorized<CanManageInvites>,
+                          params: web::Form<InviteForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let creator = &authorized.user;
+    if !invite_roles(creator.role()).contains(&params.role) {
+        templator::push_flash(&service_data.session, "Такую роль ты выдать не можешь");
+        return Ok(templator::redirect(&service_data.req, "/admin/invites"));
+    }
+    let max_uses = non_empty(&params.max_uses).map(|n| n.parse::<i32>());
+    let expires_in = non_empty(&params.expires_in).map(|n| n.parse::<i64>());
+    let (Ok(max_uses), Ok(expires_in)) = (max_uses.transpose(), expires_in.transpose()) else {
+        templator::push_flash(&service_data.session, "Число использований и срок -- целые числа");
+        return Ok(templator::redirect(&service_data.req, "/admin/invites"));
+    };
+    if max_uses.is_some_and(|n| n < 1) || expires_in.is_some_and(|n| n < 1) {
+        templator::push_flash(&service_data.session, "Число использований и срок должны быть больше нуля");
+        return Ok(templator::redirect(&service_data.req, "/admin/invites"));
+    }
+    let club = match (&creator.club, creator.can(Permission::ManageUsers)) {
+        (Some(club), false) => Some(club.clone()),
+        _ => non_empty(&params.club)
+    };
+
+    let now = Utc::now().timestamp();
+    let invite = add_invite(&service_data.client, &Invite {
+        id: None,
+        code: None,
+        created_by: creator.id,
+        role: Some(params.role.id()),
+        club,
+        max_uses,
+        uses: None,
+        created: None,
+        expires: expires_in.map(|days| now + days.saturating_mul(24 * 60 * 60)),
+        revoked: None
+    }, now).await?;
+    audit::record(&service_data, Some(creator), AuditAction::InviteCreated,
+                  AuditTarget::Invite(invite.id.unwrap_or_default()), None, snapshot(&invite)).await;
+    templator::push_flash(&service_data.session,
+                          &format!("Код приглашения: {}", invite.code.unwrap_or_default()));
+    Ok(templator::redirect(&service_data.req, "/admin/invites"))
+}
+
+#[post("/admin/invites/{id}/revoke")]
+async fn admin_invite_revoke(req: HttpRequest,
+                             session: Session,
+                             app_state: web::Data<AppState<'_>>,
+                             authorized: Authorized<CanManageInvites>,
+                             path: web::Path<IdPath>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let before = get_invite_by_id(&service_data.client, path.id).await?;
+    if invites_owner(&authorized.user).is_some_and(|owner| before.created_by != Some(owner)) {
+        return Err(DbError::NotFound.into());
+    }
+    match revoke_invite(&service_data.client, path.id, Utc::now().timestamp()).await {
+        Ok(invite) => {
+            audit::record(&service_data, Some(&authorized.user), AuditAction::InviteRevoked,
+                          AuditTarget::Invite(path.id), snapshot(&before), snapshot(&invite)).await;
+            templator::push_flash(&service_data.session,
+                                  &format!("Код {} отозван", invite.code.unwrap_or_default()));
+        }
+        Err(DbError::NotFound) => templator::push_flash(&service_data.session, "Код уже отозван"),
+        Err(e) => return Err(e.into())
+    }
+    Ok(templator::redirect(&service_data.req, "/admin/invites"))
+}
+
+#[derive(Deserialize)]
+struct RejectForm {
+    reason: String
+}
+
+#[get("/admin/registrations")]
+async fn admin_registrations(req: HttpRequest,
+                             session: Session,
+                             app_state: web::Data<AppState<'_>>,
+                             _authorized: Authorized<CanManageUsers>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let users: Vec<Value> = get_pending_users(&service_data.client).await?
+        .into_iter()
+        .map(|user| json!({
+            "id": user.id,
+            "login": user.login,
+            "name": user.name,
+            "email": user.email,
+            "club": user.club,
+            "registered": user.registered.map(format_timestamp)
+        }))
+        .collect();
+    Ok(templator::render_page(&service_data, StatusCode::OK, "pages/admin/registrations",
+                              &json!({ "users": users }), "Заявки".into()).await)
+}
+
+/// Сообщает пользователю решение по заявке, если у него есть почта
+async fn notify_approval(service_data: &ServiceData<'_>, user: &User) {
+    let Some(email) = &user.email else {
+        return;
+    };
+    let (subject, template) = match user.approval() {
+        Approval::Rejected => ("Заявка на регистрацию отклонена", "registration_rejected"),
+        _ => ("Заявка на регистрацию одобрена", "registration_approved")
+    };
+    let data = json!({
+        "name": user.name,
+        "reason": user.approval_reason,
+        "link": service_data.app_state.notify.public_url.trim_end_matches('/')
+    });
+    if let Err(e) = queue_email(service_data, email, subject, template, &data).await {
+        log::warn!("failed to queue approval email for user {}: {e}", user.id.unwrap_or_default());
+    }
+}
+
+#[post("/admin/registrations/{id}/approve")]
+async fn admin_registration_approve(req: HttpRequest,
+                                    session: Session,
+                                    app_state: web::Data<AppState<'_>>,
+                                    authorized: Authorized<CanManageUsers>,
+                                    path: web::Path<IdPath>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let user = match set_user_approval(&service_data.client, path.id, Approval::Approved, None).await {
+        Ok(user) => user,
+        Err(DbError::NotFound) => {
+            templator::push_flash(&service_data.session, "По этой заявке уже решили");
+            return Ok(templator::redirect(&service_data.req, "/admin/registrations"));
+        }
+        Err(e) => return Err(e.into())
+    };
+    audit::record(&service_data, Some(&authorized.user), AuditAction::RegistrationApproved,
+                  AuditTarget::User(path.id), None, None).await;
+    notify_approval(&service_data, &user).await;
+    templator::push_flash(&service_data.session, &format!("{} принят", actor(&user)));
+    Ok(templator::redirect(&service_data.req, "/admin/registrations"))
+}
+
+/// Отклоняет заявку с объяснением и закрывает сессии пользователя
+#[post("/admin/registrations/{id}/reject")]
+async fn admin_registration_reject(req: HttpRequest,
+                                   session: Session,
+                                   app_state: web::Data<AppState<'_>>,
+                                   authorized: Authorized<CanManageUsers>,
+                                   path: web::Path<IdPath>,
+                                   params: web::Form<RejectForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let reason = params.reason.trim();
+    if reason.is_empty() {
+        templator::push_flash(&service_data.session, "Напиши, почему заявка отклонена");
+        return Ok(templator::redirect(&service_data.req, "/admin/registrations"));
+    }
+    let user = match set_user_approval(&service_data.client, path.id, Approval::Rejected,
+                                       Some(reason)).await {
+        Ok(user) => user,
+        Err(DbError::NotFound) => {
+            templator::push_flash(&service_data.session, "По этой заявке уже решили");
+            return Ok(templator::redirect(&service_data.req, "/admin/registrations"));
+        }
+        Err(e) => return Err(e.into())
+    };
+    remove_user_sessions(&service_data.client, user.clone()).await?;
+    audit::record(&service_data, Some(&authorized.user), AuditAction::RegistrationRejected,
+                  AuditTarget::User(path.id), None, Some(json!({ "reason": reason }))).await;
+    notify_approval(&service_data, &user).await;
+    templator::push_flash(&service_data.session, &format!("Заявка {} отклонена", actor(&user)));
+    Ok(templator::redirect(&service_data.req, "/admin/registrations"))
+}
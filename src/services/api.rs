@@ -0,0 +1,363 @@
+use actix_session::Session;
+use actix_web::{delete, get, post, HttpRequest, HttpResponse, Scope, web};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, Modify, OpenApi, ToSchema};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa_redoc::{Redoc, Servable};
+use crate::AppState;
+use crate::core::{audit, events, search, ServiceData};
+use crate::core::access::{bearer_token, token_user};
+use crate::core::audit::{snapshot, AuditAction, AuditTarget};
+use crate::core::errors::{ApiError, ApiErrorBody, ApiErrorDetails, AuthError, DbError};
+use crate::core::keys::SESSION_COOKIE;
+use crate::core::events::SeatEventReason;
+use crate::core::users::{get_current_user, needs_totp};
+use crate::models::api_token::TokenScope;
+use crate::models::roles::{Permission, Role};
+use crate::models::booking::{add_booking, get_booking_by_id, get_bookings_in_range,
+                             get_upcoming_user_bookings, remove_booking, set_booking_ends,
+                             Booking, BookSeatForm, ExtendBookingForm, MAX_BOOKING_DURATION};
+use crate::models::seat::{get_all_seats, get_seat_by_id, Seat};
+use crate::models::seat::seat_type::SeatType;
+use crate::models::seat::availability_status::AvailabilityStatus;
+use crate::models::user::{PublicUser, User};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Клубный дом", description = "Бронирование мест в клубном доме"),
+    servers((url = "/api/v1")),
+    paths(list_seats, get_seat, get_seat_availability, get_me, list_my_bookings,
+          create_booking, cancel_booking, extend_booking),
+    components(schemas(Seat, SeatType, AvailabilityStatus, SeatAvailability, Booking,
+                       BookSeatForm, ExtendBookingForm, PublicUser, Role,
+                       ApiErrorBody, ApiErrorDetails)),
+    modifiers(&SessionSecurity)
+)]
+pub struct ApiDoc;
+
+/// Схемы авторизации: cookie сессии и токен доступа в `Authorization: Bearer`
+struct SessionSecurity;
+
+impl Modify for SessionSecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme("session",
+                SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::with_description(
+                    SESSION_COOKIE,
+                    "Изменяющим запросам с печенькой нужен заголовок X-CSRF-Token"))));
+            components.add_security_scheme("bearer",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()));
+        }
+    }
+}
+
+/// Описание API, по которому генерируются клиенты
+#[get("/openapi.json")]
+async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+/// Просмотрщик описания API
+pub fn docs() -> Redoc<utoipa::openapi::OpenApi> {
+    Redoc::with_url("/api/docs", ApiDoc::openapi())
+}
+
+/// Все маршруты `/api/v1`. Ошибки разбора запроса тоже отдаются в JSON.
+pub fn scope() -> Scope {
+    web::scope("/api/v1")
+        .app_data(web::JsonConfig::default()
+            .error_handler(|err, _| ApiError::BadRequest(err.to_string()).into()))
+        .app_data(web::QueryConfig::default()
+            .error_handler(|err, _| ApiError::BadRequest(err.to_string()).into()))
+        .app_data(web::PathConfig::default()
+            .error_handler(|err, _| ApiError::BadRequest(err.to_string()).into()))
+        .service(openapi_json)
+        .service(list_seats)
+        .service(get_seat)
+        .service(get_seat_availability)
+        .service(get_me)
+        .service(list_my_bookings)
+        .service(create_booking)
+        .service(cancel_booking)
+        .service(extend_booking)
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AtQuery {
+    /// UTC timestamp, по умолчанию сейчас
+    at: Option<i64>
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+struct IdPath {
+    id: i32
+}
+
+#[derive(Serialize, ToSchema)]
+struct SeatAvailability {
+    seat_id: i32,
+    at: i64,
+    availability: AvailabilityStatus
+}
+
+/// Пользователь, от имени которого сделан запрос, если его роли дано право
+/// `permission`. Токен доступа должен ещё и разрешать это право, без права
+/// хватает области `read`.
+async fn current_user(service_data: &ServiceData<'_>, permission: Option<Permission>)
+    -> Result<User, ApiError>
+{
+    let user = match bearer_token(&service_data.req) {
+        None => get_current_user(&service_data.client, service_data.session.clone())
+            .await
+            .map_err(|_| ApiError::Unauthorized)?,
+        Some(token) => {
+            let scope = permission.map_or(TokenScope::Read, TokenScope::for_permission);
+            token_user(&service_data.client, token, scope).await?
+        }
+    };
+    if needs_totp(&service_data.client, &user).await? {
+        return Err(ApiError::Auth(AuthError::TotpRequired));
+    }
+    if permission.is_some_and(|permission| !user.can(permission)) {
+        return Err(ApiError::Forbidden);
+    }
+    Ok(user)
+}
+
+/// Бронь текущего пользователя; чужие брони для него не существуют
+async fn own_booking(service_data: &ServiceData<'_>, user: &User, id: i32)
+    -> Result<Booking, ApiError>
+{
+    let booking = get_booking_by_id(&service_data.client, id).await?;
+    if booking.user_id != user.id {
+        return Err(DbError::NotFound.into());
+    }
+    Ok(booking)
+}
+
+/// Все места, с доступностью на момент `at`
+#[utoipa::path(
+    params(AtQuery),
+    responses((status = 200, body = [Seat]))
+)]
+#[get("/seats")]
+async fn list_seats(req: HttpRequest,
+               session: Session,
+               app_state: web::Data<AppState<'_>>,
+               query: web::Query<AtQuery>)
+    -> Result<HttpResponse, ApiError>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let at = query.at.unwrap_or_else(|| Utc::now().timestamp());
+    let bookings = get_bookings_in_range(&service_data.client, at, at + 1).await?;
+    let mut seats = get_all_seats(&service_data.client).await?;
+    for seat in seats.iter_mut() {
+        seat.availability = Some(search::availability_at(seat, &bookings, at));
+    }
+    Ok(HttpResponse::Ok().json(seats))
+}
+
+#[utoipa::path(
+    params(IdPath),
+    responses(
+        (status = 200, body = Seat),
+        (status = 404, body = ApiErrorBody)
+    )
+)]
+#[get("/seats/{id}")]
+async fn get_seat(req: HttpRequest,
+              session: Session,
+              app_state: web::Data<AppState<'_>>,
+              path: web::Path<IdPath>)
+    -> Result<HttpResponse, ApiError>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let seat = get_seat_by_id(&service_data.client, path.id).await?;
+    Ok(HttpResponse::Ok().json(seat))
+}
+
+/// Доступность места на момент `at`
+#[utoipa::path(
+    params(IdPath, AtQuery),
+    responses(
+        (status = 200, body = SeatAvailability),
+        (status = 404, body = ApiErrorBody)
+    )
+)]
+#[get("/seats/{id}/availability")]
+async fn get_seat_availability(req: HttpRequest,
+                           session: Session,
+                           app_state: web::Data<AppState<'_>>,
+                           path: web::Path<IdPath>,
+                           query: web::Query<AtQuery>)
+    -> Result<HttpResponse, ApiError>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let at = query.at.unwrap_or_else(|| Utc::now().timestamp());
+    let seat = get_seat_by_id(&service_data.client, path.id).await?;
+    let bookings = get_bookings_in_range(&service_data.client, at, at + 1).await?;
+    Ok(HttpResponse::Ok().json(SeatAvailability {
+        seat_id: path.id,
+        at,
+        availability: search::availability_at(&seat, &bookings, at)
+    }))
+}
+
+/// Текущий пользователь
+#[utoipa::path(
+    responses(
+        (status = 200, body = PublicUser),
+        (status = 401, body = ApiErrorBody),
+        (status = 403, description = "Токену не хватает прав", body = ApiErrorBody)
+    ),
+    security(("session" = []), ("bearer" = []))
+)]
+#[get("/me")]
+async fn get_me(req: HttpRequest, session: Session, app_state: web::Data<AppState<'_>>)
+    -> Result<HttpResponse, ApiError>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let user = current_user(&service_data, None).await?;
+    Ok(HttpResponse::Ok().json(PublicUser::from(user)))
+}
+
+/// Текущие и будущие брони текущего пользователя
+#[utoipa::path(
+    responses(
+        (status = 200, body = [Booking]),
+        (status = 401, body = ApiErrorBody),
+        (status = 403, description = "Токену не хватает прав", body = ApiErrorBody)
+    ),
+    security(("session" = []), ("bearer" = []))
+)]
+#[get("/bookings")]
+async fn list_my_bookings(req: HttpRequest, session: Session, app_state: web::Data<AppState<'_>>)
+    -> Result<HttpResponse, ApiError>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let user = current_user(&service_data, None).await?;
+    let bookings = get_upcoming_user_bookings(&service_data.client,
+                                              user.id.unwrap_or_default(),
+                                              Utc::now().timestamp()).await?;
+    Ok(HttpResponse::Ok().json(bookings))
+}
+
+/// Бронирует место
+#[utoipa::path(
+    request_body = BookSeatForm,
+    responses(
+        (status = 201, body = Booking),
+        (status = 400, body = ApiErrorBody),
+        (status = 401, body = ApiErrorBody),
+        (status = 403, description = "Токену не хватает прав", body = ApiErrorBody),
+        (status = 404, body = ApiErrorBody),
+        (status = 409, description = "Место уже занято или недоступно", body = ApiErrorBody)
+    ),
+    security(("session" = []), ("bearer" = []))
+)]
+#[post("/bookings")]
+async fn create_booking(req: HttpRequest,
+                        session: Session,
+                        app_state: web::Data<AppState<'_>>,
+                        params: web::Json<BookSeatForm>)
+    -> Result<HttpResponse, ApiError>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let user = current_user(&service_data, Some(Permission::Book)).await?;
+    if !Booking::is_valid_interval(params.starts, params.ends, Utc::now().timestamp()) {
+        return Err(ApiError::BadRequest("booking must be in the future and at most 24 hours long".into()));
+    }
+    let seat = get_seat_by_id(&service_data.client, params.seat_id).await?;
+    if seat.availability == Some(AvailabilityStatus::Unavailable) {
+        return Err(DbError::Conflict.into());
+    }
+
+    let booking = add_booking(&service_data.client, Booking {
+        id: None,
+        seat_id: Some(params.seat_id),
+        user_id: user.id,
+        starts: Some(params.starts),
+        ends: Some(params.ends)
+    }).await?;
+    audit::record(&service_data, Some(&user), AuditAction::BookingCreated,
+                  AuditTarget::Booking(booking.id.unwrap_or_default()), None, snapshot(&booking)).await;
+    events::booking_changed(&service_data.client, &service_data.app_state.seat_events,
+                            &booking, SeatEventReason::Booked).await;
+    Ok(HttpResponse::Created().json(booking))
+}
+
+/// Отменяет свою бронь
+#[utoipa::path(
+    params(IdPath),
+    responses(
+        (status = 200, description = "Отменённая бронь", body = Booking),
+        (status = 401, body = ApiErrorBody),
+        (status = 403, description = "Токену не хватает прав", body = ApiErrorBody),
+        (status = 404, body = ApiErrorBody)
+    ),
+    security(("session" = []), ("bearer" = []))
+)]
+#[delete("/bookings/{id}")]
+async fn cancel_booking(req: HttpRequest,
+                        session: Session,
+                        app_state: web::Data<AppState<'_>>,
+                        path: web::Path<IdPath>)
+    -> Result<HttpResponse, ApiError>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let user = current_user(&service_data, Some(Permission::Book)).await?;
+    own_booking(&service_data, &user, path.id).await?;
+
+    let booking = remove_booking(&service_data.client, path.id).await?;
+    audit::record(&service_data, Some(&user), AuditAction::BookingCancelled,
+                  AuditTarget::Booking(path.id), snapshot(&booking), None).await;
+    events::booking_changed(&service_data.client, &service_data.app_state.seat_events,
+                            &booking, SeatEventReason::Cancelled).await;
+    Ok(HttpResponse::Ok().json(booking))
+}
+
+/// Продлевает свою бронь
+#[utoipa::path(
+    params(IdPath),
+    request_body = ExtendBookingForm,
+    responses(
+        (status = 200, body = Booking),
+        (status = 400, body = ApiErrorBody),
+        (status = 401, body = ApiErrorBody),
+        (status = 403, description = "Токену не хватает прав", body = ApiErrorBody),
+        (status = 404, body = ApiErrorBody),
+        (status = 409, description = "Место на новое время уже занято", body = ApiErrorBody)
+    ),
+    security(("session" = []), ("bearer" = []))
+)]
+#[post("/bookings/{id}/extend")]
+async fn extend_booking(req: HttpRequest,
+                        session: Session,
+                        app_state: web::Data<AppState<'_>>,
+                        path: web::Path<IdPath>,
+                        params: web::Json<ExtendBookingForm>)
+    -> Result<HttpResponse, ApiError>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let user = current_user(&service_data, Some(Permission::Book)).await?;
+    let booking = own_booking(&service_data, &user, path.id).await?;
+
+    let (starts, ends) = (booking.starts.unwrap_or_default(), booking.ends.unwrap_or_default());
+    if ends <= Utc::now().timestamp() {
+        return Err(ApiError::BadRequest("booking is already over".into()));
+    }
+    if params.ends <= ends || params.ends - starts > MAX_BOOKING_DURATION {
+        return Err(ApiError::BadRequest("new end must be later than the current one and at most 24 hours after the start".into()));
+    }
+
+    let before = booking;
+    let booking = set_booking_ends(&service_data.client, path.id, params.ends).await?;
+    audit::record(&service_data, Some(&user), AuditAction::BookingExtended,
+                  AuditTarget::Booking(path.id), snapshot(&before), snapshot(&booking)).await;
+    events::booking_changed(&service_data.client, &service_data.app_state.seat_events,
+                            &booking, SeatEventReason::Extended).await;
+    Ok(HttpResponse::Ok().json(booking))
+}
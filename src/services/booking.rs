@@ -1,55 +1,104 @@
+use std::collections::HashSet;
 use actix_session::Session;
-use actix_web::{get, HttpRequest, HttpResponse, web};
-use actix_web::http::header::ContentType;
+use actix_web::{get, post, HttpRequest, HttpResponse, web};
 use actix_web::http::StatusCode;
-use deadpool_postgres::Status;
+use chrono::{Local, NaiveDate, NaiveTime, TimeZone, Utc};
+use deadpool_postgres::Client;
 use serde::Deserialize;
-use serde_json::json;
+use serde_json::{json, Value};
 use crate::{AppState, models};
-use crate::core::templator;
-use crate::models::seat::Seat;
+use crate::core::{audit, events, habits, search, templator, ServiceData};
+use crate::core::access::{Authorized, CanBook};
+use crate::core::adjacency::Adjacency;
+use crate::core::audit::{snapshot, AuditAction, AuditTarget};
+use crate::core::errors::DbError;
+use crate::core::events::SeatEventReason;
+use crate::core::users::get_current_user;
+use crate::models::booking::{add_booking, check_in_booking, get_bookings_in_range, get_checked_in_seats,
+                             get_past_user_bookings, get_user_seat_booking,
+                             Booking, BookNextToForm, BookSeatForm};
+use crate::models::seat::{get_favourite_seats, get_seat_by_id, get_seat_neighbours,
+                          set_favourite_seat, FavouriteSeatForm, Seat, SeatSearchForm};
+use crate::models::user::{get_user_by_login, User};
 use crate::models::seat::seat_type::SeatType;
+use crate::models::seat::availability_status::AvailabilityStatus;
+
+/// Раскладывает места по слоям карты, помечая выделенные, избранные и те, где уже отметились
+fn seats_map(seats: Vec<Seat>,
+             highlighted: &HashSet<i32>,
+             favourites: &HashSet<i32>,
+             checked_in: &HashSet<i32>)
+    -> Value
+{
+    let (mut chairs, mut computer_chairs, mut desks, mut poufs)
+        :(Vec<Value>, Vec<Value>, Vec<Value>, Vec<Value>)
+        = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    for seat in seats {
+        let mut seat_json = json!(seat);
+        seat_json["highlighted"] = json!(seat.id.is_some_and(|id| highlighted.contains(&id)));
+        seat_json["favourite"] = json!(seat.id.is_some_and(|id| favourites.contains(&id)));
+        seat_json["checked_in"] = json!(seat.id.is_some_and(|id| checked_in.contains(&id)));
+        match seat.seat_type.unwrap_or(SeatType::Chair) {
+            SeatType::Desk => desks.push(seat_json),
+            SeatType::Chair => chairs.push(seat_json),
+            SeatType::ComputerChair => computer_chairs.push(seat_json),
+            SeatType::Pouf => poufs.push(seat_json)
+        };
+    }
+    json!({
+        "chairs": chairs,
+        "computer_chairs": computer_chairs,
+        "desks": desks,
+        "poufs": poufs
+    })
+}
+
+/// Места для карты с доступностью на текущий момент
+async fn get_map_seats(client: &Client) -> Result<Vec<Seat>, DbError> {
+    let now = Utc::now().timestamp();
+    let bookings = get_bookings_in_range(client, now, now + 1).await?;
+    let mut seats = models::seat::get_all_seats(client).await?;
+    for seat in seats.iter_mut() {
+        seat.availability = Some(search::availability_at(seat, &bookings, now));
+    }
+    Ok(seats)
+}
+
+/// Места, на которых сейчас кто-то отметился по брони
+async fn checked_in_seats(client: &Client) -> Result<HashSet<i32>, DbError> {
+    let now = Utc::now().timestamp();
+    Ok(get_checked_in_seats(client, now).await?.into_iter().collect())
+}
+
+/// Избранные места текущего пользователя, для гостей пусто
+async fn current_favourites(service_data: &ServiceData<'_>) -> Result<HashSet<i32>, DbError> {
+    match get_current_user(&service_data.client, service_data.session.clone()).await {
+        Ok(User { id: Some(id), .. }) =>
+            Ok(get_favourite_seats(&service_data.client, id).await?.into_iter().collect()),
+        _ => Ok(HashSet::new())
+    }
+}
 
 #[get("/")]
 async fn index(req: HttpRequest, session: Session, app_state: web::Data<AppState<'_>>)
                -> actix_web::Result<HttpResponse>
 {
-    let service_data = crate::core::ServiceData::new(req, app_state, session).await?;
-
-    let seats = models::seat::get_all_seats(&service_data.client).await;
-
-    let (content, status):(String, StatusCode) = match seats {
-        Ok(seats) => {
-            let (mut chairs, mut computer_chairs, mut desks, mut poufs)
-                :(Vec<Seat>, Vec<Seat>, Vec<Seat>, Vec<Seat>)
-                = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
-            for seat in seats {
-                let seat_copy = seat.clone();
-                match seat.seat_type.unwrap_or(SeatType::Chair) {
-                    SeatType::Desk => desks.push(seat_copy),
-                    SeatType::Chair => chairs.push(seat_copy),
-                    SeatType::ComputerChair => computer_chairs.push(seat_copy),
-                    SeatType::Pouf => poufs.push(seat_copy)
-                };
-            }
+    let service_data = ServiceData::new(req, app_state, session).await?;
+
+    let seats = get_map_seats(&service_data.client).await;
+    let favourites = current_favourites(&service_data).await?;
+    let checked_in = checked_in_seats(&service_data.client).await?;
 
-            (service_data.app_state.handlebars
-            .render("pages/index", &json!({ "seats": {
-                "chairs": chairs,
-                "computer_chairs": computer_chairs,
-                "desks": desks,
-                "poufs": poufs
-            } }))
-            .unwrap_or_default(), StatusCode::OK)},
-        Err(e) => (service_data.app_state.handlebars
-            .render("errors/seats_unavailable", &json!({ "error": e.to_string() }))
-            .unwrap_or_default(), StatusCode::INTERNAL_SERVER_ERROR)
+    let (template, data, status) = match seats {
+        Ok(seats) => ("pages/index",
+                      json!({ "seats": seats_map(seats, &HashSet::new(), &favourites, &checked_in) }),
+                      StatusCode::OK),
+        Err(e) => ("errors/seats_unavailable",
+                   json!({ "error": e.to_string() }),
+                   StatusCode::INTERNAL_SERVER_ERROR)
     };
 
-    let wrap = templator::wrap_page(&service_data, &*content, "Главная".into()).await;
-    Ok(HttpResponse::build(status)
-        .content_type(ContentType::html())
-        .body(wrap))
+    Ok(templator::render_page(&service_data, status, template, &data, "Главная".into()).await)
 }
 
 #[derive(Deserialize)]
@@ -64,7 +113,423 @@ async fn seat_page(req: HttpRequest,
                     app_state: web::Data<AppState<'_>>)
     -> actix_web::Result<HttpResponse>
 {
-    Ok(HttpResponse::build(StatusCode::OK)
-        .content_type(ContentType::html())
-        .body(path.id.to_string()))
-}
\ No newline at end of file
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let seat = get_seat_by_id(&service_data.client, path.id).await?;
+    let user = get_current_user(&service_data.client, service_data.session.clone()).await.ok();
+    let favourite = current_favourites(&service_data).await?.contains(&path.id);
+    let current_booking = match user.as_ref().and_then(|u| u.id) {
+        Some(user_id) => {
+            let now = Utc::now().timestamp();
+            get_user_seat_booking(&service_data.client, user_id, path.id, now).await?
+        },
+        None => None
+    };
+    let checked_in = checked_in_seats(&service_data.client).await?.contains(&path.id);
+
+    let title = seat.name.clone().unwrap_or_default();
+    let data = json!({
+        "seat": seat,
+        "logged_in": user.is_some(),
+        "favourite": favourite,
+        "current_booking": current_booking,
+        "checked_in": checked_in
+    });
+    Ok(templator::render_page(&service_data, StatusCode::OK, "pages/seat",
+                              &data, Some(title.as_str())).await)
+}
+
+#[post("/seat/{id}/favourite")]
+async fn seat_favourite(req: HttpRequest,
+                        session: Session,
+                        path: web::Path<SeatPagePath>,
+                        app_state: web::Data<AppState<'_>>,
+                        params: web::Form<FavouriteSeatForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let Ok(User { id: Some(user_id), .. }) =
+        get_current_user(&service_data.client, service_data.session.clone()).await else {
+        return Ok(templator::redirect(&service_data.req, "/login"));
+    };
+    get_seat_by_id(&service_data.client, path.id).await?;
+    set_favourite_seat(&service_data.client, user_id, path.id, params.favourite).await?;
+    templator::push_flash(&service_data.session, if params.favourite {
+        "Место добавлено в избранное"
+    } else {
+        "Место убрано из избранного"
+    });
+    Ok(templator::redirect(&service_data.req, &format!("/seat/{}", path.id)))
+}
+
+/// «Я на месте» по своей текущей брони этого места
+#[post("/seat/{id}/check-in")]
+async fn seat_check_in(req: HttpRequest,
+                       session: Session,
+                       path: web::Path<SeatPagePath>,
+                       app_state: web::Data<AppState<'_>>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let Ok(user @ User { id: Some(user_id), .. }) =
+        get_current_user(&service_data.client, service_data.session.clone()).await else {
+        return Ok(templator::redirect(&service_data.req, "/login"));
+    };
+    let now = Utc::now().timestamp();
+    let booking_id = check_in_booking(&service_data.client, user_id, path.id, now).await?;
+    audit::record(&service_data, Some(&user), AuditAction::BookingCheckedIn,
+                  AuditTarget::Booking(booking_id), None, Some(json!({ "checked_in": now }))).await;
+    if let Err(e) = events::seat_changed(&service_data.client,
+                                         &service_data.app_state.seat_events,
+                                         path.id, SeatEventReason::CheckedIn).await {
+        log::warn!("failed to publish seat {} check-in: {e}", path.id);
+    }
+    templator::push_flash(&service_data.session, "Вы отметились на месте");
+    Ok(templator::redirect(&service_data.req, &format!("/seat/{}", path.id)))
+}
+
+#[derive(PartialEq)]
+enum SearchError {
+    BadTime,
+    BadSeatType
+}
+
+/// Переводит дату, время начала и длительность в интервал `[starts, ends)`.
+/// Время берётся в локальной зоне сервера, без даты — на сегодня.
+fn parse_interval(date: Option<&str>, from: Option<&str>, hours: Option<f64>)
+    -> Option<(i64, i64)>
+{
+    let date = match date.filter(|d| !d.is_empty()) {
+        Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?,
+        None => Local::now().date_naive()
+    };
+    let from = NaiveTime::parse_from_str(from?, "%H:%M").ok()?;
+    let hours = hours.unwrap_or(1.0);
+    if !(hours > 0.0 && hours <= 24.0) {
+        return None;
+    }
+    let starts = Local.from_local_datetime(&date.and_time(from)).earliest()?.timestamp();
+    Some((starts, starts + (hours * 3600.0) as i64))
+}
+
+/// Переводит форму поиска в запрос
+fn parse_search_form(form: &SeatSearchForm) -> Result<search::SeatQuery, Vec<SearchError>> {
+    let mut errors: Vec<SearchError> = Vec::new();
+
+    let interval = parse_interval(form.date.as_deref(), form.from.as_deref(), form.hours);
+    if interval.is_none() {
+        errors.push(SearchError::BadTime);
+    }
+
+    let seat_type = match form.seat_type.as_deref().filter(|t| !t.is_empty()) {
+        Some(seat_type) => match seat_type.parse::<SeatType>() {
+            Ok(seat_type) => Some(seat_type),
+            Err(_) => {
+                errors.push(SearchError::BadSeatType);
+                None
+            }
+        },
+        None => None
+    };
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let (starts, ends) = interval.unwrap_or_default();
+    Ok(search::SeatQuery {
+        starts,
+        ends,
+        seat_type,
+        amenities: form.amenities.as_deref().unwrap_or_default()
+            .split(',')
+            .map(|a| a.trim().to_string())
+            .filter(|a| !a.is_empty())
+            .collect(),
+        people: form.people.unwrap_or(1).clamp(1, 20)
+    })
+}
+
+#[get("/search")]
+async fn search_page(req: HttpRequest,
+                     session: Session,
+                     form: web::Query<SeatSearchForm>,
+                     app_state: web::Data<AppState<'_>>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let seats = get_map_seats(&service_data.client).await?;
+
+    let mut data = json!({ "form": form.0 });
+    let mut status = StatusCode::OK;
+    let mut highlighted: HashSet<i32> = HashSet::new();
+
+    if form.from.is_some() {
+        match parse_search_form(&form) {
+            Ok(query) => {
+                let (window_starts, window_ends) = query.bookings_window();
+                let bookings = get_bookings_in_range(&service_data.client,
+                                                     window_starts, window_ends).await?;
+                let neighbours = get_seat_neighbours(&service_data.client).await?;
+                let adjacency = Adjacency::build(&seats, &neighbours);
+                let candidates = search::find_candidates(&seats, &bookings, &adjacency, &query);
+                highlighted.extend(candidates.iter()
+                    .flat_map(|c| c.seats.iter().filter_map(|s| s.id)));
+                data["searched"] = json!(true);
+                data["candidates"] = json!(candidates);
+            },
+            Err(errors) => {
+                status = StatusCode::BAD_REQUEST;
+                data["errors"] = json!({
+                    "time": errors.contains(&SearchError::BadTime),
+                    "seat_type": errors.contains(&SearchError::BadSeatType)
+                });
+            }
+        }
+    }
+    let favourites = current_favourites(&service_data).await?;
+    let checked_in = checked_in_seats(&service_data.client).await?;
+    data["seats"] = seats_map(seats, &highlighted, &favourites, &checked_in);
+
+    Ok(templator::render_page(&service_data, status, "pages/search",
+                              &data, "Поиск места".into()).await)
+}
+
+#[derive(PartialEq)]
+enum BookNextToError {
+    BadTime,
+    FriendNotFound,
+    FriendNotBooked,
+    NoFreeSeats,
+    Conflict
+}
+
+async fn generate_book_next_to_page(service_data: &ServiceData<'_>,
+                                    form: Option<BookNextToForm>,
+                                    errors: &[BookNextToError])
+    -> HttpResponse
+{
+    let data = json!({
+            "errors": {
+                "time": errors.contains(&BookNextToError::BadTime),
+                "friend": errors.contains(&BookNextToError::FriendNotFound),
+                "not_booked": errors.contains(&BookNextToError::FriendNotBooked),
+                "no_seats": errors.contains(&BookNextToError::NoFreeSeats),
+                "conflict": errors.contains(&BookNextToError::Conflict)
+            },
+            "form": form
+        });
+    let status = if errors.is_empty() { StatusCode::OK } else { StatusCode::BAD_REQUEST };
+    templator::render_page(service_data, status, "pages/book_next_to",
+                           &data, "Сесть рядом".into()).await
+}
+
+#[get("/book/next-to")]
+async fn book_next_to_get(req: HttpRequest,
+                          session: Session,
+                          app_state: web::Data<AppState<'_>>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    Ok(generate_book_next_to_page(&service_data, None, &[]).await)
+}
+
+#[post("/book/next-to")]
+async fn book_next_to_post(req: HttpRequest,
+                           session: Session,
+                           app_state: web::Data<AppState<'_>>,
+                           authorized: Authorized<CanBook>,
+                           params: web::Form<BookNextToForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let user = authorized.user;
+    let form = params.into_inner();
+
+    let Some((starts, ends)) = parse_interval(form.date.as_deref(), Some(&form.from), form.hours) else {
+        return Ok(generate_book_next_to_page(&service_data, Some(form), &[BookNextToError::BadTime]).await);
+    };
+    let Ok(friend) = get_user_by_login(&service_data.client, form.login.trim().trim_start_matches('@')).await else {
+        return Ok(generate_book_next_to_page(&service_data, Some(form), &[BookNextToError::FriendNotFound]).await);
+    };
+
+    let query = search::SeatQuery { starts, ends, seat_type: None, amenities: Vec::new(), people: 1 };
+    let (window_starts, window_ends) = query.bookings_window();
+    let bookings = get_bookings_in_range(&service_data.client, window_starts, window_ends).await?;
+    let Some(friend_seat_id) = bookings.iter()
+        .find(|b| b.user_id == friend.id && b.overlaps(starts, ends))
+        .and_then(|b| b.seat_id) else {
+        return Ok(generate_book_next_to_page(&service_data, Some(form), &[BookNextToError::FriendNotBooked]).await);
+    };
+
+    let seats = models::seat::get_all_seats(&service_data.client).await?;
+    let neighbours = get_seat_neighbours(&service_data.client).await?;
+    let adjacency = Adjacency::build(&seats, &neighbours);
+    let seat = seats.iter()
+        .find(|s| s.id == Some(friend_seat_id))
+        .and_then(|friend_seat| search::nearest_free(&seats, &bookings, &adjacency, &query, friend_seat));
+    let Some(seat) = seat else {
+        return Ok(generate_book_next_to_page(&service_data, Some(form), &[BookNextToError::NoFreeSeats]).await);
+    };
+
+    let booking = Booking { id: None, seat_id: seat.id, user_id: user.id, starts: Some(starts), ends: Some(ends) };
+    match add_booking(&service_data.client, booking).await {
+        Ok(booking) => {
+            audit::record(&service_data, Some(&user), AuditAction::BookingCreated,
+                          AuditTarget::Booking(booking.id.unwrap_or_default()),
+                          None, snapshot(&booking)).await;
+            events::booking_changed(&service_data.client, &service_data.app_state.seat_events,
+                                   &booking, SeatEventReason::Booked).await;
+            let data = json!({ "seat": seat, "booking": booking, "friend": { "name": friend.name } });
+            Ok(templator::render_page(&service_data, StatusCode::OK, "pages/booked",
+                                      &data, "Сесть рядом".into()).await)
+        },
+        Err(DbError::Conflict) =>
+            Ok(generate_book_next_to_page(&service_data, Some(form), &[BookNextToError::Conflict]).await),
+        Err(e) => Err(e.into())
+    }
+}
+
+/// Текст времени брони для страниц
+pub(crate) fn format_timestamp(timestamp: i64) -> String {
+    Local.timestamp_opt(timestamp, 0)
+        .single()
+        .map(|t| t.format("%d.%m %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+#[derive(PartialEq)]
+enum BookUsualError {
+    BadTime,
+    Conflict,
+    Unavailable
+}
+
+async fn generate_book_usual_page(service_data: &ServiceData<'_>,
+                                  user_id: i32,
+                                  errors: &[BookUsualError])
+    -> actix_web::Result<HttpResponse>
+{
+    let now = Local::now();
+    let past = get_past_user_bookings(&service_data.client, user_id, now.timestamp()).await?;
+    let mut data = json!({
+        "no_history": past.is_empty(),
+        "errors": {
+            "bad_time": errors.contains(&BookUsualError::BadTime),
+            "conflict": errors.contains(&BookUsualError::Conflict),
+            "unavailable": errors.contains(&BookUsualError::Unavailable)
+        }
+    });
+
+    let usual = habits::usual_pattern(&past)
+        .and_then(|usual| usual.next_interval(now).map(|interval| (usual, interval)));
+    if let Some((usual, (starts, ends))) = usual {
+        let seats = models::seat::get_all_seats(&service_data.client).await?;
+        let usual_seat = seats.iter().find(|s| s.id == Some(usual.seat_id));
+        let query = search::SeatQuery {
+            starts,
+            ends,
+            seat_type: usual_seat.and_then(|s| s.seat_type),
+            amenities: Vec::new(),
+            people: 1
+        };
+        let (window_starts, window_ends) = query.bookings_window();
+        let bookings = get_bookings_in_range(&service_data.client, window_starts, window_ends).await?;
+
+        let proposal = match usual_seat {
+            Some(seat) if search::is_suitable(seat, &bookings, &query) => Some((seat, false)),
+            Some(seat) => {
+                let neighbours = get_seat_neighbours(&service_data.client).await?;
+                let adjacency = Adjacency::build(&seats, &neighbours);
+                search::nearest_free(&seats, &bookings, &adjacency, &query, seat)
+                    .map(|seat| (seat, true))
+            },
+            None => None
+        };
+        data["usual_seat"] = json!(usual_seat);
+        if let Some((seat, fallback)) = proposal {
+            data["proposal"] = json!({
+                "seat": seat,
+                "fallback": fallback,
+                "starts": starts,
+                "ends": ends,
+                "starts_text": format_timestamp(starts),
+                "ends_text": format_timestamp(ends)
+            });
+        }
+    }
+
+    let status = if errors.is_empty() { StatusCode::OK } else { StatusCode::BAD_REQUEST };
+    Ok(templator::render_page(service_data, status, "pages/book_usual",
+                              &data, "Как обычно".into()).await)
+}
+
+#[get("/book/usual")]
+async fn book_usual_get(req: HttpRequest,
+                        session: Session,
+                        app_state: web::Data<AppState<'_>>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let Ok(User { id: Some(user_id), .. }) =
+        get_current_user(&service_data.client, service_data.session.clone()).await else {
+        return Ok(templator::redirect(&service_data.req, "/login"));
+    };
+    generate_book_usual_page(&service_data, user_id, &[]).await
+}
+
+#[post("/book/usual")]
+async fn book_usual_post(req: HttpRequest,
+                         session: Session,
+                         app_state: web::Data<AppState<'_>>,
+                         authorized: Authorized<CanBook>,
+                         params: web::Form<BookSeatForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let Some(user_id) = authorized.user.id else {
+        return Ok(templator::redirect(&service_data.req, "/login"));
+    };
+    if !Booking::is_valid_interval(params.starts, params.ends, Utc::now().timestamp()) {
+        return generate_book_usual_page(&service_data, user_id, &[BookUsualError::BadTime]).await;
+    }
+
+    let seat = get_seat_by_id(&service_data.client, params.seat_id).await?;
+    let query = search::SeatQuery {
+        starts: params.starts,
+        ends: params.ends,
+        seat_type: None,
+        amenities: Vec::new(),
+        people: 1
+    };
+    let bookings = get_bookings_in_range(&service_data.client, params.starts, params.ends).await?;
+    if !search::is_suitable(&seat, &bookings, &query) {
+        let error = if seat.availability == Some(AvailabilityStatus::Unavailable) {
+            BookUsualError::Unavailable
+        } else {
+            BookUsualError::Conflict
+        };
+        return generate_book_usual_page(&service_data, user_id, &[error]).await;
+    }
+
+    let booking = Booking {
+        id: None,
+        seat_id: Some(params.seat_id),
+        user_id: Some(user_id),
+        starts: Some(params.starts),
+        ends: Some(params.ends)
+    };
+    match add_booking(&service_data.client, booking).await {
+        Ok(booking) => {
+            audit::record(&service_data, Some(&authorized.user), AuditAction::BookingCreated,
+                          AuditTarget::Booking(booking.id.unwrap_or_default()),
+                          None, snapshot(&booking)).await;
+            events::booking_changed(&service_data.client, &service_data.app_state.seat_events,
+                                   &booking, SeatEventReason::Booked).await;
+            Ok(templator::render_page(&service_data, StatusCode::OK, "pages/booked",
+                                      &json!({ "seat": seat, "booking": booking }),
+                                      "Как обычно".into()).await)
+        },
+        Err(DbError::Conflict) =>
+            generate_book_usual_page(&service_data, user_id, &[BookUsualError::Conflict]).await,
+        Err(e) => Err(e.into())
+    }
+}
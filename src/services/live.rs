@@ -0,0 +1,60 @@
+use std::time::Duration;
+use actix_web::{get, web, Responder};
+use actix_web::http::header;
+use actix_web_lab::sse;
+use async_stream::stream;
+use deadpool_postgres::Pool;
+use tokio::sync::broadcast::error::RecvError;
+use crate::AppState;
+use crate::core::errors::DbError;
+use crate::core::events::{self, SeatEvent};
+
+fn seat_event(event: &SeatEvent) -> Option<sse::Event> {
+    match sse::Data::new_json(event) {
+        Ok(data) => Some(sse::Event::Data(data.event("seat"))),
+        Err(e) => {
+            log::warn!("failed to serialize seat event: {e}");
+            None
+        }
+    }
+}
+
+/// Текущее состояние всех мест
+async fn resync(pool: &Pool) -> Result<Vec<SeatEvent>, DbError> {
+    let client = pool.get().await?;
+    events::snapshot(&client).await
+}
+
+/// Поток изменений доступности мест для открытой карты
+#[get("/events/seats")]
+async fn seat_events(app_state: web::Data<AppState<'_>>) -> impl Responder {
+    let mut receiver = app_state.seat_events.subscribe();
+    let pool = app_state.db_pool.clone();
+
+    let events = stream! {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => if let Some(event) = seat_event(&event) {
+                    yield event;
+                },
+                // отстали и потеряли часть событий -- присылаем состояние всех мест заново
+                Err(RecvError::Lagged(missed)) => match resync(&pool).await {
+                    Ok(snapshot) => {
+                        log::info!("seat events subscriber missed {missed} events, resyncing");
+                        for event in snapshot.iter().filter_map(seat_event) {
+                            yield event;
+                        }
+                    },
+                    Err(e) => log::warn!("failed to resync lagged seat events subscriber: {e}")
+                },
+                Err(RecvError::Closed) => break
+            }
+        }
+    };
+
+    sse::Sse::from_infallible_stream(events)
+        .with_keep_alive(Duration::from_secs(15))
+        .customize()
+        // сжатие буферизует поток, и события доходят с опозданием
+        .insert_header((header::CONTENT_ENCODING, "identity"))
+}
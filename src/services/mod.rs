@@ -2,3 +2,9 @@ pub mod legacy;
 pub mod static_pages;
 pub mod users;
 pub mod booking;
+pub mod live;
+pub mod api;
+
+pub mod tokens;
+pub mod admin;
+pub mod account;
\ No newline at end of file
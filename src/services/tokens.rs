@@ -0,0 +1,148 @@
+use actix_session::Session;
+use actix_web::{get, post, HttpRequest, HttpResponse, web};
+use actix_web::http::StatusCode;
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::AppState;
+use crate::core::{audit, templator, ServiceData};
+use crate::core::audit::{snapshot, AuditAction, AuditTarget};
+use crate::core::users::get_current_user;
+use crate::models::api_token::{add_api_token, get_user_api_tokens, remove_api_token,
+                               ApiToken, ApiTokenForm, TokenScope};
+use crate::models::user::User;
+use crate::services::booking::format_timestamp;
+
+/// Самый долгий срок жизни токена, в днях
+const MAX_TOKEN_DAYS: i64 = 365;
+
+#[derive(Deserialize)]
+struct TokenPath {
+    id: i32
+}
+
+#[derive(PartialEq)]
+enum TokenFormError {
+    Name,
+    Scope,
+    Days
+}
+
+/// Разбирает форму в имя, область доступа и срок жизни в днях
+fn parse_token_form(form: &ApiTokenForm) -> Result<(String, TokenScope, Option<i64>), Vec<TokenFormError>> {
+    let mut errors = Vec::new();
+    let name = form.name.trim();
+    if name.is_empty() || name.len() > 128 {
+        errors.push(TokenFormError::Name);
+    }
+    let scope = form.scope.parse::<TokenScope>();
+    if scope.is_err() {
+        errors.push(TokenFormError::Scope);
+    }
+    let days = match form.days.as_deref().map(str::trim).filter(|d| !d.is_empty()) {
+        None => None,
+        Some(days) => match days.parse::<i64>() {
+            Ok(days) if (1..=MAX_TOKEN_DAYS).contains(&days) => Some(days),
+            _ => {
+                errors.push(TokenFormError::Days);
+                None
+            }
+        }
+    };
+    match scope {
+        Ok(scope) if errors.is_empty() => Ok((name.to_string(), scope, days)),
+        _ => Err(errors)
+    }
+}
+
+fn token_view(token: &ApiToken) -> Value {
+    json!({
+        "id": token.id,
+        "name": token.name,
+        "scopes": token.scopes,
+        "created": token.created.map(format_timestamp),
+        "expires": token.expires.map(format_timestamp),
+        "last_used": token.last_used.map(format_timestamp)
+    })
+}
+
+async fn generate_tokens_page(service_data: &ServiceData<'_>,
+                              status: StatusCode,
+                              user_id: i32,
+                              mut data: Value)
+    -> actix_web::Result<HttpResponse>
+{
+    let tokens = get_user_api_tokens(&service_data.client, user_id).await?;
+    data["tokens"] = tokens.iter().map(token_view).collect();
+    Ok(templator::render_page(service_data, status, "pages/tokens", &data,
+                              "Токены доступа".into()).await)
+}
+
+#[get("/tokens")]
+async fn tokens_get(req: HttpRequest, session: Session, app_state: web::Data<AppState<'_>>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let Ok(User { id: Some(user_id), .. }) =
+        get_current_user(&service_data.client, service_data.session.clone()).await else {
+        return Ok(templator::redirect(&service_data.req, "/login"));
+    };
+    generate_tokens_page(&service_data, StatusCode::OK, user_id, json!({})).await
+}
+
+#[post("/tokens")]
+async fn tokens_post(req: HttpRequest,
+                     session: Session,
+                     app_state: web::Data<AppState<'_>>,
+                     params: web::Form<ApiTokenForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let Ok(user @ User { id: Some(user_id), .. }) =
+        get_current_user(&service_data.client, service_data.session.clone()).await else {
+        return Ok(templator::redirect(&service_data.req, "/login"));
+    };
+
+    let (name, scope, days) = match parse_token_form(&params) {
+        Ok(parsed) => parsed,
+        Err(errors) => return generate_tokens_page(&service_data, StatusCode::BAD_REQUEST, user_id, json!({
+            "errors": {
+                "name": errors.contains(&TokenFormError::Name),
+                "scope": errors.contains(&TokenFormError::Scope),
+                "days": errors.contains(&TokenFormError::Days)
+            },
+            "form": params.0
+        })).await
+    };
+
+    let now = Utc::now();
+    let expires = days.map(|days| (now + Duration::days(days)).timestamp());
+    let (api_token, token) = add_api_token(&service_data.client, user_id, &name, &[scope],
+                                           now.timestamp(), expires).await?;
+    audit::record(&service_data, Some(&user), AuditAction::TokenCreated,
+                  AuditTarget::Token(api_token.id.unwrap_or_default()), None, snapshot(&api_token)).await;
+    // значение показывается один раз, потом его уже не достать
+    generate_tokens_page(&service_data, StatusCode::CREATED, user_id, json!({
+        "created": { "name": api_token.name, "token": token }
+    })).await
+}
+
+#[post("/tokens/{id}/revoke")]
+async fn token_revoke(req: HttpRequest,
+                      session: Session,
+                      app_state: web::Data<AppState<'_>>,
+                      path: web::Path<TokenPath>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let Ok(user @ User { id: Some(user_id), .. }) =
+        get_current_user(&service_data.client, service_data.session.clone()).await else {
+        return Ok(templator::redirect(&service_data.req, "/login"));
+    };
+    let api_token = remove_api_token(&service_data.client, user_id, path.id).await?;
+    audit::record(&service_data, Some(&user), AuditAction::TokenRevoked,
+                  AuditTarget::Token(path.id), snapshot(&api_token), None).await;
+    templator::push_flash(&service_data.session,
+                          &format!("Токен «{}» отозван", api_token.name.unwrap_or_default()));
+    Ok(templator::redirect(&service_data.req, "/tokens"))
+}
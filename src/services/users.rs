@@ -3,32 +3,30 @@ use std::hash::{BuildHasher, Hasher};
 use std::ops::Deref;
 use actix_session::{Session, SessionGetError};
 use actix_web::{get, post, HttpRequest, HttpResponse, web};
-use actix_web::http::header::ContentType;
-use actix_web::http::{header, StatusCode};
+use actix_web::http::header::{self, ContentType};
+use actix_web::http::StatusCode;
 use deadpool_postgres::Client;
+use chrono::{Duration, Utc};
 use serde_json::json;
 use regex::Regex;
 use rs_sha512::{HasherContext, Sha512State};
 use serde::Deserialize;
 use crate::{AppState, models};
-use crate::core::{ServiceData, templator, errors::DbError};
+use crate::config::RegistrationMode;
+use crate::core::{audit, remember, ServiceData, templator, errors::AuthError};
+use crate::core::audit::{AuditAction, AuditTarget};
+use crate::core::throttle::{accept_login, format_wait, reserve_login};
+use crate::core::users::{check_second_factor, get_current_user, hash_password, needs_totp,
+                         verify_dummy_password, verify_password, PasswordCheck, SecondFactor};
+use crate::core::errors::DbError;
+use crate::models::invite::{add_invite_use, release_invite, use_invite};
+use crate::models::recovery_code::count_unused_recovery_codes;
 use crate::models::user;
-use crate::models::user::{add_user, get_user_by_login, get_user_by_token,
-                          User, UserLoginForm, UserRegisterForm};
-use crate::models::session::remove_session_by_token;
-use crate::services::users::AuthError::{BadLogin, BadName, BadPassword};
-
-
-#[derive(PartialEq)]
-enum AuthError {
-    BadName,
-    BadLogin,
-    BadPassword,
-    AlreadyExists,
-    NotFound,
-    TokenNotGenerated,
-    CookieNotWrote
-}
+use crate::models::user::{add_user, get_user_by_login, set_user_password_hash,
+                          Approval, PublicUser, User, UserLoginForm, UserRegisterForm};
+use crate::models::session::{get_user_sessions, remove_session_by_token, remove_user_session,
+                             remove_user_sessions, SessionOrigin};
+use crate::services::booking::format_timestamp;
 
 
 async fn validate_register_form(service_data: &ServiceData<'_>,
@@ -52,12 +50,37 @@ async fn validate_register_form(service_data: &ServiceData<'_>,
     {
         auth_errors.push(AuthError::BadPassword);
     }
+    if normalize_email(form.email.as_deref()).is_some_and(|email| !is_valid_email(&email)) {
+        auth_errors.push(AuthError::BadEmail);
+    }
+    if service_data.app_state.registration.mode == RegistrationMode::Invite
+        && form.invite.as_deref().is_none_or(|code| code.trim().is_empty())
+    {
+        auth_errors.push(AuthError::BadInvite);
+    }
     if(!auth_errors.is_empty()) {
         return Err(auth_errors);
     }
     Ok(())
 }
 
+/// Пароль, который примут при регистрации и смене пароля
+pub(crate) fn is_valid_password(password: &str) -> bool {
+    let regex_password: Regex = Regex::new(r"[А-Яа-яA-Za-z0-9\-_()*&^%$#@!+=/,.{}\[\]]+").unwrap();
+    password.len() >= 8 && password.len() <= 256 && regex_password.is_match(password)
+}
+
+/// Пустое поле почты -- это отсутствие почты
+pub(crate) fn normalize_email(email: Option<&str>) -> Option<String> {
+    email.map(str::trim).filter(|email| !email.is_empty()).map(str::to_string)
+}
+
+/// Грубая проверка: что-то@что-то.что-то без пробелов
+pub(crate) fn is_valid_email(email: &str) -> bool {
+    let regex_email: Regex = Regex::new(r"^[^@\s]+@[^@\s]+\.[^@\s]+$").unwrap();
+    email.len() <= 256 && regex_email.is_match(email)
+}
+
 fn validate_login_form(form: UserLoginForm) -> Result<(), Vec<AuthError>> {
     let regex_login: Regex = Regex::new(r"[А-Яа-яA-Za-z0-9\-_()*&^%$#@!+=/,.{}\[\]]+").unwrap();
     let mut auth_errors:Vec<AuthError> = Default::default();
@@ -73,6 +96,28 @@ fn validate_login_form(form: UserLoginForm) -> Result<(), Vec<AuthError>> {
     Ok(())
 }
 
+async fn generate_register_page(service_data: &ServiceData<'_>,
+                                status: StatusCode,
+                                form: &UserRegisterForm,
+                                errors: &[AuthError])
+    -> HttpResponse
+{
+    templator::render_page(service_data, status, "pages/register", &json!({
+        "auth_errors": {
+            "name": errors.contains(&AuthError::BadName),
+            "login": errors.contains(&AuthError::BadLogin),
+            "password": errors.contains(&AuthError::BadPassword),
+            "exists": errors.contains(&AuthError::AlreadyExists),
+            "email": errors.contains(&AuthError::BadEmail),
+            "invite": errors.contains(&AuthError::BadInvite)
+        },
+        "invite_required": service_data.app_state.registration.mode == RegistrationMode::Invite,
+        "user": form
+    }), "Регистрация".into()).await
+}
+
+/// Регистрация. В режиме `invite` нужен действующий код приглашения;
+/// код, если он есть, задаёт роль и клуб нового пользователя.
 #[post("/register")]
 async fn register_post(req: HttpRequest,
                        app_state: web::Data<AppState<'_>>, session: Session,
@@ -82,71 +127,93 @@ async fn register_post(req: HttpRequest,
     let service_data = crate::core::ServiceData::new(req, app_state, session).await?;
     let verify_result = validate_register_form(&service_data,
                                                params.0.clone()).await;
-    if(verify_result.is_err()) {
-        let errors = verify_result.unwrap_err();
-        let register = service_data.app_state.handlebars
-            .render("pages/register", &json!({
-                "auth_errors": {
-                    "name": errors.contains(&AuthError::BadName),
-                    "login": errors.contains(&AuthError::BadLogin),
-                    "password": errors.contains(&AuthError::BadPassword),
-                    "exists": errors.contains(&AuthError::AlreadyExists)
-                },
-                "user": params.0
-            }))
-            .unwrap_or_default();
-
-        let wrap = templator::wrap_page(&service_data, &register, "Регистрация".into()).await;
-        return Ok(HttpResponse::build(StatusCode::BAD_REQUEST)
-            .content_type(ContentType::html())
-            .body(wrap));
+    if let Err(errors) = verify_result {
+        return Ok(generate_register_page(&service_data, StatusCode::BAD_REQUEST, &params, &errors).await);
     }
 
+    let Ok(password_hash) = hash_password(params.password.as_str()).await else {
+        return Ok(templator::render_page(&service_data, StatusCode::INTERNAL_SERVER_ERROR,
+                                         "pages/register_failed", &json!({  }),
+                                         "Регистрация".into()).await);
+    };
+
+    let now = Utc::now().timestamp();
+    let invite = match params.invite.as_deref().filter(|code| !code.trim().is_empty()) {
+        Some(code) => match use_invite(&service_data.client, code, now).await {
+            Ok(invite) => Some(invite),
+            Err(DbError::NotFound) => return Ok(generate_register_page(
+                &service_data, StatusCode::BAD_REQUEST, &params, &[AuthError::BadInvite]).await),
+            Err(e) => return Err(e.into())
+        },
+        None => None
+    };
+
+    // приглашение уже и есть одобрение
+    let approval = match service_data.app_state.registration.mode {
+        RegistrationMode::Moderated if invite.is_none() => Approval::Pending,
+        _ => Approval::Approved
+    };
     let user_data : User = User {
-        id: None, role: None, score: None,
+        id: None, score: None, banned: None, approval_reason: None,
+        totp_secret: None, totp_last_step: None,
+        approval: Some(approval.as_str().to_string()),
+        registered: Some(now),
+        role: invite.as_ref().and_then(|invite| invite.role),
+        club: invite.as_ref().and_then(|invite| invite.club.clone()),
+        email: normalize_email(params.email.as_deref()),
         name: Option::from(params.name.clone()),
         login: Option::from(params.login.clone()),
-        password_hash: Option::from(crate::core::users::hash_password(params.password.as_str(),
-                                                  params.login.as_str()))
+        password_hash: Option::from(password_hash)
     };
     //let client:Client = client.await.map_err(DbError::PoolError)?;
     let template:&str;
     let status:StatusCode;
     match add_user(&service_data.client, user_data).await {
-        Ok(..) => {
+        Ok(user) => {
+            let user_id = user.id.unwrap_or_default();
+            let invite_id = invite.and_then(|invite| invite.id);
+            if let Some(invite_id) = invite_id {
+                add_invite_use(&service_data.client, invite_id, user_id, now).await?;
+            }
+            let mut after = json!(PublicUser::from(user.clone()));
+            after["invite"] = json!(invite_id);
+            after["approval"] = json!(approval);
+            audit::record(&service_data, Some(&user), AuditAction::Register,
+                          AuditTarget::User(user_id), None, Some(after)).await;
             template = "pages/register_success";
             status = StatusCode::OK
         },
         Err(..) => {
+            // код не должен сгореть из-за нашей ошибки
+            if let Some(invite_id) = invite.and_then(|invite| invite.id) {
+                release_invite(&service_data.client, invite_id).await?;
+            }
             template = "pages/register_failed";
             status = StatusCode::INTERNAL_SERVER_ERROR
         }
     }
-    let register = service_data.app_state.handlebars
-        .render(template, &json!({  }))
-        .unwrap_or_default();
-
-    let wrap = templator::wrap_page(&service_data, &register, "Регистрация".into()).await;
-    Ok(HttpResponse::build(status)
-        .content_type(ContentType::html())
-        .body(wrap))
+    Ok(templator::render_page(&service_data, status, template,
+                              &json!({ "pending": approval == Approval::Pending }),
+                              "Регистрация".into()).await)
 }
 
+#[derive(Deserialize)]
+struct RegisterQuery {
+    invite: Option<String>
+}
 
+/// Форма регистрации; ссылка вида `/register?invite=КОД` заполняет поле кода
 #[get("/register")]
 async fn register_get(req: HttpRequest, session:Session,
-                      app_state: web::Data<AppState<'_>>)
+                      app_state: web::Data<AppState<'_>>,
+                      query: web::Query<RegisterQuery>)
     -> actix_web::Result<HttpResponse>
 {
     let service_data = crate::core::ServiceData::new(req, app_state, session).await?;
-    let register = service_data.app_state.handlebars
-        .render("pages/register", &json!({  }))
-        .unwrap_or_default();
-
-    let wrap = templator::wrap_page(&service_data, &register, "Регистрация".into()).await;
-    Ok(HttpResponse::build(StatusCode::OK)
-        .content_type(ContentType::html())
-        .body(wrap))
+    Ok(templator::render_page(&service_data, StatusCode::OK, "pages/register", &json!({
+        "invite_required": service_data.app_state.registration.mode == RegistrationMode::Invite,
+        "user": { "invite": query.0.invite }
+    }), "Регистрация".into()).await)
 }
 
 async fn generate_login_page(service_data: &ServiceData<'_>,
@@ -159,21 +226,35 @@ async fn generate_login_page(service_data: &ServiceData<'_>,
                     "login": errors.contains(&AuthError::BadLogin),
                     "password": errors.contains(&AuthError::BadPassword),
                     "not_found": errors.contains(&AuthError::NotFound),
+                    "banned": errors.contains(&AuthError::Banned),
+                    "rejected": errors.contains(&AuthError::Rejected),
                     "session": errors.contains(&AuthError::TokenNotGenerated),
                     "cookie": errors.contains(&AuthError::CookieNotWrote)
                 }
             });
-    if(user.is_some()) {
-        data["user"] = json!(user.unwrap());
+    if let Some(user) = user {
+        if errors.contains(&AuthError::Rejected) {
+            data["reason"] = json!(user.approval_reason);
+        }
+        data["user"] = json!(PublicUser::from(user));
     }
-    let login = service_data.app_state.handlebars
-        .render("pages/login", &data)
-        .unwrap_or_default();
-
-    let wrap = templator::wrap_page(&service_data, &login, "Вход".into()).await;
-    return HttpResponse::build(StatusCode::BAD_REQUEST)
-        .content_type(ContentType::html())
-        .body(wrap);
+    return templator::render_page(service_data, StatusCode::BAD_REQUEST, "pages/login",
+                                  &data, "Вход".into()).await;
+}
+
+/// Страница входа с просьбой подождать `wait` секунд
+async fn generate_throttled_login_page(service_data: &ServiceData<'_>, login: &str, wait: i64)
+    -> HttpResponse
+{
+    let data = json!({
+        "auth_errors": { "throttled": true },
+        "wait": format_wait(wait),
+        "user": { "login": login }
+    });
+    let mut response = templator::render_page(service_data, StatusCode::TOO_MANY_REQUESTS,
+                                              "pages/login", &data, "Вход".into()).await;
+    response.headers_mut().insert(header::RETRY_AFTER, header::HeaderValue::from(wait));
+    response
 }
 
 #[get("/login")]
@@ -182,14 +263,8 @@ async fn login_get(req: HttpRequest, session:Session,
                    -> actix_web::Result<HttpResponse>
 {
     let service_data = crate::core::ServiceData::new(req, app_state, session).await?;
-    let login = service_data.app_state.handlebars
-        .render("pages/login", &json!({  }))
-        .unwrap_or_default();
-
-    let wrap = templator::wrap_page(&service_data, &login, "Вход".into()).await;
-    Ok(HttpResponse::build(StatusCode::OK)
-        .content_type(ContentType::html())
-        .body(wrap))
+    Ok(templator::render_page(&service_data, StatusCode::OK, "pages/login",
+                              &json!({  }), "Вход".into()).await)
 }
 
 #[post("/login")]
@@ -198,9 +273,19 @@ async fn login_post(req: HttpRequest, session: Session,
                     params: web::Form<UserLoginForm>)
     -> actix_web::Result<HttpResponse>
 {
-    let service_data = ServiceData::new(req, app_state, session).await?;
+    let mut service_data = ServiceData::new(req, app_state, session).await?;
+    let now = Utc::now().timestamp();
+    let ip = service_data.req.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_default();
+    // ждать приходится одинаково, есть такой логин или нет; попытка сразу
+    // засчитывается неудачей, а удачный вход её вернёт
+    let wait = reserve_login(&mut service_data.client, &service_data.app_state.login,
+                             &ip, params.login.as_str(), now).await?;
+    if wait > 0 {
+        return Ok(generate_throttled_login_page(&service_data, params.login.as_str(), wait).await);
+    }
     let validation_result = validate_login_form(params.0.clone());
     let mut found = false; // true потому что так надо
+    let mut password_check = PasswordCheck::Invalid;
     let mut user:User = User {
         id: None,
         login: None,
@@ -208,15 +293,26 @@ async fn login_post(req: HttpRequest, session: Session,
         password_hash: None,
         role: None,
         score: None,
+        banned: None,
+        email: None,
+        club: None,
+        approval: None,
+        approval_reason: None,
+        registered: None,
+        totp_secret: None,
+        totp_last_step: None,
     };
     if(validation_result.is_ok()) {
         match get_user_by_login(&service_data.client, params.login.as_str()).await {
             Ok(usr) => {
                 user = usr;
-                let hash = crate::core::users::hash_password(params.password.as_str(), params.login.as_str());
-                found = user.password_hash.clone().unwrap().as_str().eq(&hash);
+                password_check = verify_password(&user, params.password.as_str()).await;
+                found = password_check != PasswordCheck::Invalid;
             },
-            Err(..) => found = false
+            Err(..) => {
+                verify_dummy_password(params.password.as_str()).await;
+                found = false
+            }
         }
     }
     if(validation_result.is_err() || !found) {
@@ -225,30 +321,208 @@ async fn login_post(req: HttpRequest, session: Session,
             Err(E) => E
         };
         errors.push(AuthError::NotFound);
+        audit::record(&service_data, None, AuditAction::LoginFailed,
+                      user.id.map_or(AuditTarget::Nothing, AuditTarget::User),
+                      None, Some(json!({ "login": params.login }))).await;
         return Ok(generate_login_page(&service_data, Option::from(user), &errors).await);
     }
+    if user.banned == Some(true) {
+        return Ok(generate_login_page(&service_data,
+                                      Option::from(user),
+                                      &vec! [AuthError::Banned]).await);
+    }
+    if user.approval() == Approval::Rejected {
+        return Ok(generate_login_page(&service_data,
+                                      Option::from(user),
+                                      &vec! [AuthError::Rejected]).await);
+    }
+    if password_check == PasswordCheck::ValidNeedsRehash {
+        // пароль известен только сейчас, так что и пересчитать хеш можно только сейчас
+        if let Ok(hash) = hash_password(params.password.as_str()).await {
+            if let Err(e) = set_user_password_hash(&service_data.client, user.id.unwrap_or_default(), &hash).await {
+                log::warn!("failed to rehash password of {}: {e}", params.login);
+            }
+        }
+    }
+
+    let remember = params.remember.is_some();
+    if user.has_totp() {
+        // пароль верный, но сессию выдадим только после кода из приложения;
+        // ключ меняем уже сейчас, чтобы шаг с кодом не шёл по подсунутой печеньке
+        service_data.session.renew();
+        service_data.session.insert(TWO_FACTOR_USER, user.id.unwrap_or_default())?;
+        service_data.session.insert(TWO_FACTOR_REMEMBER, remember)?;
+        service_data.session.insert(TWO_FACTOR_STARTED, now)?;
+        return Ok(templator::redirect(&service_data.req, "/login/two-factor"));
+    }
+    start_session(&service_data, user, &ip, remember, None).await
+}
 
+/// Выдаёт сессию после всех проверок входа
+async fn start_session(service_data: &ServiceData<'_>,
+                       user: User,
+                       ip: &str,
+                       remember: bool,
+                       two_factor: Option<&str>)
+    -> actix_web::Result<HttpResponse>
+{
+    let login = user.login.clone().unwrap_or_default();
+    let config = &service_data.app_state.sessions;
+    let lifetime = remember.then(|| Duration::seconds(config.remember_timeout));
+    let origin = SessionOrigin {
+        user_agent: service_data.req.headers().get(header::USER_AGENT)
+            .and_then(|agent| agent.to_str().ok())
+            .map(str::to_string),
+        ip: service_data.req.peer_addr().map(|addr| addr.ip().to_string())
+    };
     let session_token = models::session::generate_session_token(&service_data.client,
-                                                     user.clone(), None).await;
+                                                     user.clone(), lifetime,
+                                                     config, origin).await;
 
     if(session_token.is_err()) {
-        return Ok(generate_login_page(&service_data,
+        return Ok(generate_login_page(service_data,
                                       Option::from(user),
                                       &vec! [AuthError::TokenNotGenerated]).await);
     }
 
     let session_token = session_token.unwrap();
 
+    // ключ сессии, выданный до входа, после входа не годится: иначе подсунутую
+    // заранее печеньку можно было бы использовать после чужого входа
+    service_data.session.renew();
     match service_data.session.insert("token", session_token.key.clone().unwrap()) {
-        Ok(_) => Ok(HttpResponse::Found()
-            .insert_header((header::LOCATION, "/"))
-            .finish()),
-        Err(_) => Ok(generate_login_page(&service_data,
+        Ok(_) => {
+            remember::set_remembered(&service_data.session, remember);
+            if let Err(e) = accept_login(&service_data.client, ip, &login).await {
+                log::warn!("failed to clear login failures for {login}: {e}");
+            }
+            audit::record(service_data, Some(&user), AuditAction::Login,
+                          AuditTarget::User(user.id.unwrap_or_default()), None,
+                          two_factor.map(|method| json!({ "two_factor": method }))).await;
+            templator::push_flash(&service_data.session,
+                                  &format!("Привет, {}!", user.name.clone().unwrap_or_default()));
+            if user.approval() == Approval::Pending {
+                templator::push_flash(&service_data.session,
+                                      "Заявка на регистрацию ещё на рассмотрении: бронировать можно будет после одобрения");
+            }
+            if needs_totp(&service_data.client, &user).await? {
+                templator::push_flash(&service_data.session,
+                                      "Для вашей роли нужен вход с кодом из приложения: включите его, чтобы продолжить");
+                return Ok(templator::redirect(&service_data.req, "/account/two-factor"));
+            }
+            Ok(templator::redirect(&service_data.req, "/"))
+        },
+        Err(_) => Ok(generate_login_page(service_data,
                                          Option::from(user),
                                          &vec! [AuthError::CookieNotWrote]).await)
     }
 }
 
+/// Ключи сессии, пока вход ждёт второго фактора
+const TWO_FACTOR_USER: &str = "two_factor_user";
+const TWO_FACTOR_REMEMBER: &str = "two_factor_remember";
+const TWO_FACTOR_STARTED: &str = "two_factor_started";
+
+/// Сколько секунд после пароля можно вводить код
+const TWO_FACTOR_TIMEOUT: i64 = 5 * 60;
+
+#[derive(Deserialize)]
+struct TwoFactorForm {
+    code: String
+}
+
+/// Пользователь, который ввёл пароль и ещё не ввёл код; `None`, если
+/// такого нет или время на код вышло
+async fn two_factor_user(service_data: &ServiceData<'_>, now: i64) -> actix_web::Result<Option<User>> {
+    let session = &service_data.session;
+    let started: Option<i64> = session.get(TWO_FACTOR_STARTED).ok().flatten();
+    let user_id: Option<i32> = session.get(TWO_FACTOR_USER).ok().flatten();
+    let (Some(started), Some(user_id)) = (started, user_id) else {
+        return Ok(None);
+    };
+    if now - started > TWO_FACTOR_TIMEOUT {
+        forget_two_factor(session);
+        return Ok(None);
+    }
+    match user::get_user_by_id(&service_data.client, user_id).await {
+        // пока вводили код, пользователя могли заблокировать или отклонить заявку
+        Ok(user) if user.has_totp()
+            && user.banned != Some(true)
+            && user.approval() != Approval::Rejected => Ok(Some(user)),
+        Ok(_) | Err(DbError::NotFound) => Ok(None),
+        Err(e) => Err(e.into())
+    }
+}
+
+fn forget_two_factor(session: &Session) {
+    session.remove(TWO_FACTOR_USER);
+    session.remove(TWO_FACTOR_REMEMBER);
+    session.remove(TWO_FACTOR_STARTED);
+}
+
+async fn generate_two_factor_page(service_data: &ServiceData<'_>, status: StatusCode, errors: &[AuthError])
+    -> HttpResponse
+{
+    let data = json!({ "errors": { "code": errors.contains(&AuthError::BadTotp) } });
+    templator::render_page(service_data, status, "pages/login_two_factor",
+                           &data, "Вход".into()).await
+}
+
+#[get("/login/two-factor")]
+async fn login_two_factor_get(req: HttpRequest, session: Session, app_state: web::Data<AppState<'_>>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    if two_factor_user(&service_data, Utc::now().timestamp()).await?.is_none() {
+        return Ok(templator::redirect(&service_data.req, "/login"));
+    }
+    Ok(generate_two_factor_page(&service_data, StatusCode::OK, &[]).await)
+}
+
+/// Второй шаг входа: код из приложения или одноразовый код восстановления.
+/// Неверные коды считаются неудачными попытками входа, как неверный пароль.
+#[post("/login/two-factor")]
+async fn login_two_factor_post(req: HttpRequest,
+                               session: Session,
+                               app_state: web::Data<AppState<'_>>,
+                               params: web::Form<TwoFactorForm>)
+    -> actix_web::Result<HttpResponse>
+{
+    let mut service_data = ServiceData::new(req, app_state, session).await?;
+    let now = Utc::now().timestamp();
+    let Some(user) = two_factor_user(&service_data, now).await? else {
+        templator::push_flash(&service_data.session, "Время на ввод кода вышло, войдите заново");
+        return Ok(templator::redirect(&service_data.req, "/login"));
+    };
+    let user_id = user.id.unwrap_or_default();
+    let login = user.login.clone().unwrap_or_default();
+    let ip = service_data.req.peer_addr().map(|addr| addr.ip().to_string()).unwrap_or_default();
+    // как и пароль, код сразу засчитывается неудачной попыткой входа
+    let wait = reserve_login(&mut service_data.client, &service_data.app_state.login,
+                             &ip, &login, now).await?;
+    if wait > 0 {
+        forget_two_factor(&service_data.session);
+        return Ok(generate_throttled_login_page(&service_data, &login, wait).await);
+    }
+
+    let method = check_second_factor(&service_data.client, &user, &params.code, now).await?;
+    let Some(method) = method else {
+        audit::record(&service_data, None, AuditAction::LoginFailed, AuditTarget::User(user_id),
+                      None, Some(json!({ "login": login, "two_factor": true }))).await;
+        return Ok(generate_two_factor_page(&service_data, StatusCode::BAD_REQUEST,
+                                                  &[AuthError::BadTotp]).await);
+    };
+
+    let remember = service_data.session.get(TWO_FACTOR_REMEMBER).ok().flatten().unwrap_or(false);
+    forget_two_factor(&service_data.session);
+    if method == SecondFactor::RecoveryCode {
+        let left = count_unused_recovery_codes(&service_data.client, user_id).await?;
+        templator::push_flash(&service_data.session,
+                              &format!("Использован код восстановления, осталось {left}"));
+    }
+    start_session(&service_data, user, &ip, remember, Some(method.as_str())).await
+}
+
 #[get("/logout")]
 async fn logout(req: HttpRequest,
                 app_state: web::Data<AppState<'_>>,
@@ -256,12 +530,16 @@ async fn logout(req: HttpRequest,
     -> actix_web::Result<HttpResponse>
 {
     let service_data = ServiceData::new(req, app_state, session).await?;
+    let user = get_current_user(&service_data.client, service_data.session.clone()).await.ok();
     match service_data.session.remove("token") {
         Some(token) => {
-            remove_session_by_token(&service_data.client, token.as_str());
-            Ok(HttpResponse::Found()
-                .insert_header((header::LOCATION, "/"))
-                .finish())
+            remove_session_by_token(&service_data.client, token.as_str()).await?;
+            if let Some(user) = &user {
+                audit::record(&service_data, Some(user), AuditAction::Logout,
+                              AuditTarget::User(user.id.unwrap_or_default()), None, None).await;
+            }
+            templator::push_flash(&service_data.session, "До встречи!");
+            Ok(templator::redirect(&service_data.req, "/"))
         },
         None => Ok(HttpResponse::build(StatusCode::UNAUTHORIZED)
             .content_type(ContentType::html())
@@ -274,12 +552,81 @@ async fn users(req: HttpRequest, app_state: web::Data<AppState<'_>>, session:Ses
                -> actix_web::Result<HttpResponse>
 {
     let service_data = ServiceData::new(req, app_state, session).await?;
-    let users = user::get_users(&service_data.client).await?;
-    let users_html = service_data.app_state.handlebars
-        .render("pages/users", &json!({ "users": users }))
-        .unwrap_or_default();
-    let wrap = templator::wrap_page(&service_data, &*users_html, "Пользователи".into()).await;
-    Ok(HttpResponse::build(StatusCode::OK)
-        .content_type(ContentType::html())
-        .body(wrap))
-}
\ No newline at end of file
+    let users: Vec<PublicUser> = user::get_users(&service_data.client).await?
+        .into_iter()
+        .map(PublicUser::from)
+        .collect();
+    Ok(templator::render_page(&service_data, StatusCode::OK, "pages/users",
+                              &json!({ "users": users }), "Пользователи".into()).await)
+}
+
+#[derive(Deserialize)]
+struct SessionPath {
+    id: i32
+}
+
+#[get("/sessions")]
+async fn sessions_get(req: HttpRequest, app_state: web::Data<AppState<'_>>, session: Session)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let Ok(User { id: Some(user_id), .. }) =
+        get_current_user(&service_data.client, service_data.session.clone()).await else {
+        return Ok(templator::redirect(&service_data.req, "/login"));
+    };
+    let current: Option<String> = service_data.session.get("token").ok().flatten();
+    let sessions: Vec<_> = get_user_sessions(&service_data.client, user_id, Utc::now().timestamp())
+        .await?
+        .into_iter()
+        .map(|s| json!({
+            "id": s.id,
+            "current": s.key.is_some() && s.key == current,
+            "user_agent": s.user_agent,
+            "ip": s.ip,
+            "created": s.created.map(format_timestamp),
+            "last_seen": s.last_seen.map(format_timestamp),
+            "remembered": s.idle_timeout.is_none()
+        }))
+        .collect();
+    Ok(templator::render_page(&service_data, StatusCode::OK, "pages/sessions",
+                              &json!({ "sessions": sessions }), "Мои сессии".into()).await)
+}
+
+#[post("/sessions/{id}/revoke")]
+async fn session_revoke(req: HttpRequest,
+                        app_state: web::Data<AppState<'_>>,
+                        session: Session,
+                        path: web::Path<SessionPath>)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let Ok(user @ User { id: Some(user_id), .. }) =
+        get_current_user(&service_data.client, service_data.session.clone()).await else {
+        return Ok(templator::redirect(&service_data.req, "/login"));
+    };
+    remove_user_session(&service_data.client, user_id, path.id).await?;
+    audit::record(&service_data, Some(&user), AuditAction::Logout, AuditTarget::User(user_id),
+                  None, Some(json!({ "session": path.id }))).await;
+    templator::push_flash(&service_data.session, "Сессия закрыта");
+    Ok(templator::redirect(&service_data.req, "/sessions"))
+}
+
+/// Выход отовсюду, включая эту сессию
+#[post("/sessions/revoke-all")]
+async fn sessions_revoke_all(req: HttpRequest,
+                             app_state: web::Data<AppState<'_>>,
+                             session: Session)
+    -> actix_web::Result<HttpResponse>
+{
+    let service_data = ServiceData::new(req, app_state, session).await?;
+    let Ok(user @ User { id: Some(user_id), .. }) =
+        get_current_user(&service_data.client, service_data.session.clone()).await else {
+        return Ok(templator::redirect(&service_data.req, "/login"));
+    };
+    remove_user_sessions(&service_data.client, user.clone()).await?;
+    audit::record(&service_data, Some(&user), AuditAction::Logout, AuditTarget::User(user_id),
+                  None, Some(json!({ "everywhere": true }))).await;
+    service_data.session.remove("token");
+    templator::push_flash(&service_data.session, "Вы вышли на всех устройствах");
+    Ok(templator::redirect(&service_data.req, "/login"))
+}
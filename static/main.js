@@ -5,27 +5,103 @@ function setLocation(curLoc){
     } catch(e) {}
     location.hash = '#' + curLoc;
 }
+function showFlash(messages) {
+    let flash = document.querySelector("#flash");
+    flash.innerHTML = "";
+    (messages || []).forEach((message) => {
+        let p = document.createElement("p");
+        p.className = "flash";
+        p.textContent = message;
+        flash.appendChild(p);
+    });
+}
+
+function applyFragment(fragment, target) {
+    target.innerHTML = fragment.content;
+    document.title = fragment.page.name;
+    document.querySelector("#nav_user").innerHTML = fragment.header;
+    showFlash(fragment.flash);
+}
+
+// Загружает страницу фрагментом; если ответ не фрагмент -- обычный переход
+async function navigate(url, options = {}, target = null, push = true) {
+    let resp;
+    let fragment;
+    try {
+        resp = await fetch(url, {
+            ...options,
+            headers: {
+                'X-Requested-With': 'XMLHttpRequest'
+            }
+        });
+        fragment = await resp.json();
+    } catch(e) {
+        location.href = url;
+        return;
+    }
+    if(fragment.redirect) {
+        await navigate(fragment.redirect);
+        return;
+    }
+    applyFragment(fragment, target || document.querySelector("body > #wrap > main"));
+    if(push) {
+        setLocation(url);
+    }
+}
+
+function ajaxTarget(element) {
+    let customSelector = element.getAttribute("data-ajax");
+    if(customSelector) {
+        return document.querySelector(customSelector);
+    }
+    return null;
+}
+
 function loadAjaxNav() {
-    let default_main = document.querySelector("body > #wrap > main");
-    let nav = document.querySelectorAll("a[data-ajax]");
-    nav.forEach((value, key, parent) => {
-       value.addEventListener("click", async (event) => {
-           event.preventDefault();
-           let resp = await fetch(value.href, {
-               headers: {
-                   'X-Requested-With': 'XMLHttpRequest'
-               }
-           });
-           let txt = await resp.text();
-           let customSelector = value.attributes["data-ajax"];
-           if(typeof customSelector === "string" && customSelector !== "") {
-               document.querySelector(customSelector).innerHTML = txt;
-           } else {
-               default_main.innerHTML = txt;
-           }
-           setLocation(value.href);
-        })
+    document.addEventListener("click", (event) => {
+        let link = event.target.closest("a[data-ajax]");
+        if(!link) {
+            return;
+        }
+        event.preventDefault();
+        navigate(link.href, {}, ajaxTarget(link));
+    });
+    document.addEventListener("submit", (event) => {
+        let form = event.target.closest("form[data-ajax]");
+        if(!form) {
+            return;
+        }
+        event.preventDefault();
+        let action = form.getAttribute("action") || location.pathname;
+        let data = new FormData(form);
+        if(form.method.toUpperCase() === "GET") {
+            navigate(action + "?" + new URLSearchParams(data), {}, ajaxTarget(form));
+        } else {
+            navigate(action, { method: "POST", body: new URLSearchParams(data) },
+                ajaxTarget(form), false);
+        }
+    });
+    window.addEventListener("popstate", () => {
+        navigate(location.href, {}, null, false);
+    });
+}
+
+const AVAILABILITY_CLASSES = ["free", "taken", "unavailable"];
+
+function listenSeatEvents() {
+    if(typeof EventSource === "undefined") {
+        return;
+    }
+    let source = new EventSource("/events/seats");
+    source.addEventListener("seat", (event) => {
+        let change = JSON.parse(event.data);
+        document.querySelectorAll(`[data-seat="${change.seat_id}"]`).forEach((seat) => {
+            seat.classList.remove(...AVAILABILITY_CLASSES);
+            seat.classList.add(change.availability);
+            seat.classList.toggle("checked-in", change.checked_in);
+        });
     });
 }
 
-loadAjaxNav();
\ No newline at end of file
+loadAjaxNav();
+listenSeatEvents();
\ No newline at end of file